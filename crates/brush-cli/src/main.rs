@@ -0,0 +1,440 @@
+use std::{
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::Context;
+use brush_dataset::{
+    scene_export, scene_loader::SceneLoader, splat_export, zip::DatasetZip, LoadDatasetArgs,
+};
+use brush_render::gaussian_splats::{RandomSplatsConfig, Splats};
+use brush_train::{
+    eval::eval_stats,
+    image::tensor_into_image,
+    train::{SplatTrainer, TrainConfig},
+};
+use burn::{backend::Autodiff, config::Config, module::AutodiffModule};
+use burn_wgpu::{Wgpu, WgpuDevice};
+use clap::{Args, Parser};
+use rand::SeedableRng;
+use tokio_stream::StreamExt;
+use tracing::{trace_span, Instrument};
+
+type Backend = Autodiff<Wgpu>;
+
+/// Train gaussian splats on a dataset, without opening a window.
+#[derive(Parser)]
+#[command(name = "brush_cli", version)]
+struct Cli {
+    /// Dataset to train on. Either a zip file, or a directory with the same layout.
+    dataset: PathBuf,
+
+    /// Training config, as a .json or .toml file. Any missing fields use their defaults.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Directory to write exported splats, checkpoints and metrics to.
+    #[arg(long, default_value = "brush_output")]
+    output: PathBuf,
+
+    /// Total number of steps to train for.
+    #[arg(long, default_value_t = 30000)]
+    total_steps: u32,
+
+    /// Export a ply file every this many steps. The final splats are always exported.
+    #[arg(long, default_value_t = 5000)]
+    export_every: u32,
+
+    /// Evaluate on the eval split every this many steps. 0 only evaluates at the end.
+    #[arg(long, default_value_t = 1000)]
+    eval_every: u32,
+
+    /// Number of views to evaluate on. Uses all eval views when not set.
+    #[arg(long)]
+    eval_views: Option<usize>,
+
+    /// Save a training checkpoint every this many steps.
+    #[arg(long)]
+    checkpoint_every: Option<u32>,
+
+    /// Resume training from a checkpoint. This also restores the training config.
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Log training stats every this many steps. 0 disables logging.
+    #[arg(long, default_value_t = 100)]
+    log_every: u32,
+
+    /// Save the eval renders as png files, next to the metrics.
+    #[arg(long)]
+    save_renders: bool,
+
+    /// Apply the learned appearance of this training view to saved renders. Needs
+    /// per_view_appearance to be enabled in the config.
+    #[arg(long, requires = "save_renders")]
+    render_appearance: Option<usize>,
+
+    /// SH degree of randomly initialized splats.
+    #[arg(long, default_value_t = 2)]
+    sh_degree: u32,
+
+    #[command(flatten)]
+    load_args: LoadArgs,
+}
+
+#[derive(Args)]
+struct LoadArgs {
+    /// Max number of frames to load.
+    #[arg(long)]
+    max_frames: Option<usize>,
+
+    /// Max resolution of the images. Bigger images are downscaled.
+    #[arg(long)]
+    max_resolution: Option<u32>,
+
+    /// Use every nth frame for evaluation instead of training.
+    #[arg(long)]
+    eval_split_every: Option<usize>,
+
+    /// Only load every nth frame.
+    #[arg(long)]
+    subsample_frames: Option<u32>,
+
+    /// Only load every nth initial point.
+    #[arg(long)]
+    subsample_points: Option<u32>,
+
+    /// Scale to convert the values of COLMAP depth maps to world units, eg. 0.001 for
+    /// depth maps in millimeters. Defaults to 1.
+    #[arg(long)]
+    depth_scale: Option<f32>,
+}
+
+impl From<LoadArgs> for LoadDatasetArgs {
+    fn from(args: LoadArgs) -> Self {
+        LoadDatasetArgs {
+            max_frames: args.max_frames,
+            max_resolution: args.max_resolution,
+            eval_split_every: args.eval_split_every,
+            subsample_frames: args.subsample_frames,
+            subsample_points: args.subsample_points,
+            depth_scale: args.depth_scale,
+        }
+    }
+}
+
+fn load_config(path: &Path) -> anyhow::Result<TrainConfig> {
+    let config = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => {
+            let text = std::fs::read_to_string(path)?;
+            toml::from_str(&text)?
+        }
+        _ => TrainConfig::load(path)?,
+    };
+    Ok(config)
+}
+
+// Datasets are always read as a zip archive, so pack directories into an
+// (uncompressed) archive in memory.
+fn zip_directory(dir: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+
+    let mut stack = vec![dir.to_owned()];
+    while let Some(cur) = stack.pop() {
+        for entry in std::fs::read_dir(&cur)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let name = path.strip_prefix(dir)?.to_string_lossy().replace('\\', "/");
+            writer.start_file(name, options)?;
+            writer.write_all(&std::fs::read(&path)?)?;
+        }
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    let config = match &cli.config {
+        Some(path) => load_config(path)
+            .with_context(|| format!("Failed to load config {}", path.display()))?,
+        None => TrainConfig::default(),
+    };
+
+    let device = WgpuDevice::DefaultDevice;
+
+    let data = if cli.dataset.is_dir() {
+        zip_directory(&cli.dataset)?
+    } else {
+        std::fs::read(&cli.dataset)?
+    };
+    let zip_data = DatasetZip::from_data(data)?;
+
+    let load_args: LoadDatasetArgs = cli.load_args.into();
+    let (mut splat_stream, mut data_stream) =
+        brush_dataset::load_dataset::<Backend>(zip_data, &load_args, &device)?;
+
+    let mut initial_splats = None;
+    while let Some(splats) = splat_stream.next().await {
+        initial_splats = Some(splats?.with_min_sh_degree(cli.sh_degree));
+    }
+
+    let mut dataset = None;
+    while let Some(d) = data_stream.next().await {
+        dataset = Some(d?);
+    }
+    let dataset = dataset.context("Dataset doesn't contain any views")?;
+    log::info!(
+        "Loaded {} training views, {} eval views",
+        dataset.train.views.len(),
+        dataset.eval.as_ref().map_or(0, |s| s.views.len())
+    );
+
+    let seed = config.seed;
+    <Wgpu as burn::prelude::Backend>::seed(seed);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+    let (mut trainer, mut splats) = if let Some(path) = &cli.resume {
+        let data = std::fs::read(path)?;
+        let (trainer, splats) = SplatTrainer::from_checkpoint(&data, &device)
+            .with_context(|| format!("Failed to load checkpoint {}", path.display()))?;
+        log::info!("Resuming from step {}", trainer.iter);
+        (trainer, splats)
+    } else {
+        let splats = if let Some(splats) = initial_splats {
+            splats
+        } else {
+            // Same as the viewer, spawn the splats in the scene bounds.
+            let bounds = dataset.train.bounds(0.0, 0.0);
+            let bounds_extent = bounds.extent.length();
+            let adjusted_bounds = dataset.train.bounds(bounds_extent * 0.25, bounds_extent);
+            let config = RandomSplatsConfig::new().with_sh_degree(cli.sh_degree);
+            Splats::from_random_config(config, adjusted_bounds, &mut rng, &device)
+        };
+        (
+            SplatTrainer::new(splats.num_splats(), &config, &device),
+            splats,
+        )
+    };
+
+    std::fs::create_dir_all(&cli.output)?;
+    let metrics_path = cli.output.join("eval_metrics.csv");
+    if !metrics_path.exists() {
+        std::fs::write(&metrics_path, "iter,num_splats,psnr,ssim\n")?;
+    }
+
+    let mut dataloader = SceneLoader::new(
+        &dataset.train,
+        trainer.config(),
+        trainer.iter,
+        seed,
+        &device,
+    )?;
+
+    while trainer.iter < cli.total_steps {
+        let batch = dataloader
+            .next_batch()
+            .instrument(trace_span!("Get batch"))
+            .await;
+        let (new_splats, stats) = trainer
+            .step(batch, splats)
+            .instrument(trace_span!("Train step"))
+            .await?;
+        splats = new_splats;
+
+        let iter = trainer.iter;
+
+        if cli.log_every > 0 && iter % cli.log_every == 0 {
+            let loss = stats.loss.into_scalar_async().await;
+            log::info!(
+                "Step {iter}: loss {loss:.5}, {} splats",
+                splats.num_splats()
+            );
+        }
+
+        if let Some(refine) = stats.refine {
+            if refine.num_over_budget > 0 {
+                log::info!(
+                    "Step {iter}: skipped {} splats over the budget of {:?}",
+                    refine.num_over_budget,
+                    refine.max_splats
+                );
+            }
+            log::info!(
+                "Step {iter}: split {}, cloned {}, pruned {}, relocated {}",
+                refine.num_split,
+                refine.num_cloned,
+                refine.num_transparent_pruned + refine.num_scale_pruned + refine.num_crop_pruned,
+                refine.num_relocated
+            );
+        }
+
+        if cli.eval_every > 0 && iter % cli.eval_every == 0 && iter < cli.total_steps {
+            write_eval(
+                &cli,
+                &dataset,
+                &trainer,
+                &splats,
+                iter,
+                &metrics_path,
+                &mut rng,
+                &device,
+            )
+            .await?;
+        }
+
+        if cli.export_every > 0 && iter % cli.export_every == 0 && iter < cli.total_steps {
+            let ply = export_ply(trainer.config(), splats.valid()).await?;
+            std::fs::write(cli.output.join(format!("export_{iter}.ply")), ply)?;
+        }
+
+        if let Some(every) = cli.checkpoint_every {
+            if every > 0 && iter % every == 0 {
+                let data = trainer.to_checkpoint(&splats).await?;
+                std::fs::write(
+                    cli.output.join(format!("checkpoint_{iter}.safetensors")),
+                    data,
+                )?;
+            }
+        }
+    }
+
+    let iter = trainer.iter;
+    write_eval(
+        &cli,
+        &dataset,
+        &trainer,
+        &splats,
+        iter,
+        &metrics_path,
+        &mut rng,
+        &device,
+    )
+    .await?;
+    let ply = export_ply(trainer.config(), splats.valid()).await?;
+    std::fs::write(cli.output.join("export_final.ply"), ply)?;
+
+    if trainer.pose_correction().is_some() {
+        let refined = trainer.refined_scene(&dataset.train).await;
+        let transforms = scene_export::scene_to_transforms(&refined)?;
+        std::fs::write(cli.output.join("refined_cameras.json"), transforms)?;
+    }
+    log::info!("Finished training after {iter} steps.");
+
+    Ok(())
+}
+
+// Export the splats as a ply, leaving out the splats outside of the crop box.
+async fn export_ply(config: &TrainConfig, splats: Splats<Wgpu>) -> anyhow::Result<Vec<u8>> {
+    let splats = match &config.crop_box {
+        Some(crop_box) => splats.crop(crop_box).await,
+        None => splats,
+    };
+    splat_export::splat_to_ply(splats).await
+}
+
+async fn write_eval(
+    cli: &Cli,
+    dataset: &brush_dataset::Dataset,
+    trainer: &SplatTrainer<Backend>,
+    splats: &Splats<Backend>,
+    iter: u32,
+    metrics_path: &Path,
+    rng: &mut impl rand::Rng,
+    device: &WgpuDevice,
+) -> anyhow::Result<()> {
+    let Some(eval_scene) = dataset.eval.as_ref() else {
+        return Ok(());
+    };
+
+    let eval = eval_stats(splats.valid(), eval_scene, cli.eval_views, rng, device).await;
+    let count = eval.samples.len().max(1) as f32;
+    let psnr = eval.samples.iter().map(|s| s.psnr).sum::<f32>() / count;
+    let ssim = eval.samples.iter().map(|s| s.ssim).sum::<f32>() / count;
+    log::info!("Step {iter}: eval psnr {psnr:.3}, ssim {ssim:.4}");
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(metrics_path)?;
+    writeln!(file, "{iter},{},{psnr},{ssim}", splats.num_splats())?;
+
+    if cli.save_renders {
+        let appearance = match cli.render_appearance {
+            Some(view_id) => {
+                let appearance = trainer
+                    .appearance()
+                    .context("Training doesn't have per view appearance enabled")?
+                    .valid();
+                anyhow::ensure!(
+                    view_id < appearance.num_views(),
+                    "No appearance learned for view {view_id}"
+                );
+                Some((appearance, view_id))
+            }
+            None => None,
+        };
+
+        let dir = cli.output.join(format!("renders_{iter}"));
+        std::fs::create_dir_all(&dir)?;
+        for (i, sample) in eval.samples.into_iter().enumerate() {
+            let rendered = match &appearance {
+                Some((appearance, view_id)) => appearance.apply_view(sample.rendered, *view_id),
+                None => sample.rendered,
+            };
+            let image = tensor_into_image(rendered.clamp(0.0, 1.0).into_data_async().await);
+            image.to_rgb8().save(dir.join(format!("eval_{i}.png")))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let cli = Cli::parse();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create tokio runtime");
+
+    match runtime.block_on(run(cli)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("Training failed: {e:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_partial_config() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("brush_cli_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+
+        let toml_path = dir.join("config.toml");
+        std::fs::write(&toml_path, "batch_size = 2\nseed = 7\n")?;
+        let config = load_config(&toml_path)?;
+        assert_eq!(config.batch_size, 2);
+        assert_eq!(config.seed, 7);
+
+        let json_path = dir.join("config.json");
+        std::fs::write(&json_path, r#"{ "batch_size": 3 }"#)?;
+        let config = load_config(&json_path)?;
+        assert_eq!(config.batch_size, 3);
+        assert_eq!(config.seed, TrainConfig::default().seed);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
-use std::{future::Future, sync::Arc};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use super::{DataStream, DatasetZip, LoadDatasetArgs};
 use crate::{stream_fut_parallel, Dataset};
@@ -10,7 +14,7 @@ use brush_render::{
     render::rgb_to_sh,
     Backend,
 };
-use brush_train::scene::SceneView;
+use brush_train::scene::{SceneView, SparsePoint};
 use glam::Vec3;
 use tokio_stream::StreamExt;
 
@@ -52,6 +56,18 @@ fn read_views(
         colmap_reader::read_images(&mut buf_reader, is_binary)?
     };
 
+    // The sparse points are optional, without them views just don't have any.
+    let points_path = if is_binary {
+        base_path.join("sparse/0/points3D.bin")
+    } else {
+        base_path.join("sparse/0/points3D.txt")
+    };
+    let points_data = archive
+        .file_at_path(&points_path)
+        .ok()
+        .and_then(|mut points_file| colmap_reader::read_points3d(&mut points_file, is_binary).ok())
+        .map(Arc::new);
+
     let mut img_info_list = img_infos.into_iter().collect::<Vec<_>>();
 
     log::info!("Colmap dataset contains {} images", img_info_list.len());
@@ -69,6 +85,7 @@ fn read_views(
             let load_args = load_args.clone();
             let base_path = base_path.clone();
             let mut archive = archive.clone();
+            let points_data = points_data.clone();
 
             // Create a future to handle loading the image.
             async move {
@@ -97,10 +114,68 @@ fn read_views(
 
                 let camera = Camera::new(translation, quat, fovx, fovy, center_uv);
 
+                // Masks are optional, and live in a masks folder next to the images. They are
+                // either named the same as the image, or have an added or replaced .png extension.
+                let img_name = Path::new(&img_info.name);
+                let mask_bytes = [
+                    img_name.to_owned(),
+                    PathBuf::from(format!("{}.png", img_info.name)),
+                    img_name.with_extension("png"),
+                ]
+                .into_iter()
+                .find_map(|name| {
+                    archive
+                        .read_bytes_at_path(&base_path.join("masks").join(name))
+                        .ok()
+                });
+                let mask = mask_bytes
+                    .map(|bytes| crate::load_mask(&bytes, &img))
+                    .transpose()?;
+
+                // Depth maps are found the same way in a depths folder. Their values are
+                // multiplied by the depth scale to get the units of the reconstruction.
+                let depth_bytes = [
+                    img_name.to_owned(),
+                    PathBuf::from(format!("{}.png", img_info.name)),
+                    img_name.with_extension("png"),
+                ]
+                .into_iter()
+                .find_map(|name| {
+                    archive
+                        .read_bytes_at_path(&base_path.join("depths").join(name))
+                        .ok()
+                });
+                let depth_scale = load_args.depth_scale.unwrap_or(1.0);
+                let depth = depth_bytes
+                    .map(|bytes| crate::load_depth(&bytes, &img, depth_scale))
+                    .transpose()?;
+
+                // The 2D observations are in pixels of the original COLMAP image.
+                let colmap_size = glam::vec2(cam_data.width as f32, cam_data.height as f32);
+                let sparse_points = points_data.map(|points_data| {
+                    img_info
+                        .xys
+                        .iter()
+                        .zip(&img_info.point3d_ids)
+                        .filter_map(|(xy, id)| {
+                            // Unmatched observations have an id of -1.
+                            let point = points_data.get(id)?;
+                            Some(SparsePoint {
+                                uv: *xy / colmap_size,
+                                xyz: point.xyz,
+                                error: point.error as f32,
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                });
+
                 let view = SceneView {
                     name: img_path.to_str().context("Invalid file name")?.to_owned(),
                     camera,
                     image: Arc::new(img),
+                    mask: mask.map(Arc::new),
+                    depth: depth.map(Arc::new),
+                    sparse_points: sparse_points.map(Arc::new),
                 };
                 Ok(view)
             }
@@ -27,6 +27,10 @@ struct JsonScene {
     // Nerfstudio doesn't mention this in their format? But fine to include really.
     ply_file_path: Option<String>,
 
+    /// Scale to convert depth map values to world units. Defaults to 1e-3, for depth
+    /// maps in millimeters.
+    depth_unit_scale_factor: Option<f64>,
+
     /// Focal length x
     fl_x: Option<f64>,
     /// Focal length y
@@ -90,6 +94,11 @@ struct FrameData {
 
     transform_matrix: Vec<Vec<f32>>,
     file_path: String,
+    /// Optional mask image, relative to the transforms file. Pixels where the mask
+    /// is zero are ignored during training.
+    mask_path: Option<String>,
+    /// Optional depth map, relative to the transforms file.
+    depth_file_path: Option<String>,
 }
 
 fn read_transforms_file(
@@ -159,10 +168,30 @@ fn read_transforms_file(
 
                 let cuv = glam::vec2((cx / w as f64) as f32, (cy / h as f64) as f32);
 
+                let mask = if let Some(mask_path) = &frame.mask_path {
+                    let path = transforms_path.parent().unwrap().join(mask_path);
+                    let mask_buffer = archive.read_bytes_at_path(&path)?;
+                    Some(Arc::new(crate::load_mask(&mask_buffer, &image)?))
+                } else {
+                    None
+                };
+
+                let depth = if let Some(depth_path) = &frame.depth_file_path {
+                    let path = transforms_path.parent().unwrap().join(depth_path);
+                    let depth_buffer = archive.read_bytes_at_path(&path)?;
+                    let scale = scene.depth_unit_scale_factor.unwrap_or(1e-3) as f32;
+                    Some(Arc::new(crate::load_depth(&depth_buffer, &image, scale)?))
+                } else {
+                    None
+                };
+
                 let view = SceneView {
                     name: frame.file_path.to_owned(),
                     camera: Camera::new(translation, rotation, fovx, fovy, cuv),
                     image: Arc::new(image),
+                    mask,
+                    depth,
+                    sparse_points: None,
                 };
                 anyhow::Result::<SceneView>::Ok(view)
             }
@@ -1,4 +1,5 @@
 mod formats;
+pub mod scene_export;
 pub mod scene_loader;
 pub mod splat_export;
 pub mod splat_import;
@@ -6,9 +7,9 @@ pub mod zip;
 
 pub use formats::load_dataset;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_fn_stream::fn_stream;
-use brush_train::scene::{Scene, SceneView};
+use brush_train::scene::{DepthImage, Scene, SceneView};
 use image::DynamicImage;
 use std::future::Future;
 use std::num::NonZero;
@@ -24,6 +25,9 @@ pub struct LoadDatasetArgs {
     pub eval_split_every: Option<usize>,
     pub subsample_frames: Option<u32>,
     pub subsample_points: Option<u32>,
+    /// Scale to convert the values of COLMAP depth maps to world units. Defaults to 1.
+    /// Nerfstudio datasets set this per dataset with `depth_unit_scale_factor` instead.
+    pub depth_scale: Option<f32>,
 }
 
 #[derive(Clone)]
@@ -77,6 +81,46 @@ pub(crate) fn clamp_img_to_max_size(image: DynamicImage, max_size: u32) -> Dynam
     image.resize(new_width, new_height, image::imageops::FilterType::Lanczos3)
 }
 
+// Decodes a mask, and resizes it to match the (possibly downscaled) image it belongs to.
+pub(crate) fn load_mask(bytes: &[u8], image: &DynamicImage) -> Result<DynamicImage> {
+    let mask = image::load_from_memory(bytes)?.to_luma8();
+    let mask = image::imageops::resize(
+        &mask,
+        image.width(),
+        image.height(),
+        image::imageops::FilterType::Nearest,
+    );
+    Ok(mask.into())
+}
+
+// Decodes a depth map, and resizes it to match the image it belongs to. Depth values are
+// multiplied by `scale` to convert them to world units.
+pub(crate) fn load_depth(bytes: &[u8], image: &DynamicImage, scale: f32) -> Result<DepthImage> {
+    let (w, h, depth): (u32, u32, Vec<f32>) = match image::load_from_memory(bytes)? {
+        DynamicImage::ImageLuma8(img) => (
+            img.width(),
+            img.height(),
+            img.into_raw().into_iter().map(|d| d as f32).collect(),
+        ),
+        DynamicImage::ImageLuma16(img) => (
+            img.width(),
+            img.height(),
+            img.into_raw().into_iter().map(|d| d as f32).collect(),
+        ),
+        img => anyhow::bail!("Unsupported depth map format {:?}", img.color()),
+    };
+
+    let depth = DepthImage::from_raw(w, h, depth.into_iter().map(|d| d * scale).collect())
+        .context("Invalid depth map size")?;
+    // Nearest filtering, as interpolating depth across edges gives floating geometry.
+    Ok(image::imageops::resize(
+        &depth,
+        image.width(),
+        image.height(),
+        image::imageops::FilterType::Nearest,
+    ))
+}
+
 pub(crate) type DataStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send + 'static>>;
 
 pub(crate) fn stream_fut_parallel<T: Send + 'static>(
@@ -0,0 +1,36 @@
+use brush_train::scene::Scene;
+use serde_json::json;
+
+// Writes the cameras of a scene as a nerfstudio style transforms.json. The file paths are
+// the names of the views, so this can be loaded again next to the original images.
+pub fn scene_to_transforms(scene: &Scene) -> anyhow::Result<Vec<u8>> {
+    let frames: Vec<_> = scene
+        .views
+        .iter()
+        .map(|view| {
+            let size = glam::uvec2(view.image.width(), view.image.height());
+            let focal = view.camera.focal(size);
+            let center = view.camera.center(size);
+
+            // Swap the basis back to the nerfstudio convention, see the nerfstudio loader.
+            let mut transform = view.camera.local_to_world();
+            transform.y_axis *= -1.0;
+            transform.z_axis *= -1.0;
+            // Nerfstudio stores the matrix row by row.
+            let rows = transform.transpose().to_cols_array_2d();
+
+            json!({
+                "file_path": view.name,
+                "transform_matrix": rows,
+                "fl_x": focal.x,
+                "fl_y": focal.y,
+                "cx": center.x,
+                "cy": center.y,
+                "w": size.x,
+                "h": size.y,
+            })
+        })
+        .collect();
+
+    Ok(serde_json::to_vec_pretty(&json!({ "frames": frames }))?)
+}
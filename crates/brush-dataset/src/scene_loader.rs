@@ -1,9 +1,11 @@
 use ::tokio::sync::mpsc;
 use ::tokio::sync::mpsc::Receiver;
+use anyhow::Result;
 use brush_render::Backend;
-use brush_train::image::image_to_tensor;
-use brush_train::scene::Scene;
-use brush_train::train::SceneBatch;
+use brush_train::image::{depth_to_tensor, image_to_tensor, mask_to_tensor};
+use brush_train::scene::{Scene, SceneView};
+use brush_train::train::{SceneBatch, TrainConfig};
+use burn::tensor::module::avg_pool2d;
 use burn::tensor::Tensor;
 use rand::{seq::SliceRandom, SeedableRng};
 use tokio_with_wasm::alias as tokio;
@@ -12,9 +14,69 @@ pub struct SceneLoader<B: Backend> {
     receiver: Receiver<SceneBatch<B>>,
 }
 
+// Downscale a [N, H, W, C] batch of images by averaging blocks of factor x factor pixels.
+// Sizes that aren't a multiple of the factor are rounded up, and the blocks on the bottom and
+// right edges only average the pixels they cover.
+fn downscale<B: Backend>(images: Tensor<B, 4>, factor: usize) -> Tensor<B, 4> {
+    let pool = |x: Tensor<B, 4>| {
+        avg_pool2d(
+            x.permute([0, 3, 1, 2]),
+            [factor, factor],
+            [factor, factor],
+            [0, 0],
+            true,
+        )
+        .permute([0, 2, 3, 1])
+    };
+
+    let [n, h, w, c] = images.dims();
+    let (padded_h, padded_w) = (h.next_multiple_of(factor), w.next_multiple_of(factor));
+    if padded_h == h && padded_w == w {
+        return pool(images);
+    }
+
+    let device = images.device();
+    let padded = Tensor::zeros([n, padded_h, padded_w, c], &device)
+        .slice_assign([0..n, 0..h, 0..w, 0..c], images);
+    let coverage = Tensor::zeros([1, padded_h, padded_w, 1], &device).slice_assign(
+        [0..1, 0..h, 0..w, 0..1],
+        Tensor::ones([1, h, w, 1], &device),
+    );
+    pool(padded) / pool(coverage)
+}
+
 impl<B: Backend> SceneLoader<B> {
-    pub fn new(scene: &Scene, batch_size: usize, seed: u64, device: &B::Device) -> Self {
+    /// Create a loader for the batches of the training steps from `start_iter` on. The
+    /// images of each batch are downscaled according to the resolution schedule of the config.
+    ///
+    /// The images of a batch are stacked together, so with a batch size above 1 all views
+    /// need to have the same resolution.
+    pub fn new(
+        scene: &Scene,
+        config: &TrainConfig,
+        start_iter: u32,
+        seed: u64,
+        device: &B::Device,
+    ) -> Result<Self> {
         let scene = scene.clone();
+        let config = config.clone();
+        let batch_size = config.batch_size;
+
+        if batch_size > 1 {
+            let size = |view: &SceneView| (view.image.width(), view.image.height());
+            if let Some(first) = scene.views.first() {
+                if let Some(view) = scene.views.iter().find(|v| size(v) != size(first)) {
+                    let (first_w, first_h) = size(first);
+                    let (w, h) = size(view);
+                    anyhow::bail!(
+                        "A batch size of {batch_size} needs all images to have the same resolution, \
+                        but {} is {first_w}x{first_h} and {} is {w}x{h}",
+                        first.name,
+                        view.name
+                    );
+                }
+            }
+        }
         // The bounded size == number of batches to prefetch.
         let (tx, rx) = mpsc::channel(5);
         let device = device.clone();
@@ -24,25 +86,87 @@ impl<B: Backend> SceneLoader<B> {
 
         let fut = async move {
             let mut shuf_indices = vec![];
+            let mut iter = start_iter;
 
             loop {
-                let (selected_tensors, gt_views) = (0..batch_size)
+                let gt_view_ids: Vec<usize> = (0..batch_size)
                     .map(|_| {
-                        let index = shuf_indices.pop().unwrap_or_else(|| {
+                        shuf_indices.pop().unwrap_or_else(|| {
                             shuf_indices = (0..scene.views.len()).collect();
                             shuf_indices.shuffle(&mut rng);
                             shuf_indices.pop().unwrap()
-                        });
-                        let view = scene.views[index].clone();
-                        (image_to_tensor(&view.image, &device), view)
+                        })
                     })
-                    .unzip();
+                    .collect();
+                let gt_views: Vec<SceneView> = gt_view_ids
+                    .iter()
+                    .map(|&index| scene.views[index].clone())
+                    .collect();
+                let selected_tensors = gt_views
+                    .iter()
+                    .map(|view| image_to_tensor(&view.image, &device))
+                    .collect();
 
                 let batch_tensor = Tensor::stack(selected_tensors, 0);
 
+                // Views without a mask train on all their pixels.
+                let gt_masks = if gt_views.iter().any(|v| v.mask.is_some()) {
+                    let masks = gt_views
+                        .iter()
+                        .map(|view| match &view.mask {
+                            Some(mask) => mask_to_tensor(mask, &device),
+                            None => Tensor::ones(
+                                [view.image.height() as usize, view.image.width() as usize, 1],
+                                &device,
+                            ),
+                        })
+                        .collect();
+                    Some(Tensor::stack(masks, 0))
+                } else {
+                    None
+                };
+
+                // Views without a depth map get a zero (invalid) depth everywhere.
+                let gt_depths = if gt_views.iter().any(|v| v.depth.is_some()) {
+                    let depths = gt_views
+                        .iter()
+                        .map(|view| match &view.depth {
+                            Some(depth) => depth_to_tensor(depth, &device),
+                            None => Tensor::zeros(
+                                [view.image.height() as usize, view.image.width() as usize, 1],
+                                &device,
+                            ),
+                        })
+                        .collect();
+                    Some(Tensor::stack(depths, 0))
+                } else {
+                    None
+                };
+
+                let factor = config.resolution_downscale(iter) as usize;
+                iter += 1;
+
+                let (batch_tensor, gt_masks, gt_depths) = if factor > 1 {
+                    // Average only the valid depths, so pixels next to a hole keep their depth.
+                    let gt_depths = gt_depths.map(|depths| {
+                        let valid = depths.clone().greater_elem(0.0).float();
+                        downscale(depths, factor) / downscale(valid, factor).clamp_min(1e-6)
+                    });
+                    (
+                        downscale(batch_tensor, factor),
+                        gt_masks.map(|masks| downscale(masks, factor)),
+                        gt_depths,
+                    )
+                } else {
+                    (batch_tensor, gt_masks, gt_depths)
+                };
+
                 let scene_batch = SceneBatch {
                     gt_images: batch_tensor,
+                    gt_masks,
+                    gt_depths,
                     gt_views,
+                    gt_view_ids,
                     scene_extent,
                 };
 
@@ -53,7 +177,7 @@ impl<B: Backend> SceneLoader<B> {
         };
 
         tokio::task::spawn(fut);
-        Self { receiver: rx }
+        Ok(Self { receiver: rx })
     }
 
     pub async fn next_batch(&mut self) -> SceneBatch<B> {
@@ -63,3 +187,63 @@ impl<B: Backend> SceneLoader<B> {
             .expect("Somehow lost data loading channel!")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brush_render::camera::Camera;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use burn::tensor::Int;
+    use std::sync::Arc;
+
+    fn test_view(name: &str, width: u32, height: u32) -> SceneView {
+        SceneView {
+            name: name.to_owned(),
+            camera: Camera::new(
+                glam::Vec3::ZERO,
+                glam::Quat::IDENTITY,
+                0.8,
+                0.8,
+                glam::vec2(0.5, 0.5),
+            ),
+            image: Arc::new(image::RgbImage::new(width, height).into()),
+            mask: None,
+            depth: None,
+            sparse_points: None,
+        }
+    }
+
+    #[test]
+    fn test_mismatched_resolutions() {
+        let scene = Scene::new(vec![
+            test_view("a", 16, 16),
+            test_view("b", 16, 16),
+            test_view("c", 8, 16),
+        ]);
+        let config = TrainConfig::new().with_batch_size(2);
+        let err = SceneLoader::<NdArray>::new(&scene, &config, 0, 0, &NdArrayDevice::Cpu)
+            .err()
+            .expect("Views have different resolutions");
+        let msg = err.to_string();
+        assert!(
+            msg.contains("a is 16x16") && msg.contains("c is 8x16"),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn test_downscale_uneven() {
+        let device = NdArrayDevice::Cpu;
+
+        // A 3x5 image, with the index of each pixel as its value.
+        let images = Tensor::<NdArray, 1, Int>::arange(0..15, &device)
+            .float()
+            .reshape([1, 3, 5, 1]);
+        let downscaled = downscale(images, 2);
+        assert_eq!(downscaled.dims(), [1, 2, 3, 1]);
+
+        // The last row and column aren't dropped, their blocks average the pixels they cover.
+        let values = downscaled.into_data().to_vec::<f32>().expect("Wrong type");
+        assert_eq!(values, vec![3.0, 5.0, 6.5, 10.5, 12.5, 14.0]);
+    }
+}
@@ -175,7 +175,7 @@ fn bench_general(
     if grad {
         bencher.bench_local(move || {
             for _ in 0..INTERNAL_ITERS {
-                let out = splats.render(&camera, resolution, false);
+                let out = splats.render(&camera, resolution, glam::Vec3::ZERO, false);
                 let _ = out.0.mean().backward();
             }
             // Wait for GPU work.
@@ -187,7 +187,7 @@ fn bench_general(
 
         bencher.bench_local(move || {
             for _ in 0..INTERNAL_ITERS {
-                let _ = splats.render(&camera, resolution, true);
+                let _ = splats.render(&camera, resolution, glam::Vec3::ZERO, true);
             }
             // Wait for GPU work.
             <Wgpu as burn::prelude::Backend>::sync(&device);
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct BoundingBox {
     pub center: glam::Vec3,
     pub extent: glam::Vec3,
@@ -20,3 +20,95 @@ impl BoundingBox {
         self.center + self.extent
     }
 }
+
+/// An oriented box around a region of interest. Splats outside of it can be hidden, pruned
+/// while training, and left out of exports.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CropBox {
+    /// The box in its own frame, before it is rotated.
+    pub bounds: BoundingBox,
+    /// Rotation of the box around its center.
+    pub rotation: glam::Quat,
+}
+
+impl CropBox {
+    pub fn new(bounds: BoundingBox, rotation: glam::Quat) -> Self {
+        Self { bounds, rotation }
+    }
+
+    /// Convert a world space position to the frame of the box, relative to its center.
+    pub fn to_local(&self, point: glam::Vec3) -> glam::Vec3 {
+        self.rotation.inverse() * (point - self.bounds.center)
+    }
+
+    pub fn contains(&self, point: glam::Vec3) -> bool {
+        self.to_local(point).abs().cmple(self.bounds.extent).all()
+    }
+
+    /// The world space corners of the box. Corner `i` is at the max of axis `k` when bit `k`
+    /// of `i` is set.
+    pub fn corners(&self) -> [glam::Vec3; 8] {
+        std::array::from_fn(|i| {
+            let sign = glam::vec3(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            );
+            self.bounds.center + self.rotation * (sign * self.bounds.extent)
+        })
+    }
+}
+
+impl From<BoundingBox> for CropBox {
+    fn from(bounds: BoundingBox) -> Self {
+        Self::new(bounds, glam::Quat::IDENTITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundingBox, CropBox};
+    use crate::gaussian_splats::Splats;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use glam::{vec3, Quat, Vec3};
+
+    #[tokio::test]
+    async fn test_crop_box() {
+        let crop = CropBox::new(
+            BoundingBox::from_min_max(vec3(-1.0, -0.5, -2.0), vec3(1.0, 0.5, 2.0)),
+            Quat::from_rotation_y(0.7) * Quat::from_rotation_x(-0.3),
+        );
+
+        // A grid of points, some of them inside of the rotated box.
+        let means: Vec<Vec3> = (0..512)
+            .map(|i| vec3((i % 8) as f32, ((i / 8) % 8) as f32, (i / 64) as f32) * 0.5 - 1.75)
+            .collect();
+        let expected: Vec<bool> = means.iter().map(|&p| crop.contains(p)).collect();
+        assert!(expected.iter().any(|&inside| inside));
+        assert!(expected.iter().any(|&inside| !inside));
+
+        let device = NdArrayDevice::Cpu;
+        let splats = Splats::<NdArray>::from_raw(
+            means.clone(),
+            None,
+            Some(vec![Vec3::ZERO; means.len()]),
+            None,
+            None,
+            &device,
+        );
+        let mask = splats
+            .crop_mask(&crop)
+            .into_data()
+            .to_vec::<bool>()
+            .expect("Mask is a bool tensor");
+        assert_eq!(mask, expected);
+
+        let cropped = splats.crop(&crop).await;
+        let num_inside = expected.iter().filter(|&&inside| inside).count();
+        assert_eq!(cropped.num_splats(), num_inside);
+
+        for corner in crop.corners() {
+            assert!(crop.contains(corner * 0.999 + crop.bounds.center * 0.001));
+        }
+    }
+}
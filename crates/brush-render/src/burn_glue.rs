@@ -5,20 +5,24 @@ use burn::{
             grads::Gradients,
             ops::{Backward, Ops, OpsKind},
         },
-        Autodiff,
+        ndarray::NdArrayDevice,
+        Autodiff, NdArray,
     },
     tensor::{
+        ops::FloatTensorOps,
         repr::{CustomOpDescription, HandleContainer, OperationDescription},
-        BasicAutodiffOps, DType, Float, Tensor, TensorPrimitive,
+        BasicAutodiffOps, DType, Float, Int, Tensor, TensorData, TensorPrimitive,
     },
 };
 use burn_fusion::{client::FusionClient, stream::Operation, Fusion};
 use burn_jit::fusion::{FusionJitRuntime, JitFusionHandle};
 use burn_wgpu::WgpuRuntime;
-use glam::uvec2;
+use bytemuck::Zeroable;
+use glam::{uvec2, Vec4};
 
 use crate::{
     camera::Camera,
+    reference::{self, CpuRender, CpuSplats},
     render::{render_backward, render_forward, sh_coeffs_for_degree, sh_degree_from_coeffs},
     shaders, AutodiffBackend, Backend, GaussianBackwardState, InnerWgpu, RenderAux, SplatGrads,
 };
@@ -30,21 +34,31 @@ impl Backend for InnerWgpu {
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         _xy_dummy: Self::FloatTensorPrimitive,
+        viewmat: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        features: Option<Self::FloatTensorPrimitive>,
+        active_sh_degree: u32,
+        background: glam::Vec3,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
         render_forward(
             camera,
             img_size,
             means,
+            viewmat,
             log_scales,
             quats,
             sh_coeffs,
             raw_opacity,
+            features,
+            active_sh_degree,
+            background,
             render_u32_buffer,
+            render_depth,
         )
     }
 
@@ -57,6 +71,7 @@ impl Backend for InnerWgpu {
             state.quats,
             state.log_scales,
             state.raw_opac,
+            state.features,
             state.out_img,
             v_output,
             state.aux.projected_splats,
@@ -67,6 +82,7 @@ impl Backend for InnerWgpu {
             state.aux.tile_bins,
             state.aux.final_index,
             state.sh_degree,
+            state.render_depth,
         )
     }
 }
@@ -74,7 +90,7 @@ impl Backend for InnerWgpu {
 #[derive(Debug)]
 struct RenderBackwards;
 
-const NUM_ARGS: usize = 6;
+const NUM_ARGS: usize = 8;
 
 // Implement gradient registration when rendering backwards.
 impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
@@ -94,7 +110,7 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
 
         // Register gradients for parent nodes (This code is already skipped entirely
         // if no parent nodes require gradients).
-        let [mean_parent, xys_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent] =
+        let [mean_parent, xys_parent, viewmat_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent, features_parent] =
             ops.parents;
 
         let v_tens = B::render_splats_bwd(state, v_output);
@@ -108,6 +124,10 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
             grads.register::<B>(node.id, v_tens.v_xy);
         }
 
+        if let Some(node) = viewmat_parent {
+            grads.register::<B>(node.id, v_tens.v_viewmat);
+        }
+
         if let Some(node) = log_scales_parent {
             grads.register::<B>(node.id, v_tens.v_scales);
         }
@@ -123,6 +143,10 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
         if let Some(node) = raw_opacity_parent {
             grads.register::<B>(node.id, v_tens.v_raw_opac);
         }
+
+        if let (Some(node), Some(v_features)) = (features_parent, v_tens.v_features) {
+            grads.register::<B>(node.id, v_features);
+        }
     }
 }
 
@@ -133,24 +157,37 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         xy_dummy: Self::FloatTensorPrimitive,
+        viewmat: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        features: Option<Self::FloatTensorPrimitive>,
+        active_sh_degree: u32,
+        background: glam::Vec3,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
         // Get backend tensors & dequantize if needed. Could try and support quantized inputs
         // in the future.
 
+        // Without features, an untracked placeholder keeps the number of parents the same.
+        let features_node = features.as_ref().map_or_else(
+            || Self::float_zeros([1].into(), &Self::float_device(&means)).node,
+            |features| features.node.clone(),
+        );
+
         // Prepare backward pass, and check if we even need to do it. Store nodes that need gradients.
         let prep_nodes = RenderBackwards
             .prepare::<C>([
                 means.node.clone(),
                 xy_dummy.node.clone(),
+                viewmat.node.clone(),
                 log_scales.node.clone(),
                 quats.node.clone(),
                 sh_coeffs.node.clone(),
                 raw_opacity.node.clone(),
+                features_node,
             ])
             .compute_bound()
             .stateful();
@@ -161,11 +198,16 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             img_size,
             means.clone().into_primitive(),
             xy_dummy.into_primitive(),
+            viewmat.into_primitive(),
             log_scales.clone().into_primitive(),
             quats.clone().into_primitive(),
             sh_coeffs.clone().into_primitive(),
             raw_opacity.clone().into_primitive(),
+            features.clone().map(|f| f.into_primitive()),
+            active_sh_degree,
+            background,
             render_u32_buffer,
+            render_depth,
         );
 
         // Not sure why going into the autodiff float tensor type is so verbose.
@@ -180,6 +222,7 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             num_intersections: aux.num_intersections,
             num_visible: aux.num_visible,
             final_index: aux.final_index,
+            num_contributing: aux.num_contributing,
             cum_tiles_hit: aux.cum_tiles_hit,
             tile_bins: aux.tile_bins,
             compact_gid_from_isect: aux.compact_gid_from_isect,
@@ -196,9 +239,12 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
                     quats: quats.into_primitive(),
                     raw_opac: raw_opacity.into_primitive(),
                     sh_degree: sh_degree_from_coeffs(
-                        Tensor::<Self, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs)).dims()
-                            [1] as u32,
+                        Tensor::<Self, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs.clone()))
+                            .dims()[1] as u32,
                     ),
+                    sh_coeffs: sh_coeffs.into_primitive(),
+                    features: features.map(|f| f.into_primitive()),
+                    render_depth,
                     aux: auxc,
                     out_img: out_img.clone(),
                 };
@@ -222,35 +268,53 @@ impl Backend for Fusion<InnerWgpu> {
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         _xy_grad_dummy: Self::FloatTensorPrimitive,
+        viewmat: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        features: Option<Self::FloatTensorPrimitive>,
+        active_sh_degree: u32,
+        background: glam::Vec3,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
         struct CustomOp {
             cam: Camera,
             img_size: glam::UVec2,
+            active_sh_degree: u32,
+            background: glam::Vec3,
             render_u32_buffer: bool,
+            render_depth: bool,
+            // Whether the features input holds features, or is just a placeholder.
+            has_features: bool,
             desc: CustomOpDescription,
         }
 
         impl Operation<FusionJitRuntime<WgpuRuntime>> for CustomOp {
             fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
                 let (
-                    [means, log_scales, quats, sh_coeffs, raw_opacity],
-                    [projected_splats, uniforms_buffer, num_intersections, num_visible, final_index, cum_tiles_hit, tile_bins, compact_gid_from_isect, global_from_compact_gid, out_img],
+                    [means, viewmat, log_scales, quats, sh_coeffs, raw_opacity, features],
+                    [projected_splats, uniforms_buffer, num_intersections, num_visible, final_index, num_contributing, cum_tiles_hit, tile_bins, compact_gid_from_isect, global_from_compact_gid, out_img],
                 ) = self.desc.consume();
 
+                // Always take the features, so a placeholder is released as well.
+                let features = h.get_float_tensor::<InnerWgpu>(&features);
+
                 let (img, aux) = render_forward(
                     &self.cam,
                     self.img_size,
                     h.get_float_tensor::<InnerWgpu>(&means),
+                    h.get_float_tensor::<InnerWgpu>(&viewmat),
                     h.get_float_tensor::<InnerWgpu>(&log_scales),
                     h.get_float_tensor::<InnerWgpu>(&quats),
                     h.get_float_tensor::<InnerWgpu>(&sh_coeffs),
                     h.get_float_tensor::<InnerWgpu>(&raw_opacity),
+                    self.has_features.then_some(features),
+                    self.active_sh_degree,
+                    self.background,
                     self.render_u32_buffer,
+                    self.render_depth,
                 );
 
                 // Register output.
@@ -260,6 +324,7 @@ impl Backend for Fusion<InnerWgpu> {
                 h.register_int_tensor::<InnerWgpu>(&num_intersections.id, aux.num_intersections);
                 h.register_int_tensor::<InnerWgpu>(&num_visible.id, aux.num_visible);
                 h.register_int_tensor::<InnerWgpu>(&final_index.id, aux.final_index);
+                h.register_int_tensor::<InnerWgpu>(&num_contributing.id, aux.num_contributing);
                 h.register_int_tensor::<InnerWgpu>(&cum_tiles_hit.id, aux.cum_tiles_hit);
                 h.register_int_tensor::<InnerWgpu>(&tile_bins.id, aux.tile_bins);
                 h.register_int_tensor::<InnerWgpu>(
@@ -278,6 +343,12 @@ impl Backend for Fusion<InnerWgpu> {
 
         let num_points = means.shape[0];
 
+        // The op always takes a features input, use a placeholder when there are none.
+        let has_features = features.is_some();
+        let num_features = features.as_ref().map_or(0, |f| f.shape[1]);
+        let features =
+            features.unwrap_or_else(|| Self::float_zeros([1].into(), &Self::float_device(&means)));
+
         let proj_size = size_of::<shaders::helpers::ProjectedSplat>() / 4;
         let uniforms_size = size_of::<shaders::helpers::RenderUniforms>() / 4;
 
@@ -292,8 +363,15 @@ impl Backend for Fusion<InnerWgpu> {
             .min(128 * 65535);
 
         // If render_u32_buffer is true, we render a packed buffer of u32 values, otherwise
-        // render RGBA f32 values.
-        let channels = if render_u32_buffer { 1 } else { 4 };
+        // render RGBA f32 values, optionally followed by the weighted and median depth, and
+        // the features.
+        let channels = if render_u32_buffer {
+            1
+        } else if render_depth {
+            6 + num_features
+        } else {
+            4 + num_features
+        };
 
         let out_img = client.tensor_uninitialized(
             vec![img_size.y as usize, img_size.x as usize, channels],
@@ -308,9 +386,13 @@ impl Backend for Fusion<InnerWgpu> {
             num_visible: client.tensor_uninitialized(vec![1], DType::I32),
             final_index: client
                 .tensor_uninitialized(vec![img_size.y as usize, img_size.x as usize], DType::I32),
-            cum_tiles_hit: client.tensor_uninitialized(vec![num_points], DType::I32),
-            tile_bins: client
+            num_contributing: client
                 .tensor_uninitialized(vec![img_size.y as usize, img_size.x as usize], DType::I32),
+            cum_tiles_hit: client.tensor_uninitialized(vec![num_points], DType::I32),
+            tile_bins: client.tensor_uninitialized(
+                vec![tile_bounds.y as usize, tile_bounds.x as usize, 2],
+                DType::I32,
+            ),
             compact_gid_from_isect: client.tensor_uninitialized(vec![max_intersects], DType::I32),
             global_from_compact_gid: client.tensor_uninitialized(vec![num_points], DType::I32),
         };
@@ -319,10 +401,12 @@ impl Backend for Fusion<InnerWgpu> {
             "render_splats",
             &[
                 means.into_description(),
+                viewmat.into_description(),
                 log_scales.into_description(),
                 quats.into_description(),
                 sh_coeffs.into_description(),
                 raw_opacity.into_description(),
+                features.into_description(),
             ],
             &[
                 aux.projected_splats.to_description_out(),
@@ -330,6 +414,7 @@ impl Backend for Fusion<InnerWgpu> {
                 aux.num_intersections.to_description_out(),
                 aux.num_visible.to_description_out(),
                 aux.final_index.to_description_out(),
+                aux.num_contributing.to_description_out(),
                 aux.cum_tiles_hit.to_description_out(),
                 aux.tile_bins.to_description_out(),
                 aux.compact_gid_from_isect.to_description_out(),
@@ -341,7 +426,11 @@ impl Backend for Fusion<InnerWgpu> {
         let op = CustomOp {
             cam: cam.clone(),
             img_size,
+            active_sh_degree,
+            background,
             render_u32_buffer,
+            render_depth,
+            has_features,
             desc: desc.clone(),
         };
 
@@ -357,20 +446,28 @@ impl Backend for Fusion<InnerWgpu> {
         struct CustomOp {
             desc: CustomOpDescription,
             sh_degree: u32,
+            render_depth: bool,
+            // Whether the features input holds features, or is just a placeholder.
+            has_features: bool,
         }
 
         impl Operation<FusionJitRuntime<WgpuRuntime>> for CustomOp {
             fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
                 let (
-                    [v_output, means, log_scales, quats, raw_opac, out_img, projected_splats, num_visible, uniforms_buffer, compact_gid_from_isect, global_from_compact_gid, tile_bins, final_index],
-                    [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_xy],
+                    [v_output, means, log_scales, quats, raw_opac, features, out_img, projected_splats, num_visible, uniforms_buffer, compact_gid_from_isect, global_from_compact_gid, tile_bins, final_index],
+                    [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_xy, v_viewmat, v_features],
                 ) = self.desc.consume();
 
+                // Always take the features, so a placeholder is released as well.
+                let features = h.get_float_tensor::<InnerWgpu>(&features);
+                let device = features.device.clone();
+
                 let grads = render_backward(
                     h.get_float_tensor::<InnerWgpu>(&means),
                     h.get_float_tensor::<InnerWgpu>(&quats),
                     h.get_float_tensor::<InnerWgpu>(&log_scales),
                     h.get_float_tensor::<InnerWgpu>(&raw_opac),
+                    self.has_features.then_some(features),
                     h.get_float_tensor::<InnerWgpu>(&out_img),
                     h.get_float_tensor::<InnerWgpu>(&v_output),
                     h.get_float_tensor::<InnerWgpu>(&projected_splats),
@@ -381,6 +478,7 @@ impl Backend for Fusion<InnerWgpu> {
                     h.get_int_tensor::<InnerWgpu>(&tile_bins),
                     h.get_int_tensor::<InnerWgpu>(&final_index),
                     self.sh_degree,
+                    self.render_depth,
                 );
 
                 // // Register output.
@@ -390,6 +488,12 @@ impl Backend for Fusion<InnerWgpu> {
                 h.register_float_tensor::<InnerWgpu>(&v_coeffs.id, grads.v_coeffs);
                 h.register_float_tensor::<InnerWgpu>(&v_raw_opac.id, grads.v_raw_opac);
                 h.register_float_tensor::<InnerWgpu>(&v_xy.id, grads.v_xy);
+                h.register_float_tensor::<InnerWgpu>(&v_viewmat.id, grads.v_viewmat);
+                // Without features, the placeholder output is never read.
+                let v_features_grad = grads
+                    .v_features
+                    .unwrap_or_else(|| InnerWgpu::float_zeros([1].into(), &device));
+                h.register_float_tensor::<InnerWgpu>(&v_features.id, v_features_grad);
             }
         }
 
@@ -399,13 +503,22 @@ impl Backend for Fusion<InnerWgpu> {
         let num_points = state.means.shape[0];
         let coeffs = sh_coeffs_for_degree(state.sh_degree) as usize;
 
+        // Like the forward pass, use a placeholder when there are no features.
+        let has_features = state.features.is_some();
+        let features = state
+            .features
+            .unwrap_or_else(|| Self::float_zeros([1].into(), &Self::float_device(&state.means)));
+        let v_features = client.tensor_uninitialized(features.shape.clone(), DType::F32);
+
         let grads = SplatGrads::<Self> {
             v_means: client.tensor_uninitialized(vec![num_points, 3], DType::F32),
             v_quats: client.tensor_uninitialized(vec![num_points, 4], DType::F32),
             v_scales: client.tensor_uninitialized(vec![num_points, 3], DType::F32),
             v_coeffs: client.tensor_uninitialized(vec![num_points, coeffs, 3], DType::F32),
             v_raw_opac: client.tensor_uninitialized(vec![num_points], DType::F32),
-            v_xy: client.tensor_uninitialized(vec![num_points, 2], DType::F32),
+            v_xy: client.tensor_uninitialized(vec![num_points, 4], DType::F32),
+            v_viewmat: client.tensor_uninitialized(vec![4, 4], DType::F32),
+            v_features: has_features.then(|| v_features.clone()),
         };
 
         let desc = CustomOpDescription::new(
@@ -416,6 +529,7 @@ impl Backend for Fusion<InnerWgpu> {
                 state.log_scales.into_description(),
                 state.quats.into_description(),
                 state.raw_opac.into_description(),
+                features.into_description(),
                 state.out_img.into_description(),
                 state.aux.projected_splats.into_description(),
                 state.aux.num_visible.into_description(),
@@ -432,11 +546,15 @@ impl Backend for Fusion<InnerWgpu> {
                 grads.v_coeffs.to_description_out(),
                 grads.v_raw_opac.to_description_out(),
                 grads.v_xy.to_description_out(),
+                grads.v_viewmat.to_description_out(),
+                v_features.to_description_out(),
             ],
         );
 
         let op = CustomOp {
             sh_degree: state.sh_degree,
+            render_depth: state.render_depth,
+            has_features,
             desc: desc.clone(),
         };
 
@@ -445,4 +563,307 @@ impl Backend for Fusion<InnerWgpu> {
     }
 }
 
+// Implement the render functions on the CPU for the NdArray backend, using the CPU
+// reference implementation. This is a lot slower than the kernels, but works without a GPU.
+impl Backend for NdArray {
+    fn render_splats(
+        camera: &Camera,
+        img_size: glam::UVec2,
+        means: Self::FloatTensorPrimitive,
+        _xy_dummy: Self::FloatTensorPrimitive,
+        viewmat: Self::FloatTensorPrimitive,
+        log_scales: Self::FloatTensorPrimitive,
+        quats: Self::FloatTensorPrimitive,
+        sh_coeffs: Self::FloatTensorPrimitive,
+        raw_opacity: Self::FloatTensorPrimitive,
+        features: Option<Self::FloatTensorPrimitive>,
+        active_sh_degree: u32,
+        background: glam::Vec3,
+        render_u32_buffer: bool,
+        render_depth: bool,
+    ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
+        let splats = read_cpu_splats(means, quats, log_scales, sh_coeffs, raw_opacity);
+        let (features, num_features) = read_cpu_features(features);
+        let viewmat = glam::Mat4::from_cols_slice(&read_cpu_floats::<2>(viewmat));
+        let uniforms = reference::Uniforms::new(
+            camera,
+            img_size,
+            splats.coeffs_per_splat,
+            active_sh_degree,
+            background,
+        )
+        .with_viewmat(viewmat);
+        let out = reference::render_with_uniforms(&splats, uniforms, &features, num_features);
+
+        let [h, w] = [img_size.y as usize, img_size.x as usize];
+        let out_img = if render_u32_buffer {
+            let packed = out
+                .image
+                .iter()
+                .map(|c| {
+                    let c = (*c * 255.0)
+                        .clamp(Vec4::ZERO, Vec4::splat(255.0))
+                        .as_uvec4();
+                    f32::from_bits(c.x | (c.y << 8) | (c.z << 16) | (c.w << 24))
+                })
+                .collect();
+            cpu_float_tensor(packed, [h, w, 1])
+        } else {
+            let base_channels = if render_depth { 6 } else { 4 };
+            let channels = base_channels + out.num_features;
+            let mut img = Vec::with_capacity(h * w * channels);
+            for pix_id in 0..h * w {
+                img.extend(out.image[pix_id].to_array());
+                if render_depth {
+                    img.extend([out.depth[pix_id], out.median_depth[pix_id]]);
+                }
+                img.extend(&out.features[pix_id * out.num_features..][..out.num_features]);
+            }
+            cpu_float_tensor(img, [h, w, channels])
+        };
+
+        (out_img, cpu_render_aux(&out, &splats))
+    }
+
+    fn render_splats_bwd(
+        state: GaussianBackwardState<Self>,
+        v_output: Self::FloatTensorPrimitive,
+    ) -> SplatGrads<Self> {
+        let splats = read_cpu_splats(
+            state.means,
+            state.quats,
+            state.log_scales,
+            state.sh_coeffs,
+            state.raw_opac,
+        );
+
+        let (features, num_features) = read_cpu_features(state.features);
+
+        let words: Vec<u32> = read_cpu_ints(state.aux.uniforms_buffer)
+            .into_iter()
+            .map(|w| w as u32)
+            .collect();
+        let uniforms = reference::Uniforms::from_kernel(&bytemuck::pod_read_unaligned::<
+            shaders::helpers::RenderUniforms,
+        >(bytemuck::cast_slice(&words)));
+
+        // Rendering again is about as fast as restoring the render from the aux buffers.
+        let out = reference::render_with_uniforms(&splats, uniforms, &features, num_features);
+
+        let v_output = read_cpu_floats::<3>(v_output);
+        let channels = v_output.len() / out.image.len();
+        let v_image: Vec<_> = v_output
+            .chunks_exact(channels)
+            .map(Vec4::from_slice)
+            .collect();
+        let v_depth: Option<Vec<_>> = state
+            .render_depth
+            .then(|| v_output.chunks_exact(channels).map(|v| v[4]).collect());
+        // The features are the last channels of each pixel.
+        let feature_start = channels - out.num_features;
+        let v_features: Option<Vec<_>> = (out.num_features > 0).then(|| {
+            v_output
+                .chunks_exact(channels)
+                .flat_map(|v| &v[feature_start..])
+                .copied()
+                .collect()
+        });
+
+        let grads = out.backward(&splats, &v_image, v_depth.as_deref(), v_features.as_deref());
+
+        let n = splats.num_splats();
+        let flat3 = |v: Vec<glam::Vec3>| v.into_iter().flat_map(|v| v.to_array()).collect();
+        let flat4 = |v: Vec<Vec4>| v.into_iter().flat_map(|v| v.to_array()).collect();
+
+        SplatGrads {
+            v_means: cpu_float_tensor(flat3(grads.v_means), [n, 3]),
+            v_quats: cpu_float_tensor(flat4(grads.v_rotations), [n, 4]),
+            v_scales: cpu_float_tensor(flat3(grads.v_log_scales), [n, 3]),
+            v_coeffs: cpu_float_tensor(flat3(grads.v_sh_coeffs), [n, splats.coeffs_per_splat, 3]),
+            v_raw_opac: cpu_float_tensor(grads.v_raw_opacities, [n]),
+            v_xy: cpu_float_tensor(flat4(grads.v_xy), [n, 4]),
+            v_viewmat: cpu_float_tensor(grads.v_viewmat.to_cols_array().to_vec(), [4, 4]),
+            v_features: (out.num_features > 0)
+                .then(|| cpu_float_tensor(grads.v_features, [n, out.num_features])),
+        }
+    }
+}
+
+fn read_cpu_floats<const D: usize>(tensor: <NdArray as Backend>::FloatTensorPrimitive) -> Vec<f32> {
+    Tensor::<NdArray, D>::from_primitive(TensorPrimitive::Float(tensor))
+        .into_data()
+        .convert::<f32>()
+        .to_vec()
+        .expect("Tensor was converted to f32")
+}
+
+fn read_cpu_ints(tensor: <NdArray as Backend>::IntTensorPrimitive) -> Vec<i32> {
+    Tensor::<NdArray, 1, Int>::from_primitive(tensor)
+        .into_data()
+        .convert::<i32>()
+        .to_vec()
+        .expect("Tensor was converted to i32")
+}
+
+fn cpu_float_tensor<const D: usize>(
+    values: Vec<f32>,
+    shape: [usize; D],
+) -> <NdArray as Backend>::FloatTensorPrimitive {
+    Tensor::<NdArray, D>::from_data(TensorData::new(values, shape), &NdArrayDevice::Cpu)
+        .into_primitive()
+        .tensor()
+}
+
+fn cpu_int_tensor<const D: usize>(
+    values: Vec<i32>,
+    shape: [usize; D],
+) -> <NdArray as Backend>::IntTensorPrimitive {
+    Tensor::<NdArray, D, Int>::from_data(TensorData::new(values, shape), &NdArrayDevice::Cpu)
+        .into_primitive()
+}
+
+fn read_cpu_splats(
+    means: <NdArray as Backend>::FloatTensorPrimitive,
+    quats: <NdArray as Backend>::FloatTensorPrimitive,
+    log_scales: <NdArray as Backend>::FloatTensorPrimitive,
+    sh_coeffs: <NdArray as Backend>::FloatTensorPrimitive,
+    raw_opacity: <NdArray as Backend>::FloatTensorPrimitive,
+) -> CpuSplats {
+    let coeffs_per_splat =
+        Tensor::<NdArray, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs.clone())).dims()[1];
+
+    CpuSplats {
+        means: read_cpu_floats::<2>(means)
+            .chunks_exact(3)
+            .map(glam::Vec3::from_slice)
+            .collect(),
+        rotations: read_cpu_floats::<2>(quats)
+            .chunks_exact(4)
+            .map(Vec4::from_slice)
+            .collect(),
+        log_scales: read_cpu_floats::<2>(log_scales)
+            .chunks_exact(3)
+            .map(glam::Vec3::from_slice)
+            .collect(),
+        sh_coeffs: read_cpu_floats::<3>(sh_coeffs)
+            .chunks_exact(3)
+            .map(glam::Vec3::from_slice)
+            .collect(),
+        coeffs_per_splat,
+        raw_opacities: read_cpu_floats::<1>(raw_opacity),
+    }
+}
+
+// The features as a flat array, and the number of features per splat.
+fn read_cpu_features(
+    features: Option<<NdArray as Backend>::FloatTensorPrimitive>,
+) -> (Vec<f32>, usize) {
+    features.map_or((vec![], 0), |features| {
+        let features = Tensor::<NdArray, 2>::from_primitive(TensorPrimitive::Float(features));
+        let num_features = features.dims()[1];
+        let values = features
+            .into_data()
+            .convert::<f32>()
+            .to_vec()
+            .expect("Tensor was converted to f32");
+        (values, num_features)
+    })
+}
+
+// Fill in the aux buffers the same way the kernels do.
+fn cpu_render_aux(out: &CpuRender, splats: &CpuSplats) -> RenderAux<NdArray> {
+    let num_points = splats.num_splats();
+    let num_visible = out.projected.len();
+    let tile_bounds = out.uniforms.tile_bounds;
+
+    let mut projected_splats = vec![shaders::helpers::ProjectedSplat::zeroed(); num_points];
+    let mut global_from_compact_gid = vec![0; num_points];
+    for (compact_gid, p) in out.projected.iter().enumerate() {
+        projected_splats[compact_gid] = shaders::helpers::ProjectedSplat {
+            xy_x: p.xy.x,
+            xy_y: p.xy.y,
+            conic_x: p.conic.x,
+            conic_y: p.conic.y,
+            conic_z: p.conic.z,
+            color_r: p.color.x,
+            color_g: p.color.y,
+            color_b: p.color.z,
+            color_a: p.color.w,
+            depth: p.depth,
+        };
+        global_from_compact_gid[compact_gid] = p.global_gid as i32;
+    }
+
+    // Intersections are ordered by tile, and by depth within each tile.
+    let mut compact_gid_from_isect = vec![];
+    let mut tile_bins = vec![];
+    let mut tiles_hit = vec![0; num_points];
+    for tile in &out.tile_splats {
+        let start = compact_gid_from_isect.len() as i32;
+        for &compact_gid in tile {
+            compact_gid_from_isect.push(compact_gid as i32);
+            tiles_hit[compact_gid] += 1;
+        }
+        tile_bins.extend([start, compact_gid_from_isect.len() as i32]);
+    }
+    let num_intersections = compact_gid_from_isect.len();
+
+    let cum_tiles_hit = tiles_hit
+        .iter()
+        .scan(0, |sum, &hits| {
+            *sum += hits;
+            Some(*sum)
+        })
+        .collect();
+
+    // The final index is the last intersection that was composited, or 0 when there are none.
+    let img_size = out.img_size;
+    let final_index = (0..img_size.y)
+        .flat_map(|y| (0..img_size.x).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let pix_id = (x + y * img_size.x) as usize;
+            let tile_id = (x / shaders::helpers::TILE_WIDTH
+                + (y / shaders::helpers::TILE_WIDTH) * tile_bounds.x)
+                as usize;
+            match out.num_composited[pix_id] {
+                0 => 0,
+                count => tile_bins[tile_id * 2] + count as i32 - 1,
+            }
+        })
+        .collect();
+
+    let num_contributing = out.num_contributing.iter().map(|&n| n as i32).collect();
+
+    let uniforms = out.uniforms.to_kernel(
+        num_points as u32,
+        num_visible as u32,
+        splats.coeffs_per_splat as u32,
+    );
+    let uniforms_buffer: Vec<i32> = bytemuck::pod_collect_to_vec(bytemuck::bytes_of(&uniforms));
+    let uniforms_size = uniforms_buffer.len();
+
+    let [h, w] = [img_size.y as usize, img_size.x as usize];
+    RenderAux {
+        projected_splats: cpu_float_tensor(
+            bytemuck::cast_slice(&projected_splats).to_vec(),
+            [
+                num_points,
+                size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>(),
+            ],
+        ),
+        uniforms_buffer: cpu_int_tensor(uniforms_buffer, [uniforms_size]),
+        num_intersections: cpu_int_tensor(vec![num_intersections as i32], [1]),
+        num_visible: cpu_int_tensor(vec![num_visible as i32], [1]),
+        final_index: cpu_int_tensor(final_index, [h, w]),
+        num_contributing: cpu_int_tensor(num_contributing, [h, w]),
+        cum_tiles_hit: cpu_int_tensor(cum_tiles_hit, [num_points]),
+        tile_bins: cpu_int_tensor(
+            tile_bins,
+            [tile_bounds.y as usize, tile_bounds.x as usize, 2],
+        ),
+        compact_gid_from_isect: cpu_int_tensor(compact_gid_from_isect, [num_intersections]),
+        global_from_compact_gid: cpu_int_tensor(global_from_compact_gid, [num_points]),
+    }
+}
+
 impl<B: Backend, C: CheckpointStrategy> AutodiffBackend for Autodiff<B, C> {}
@@ -1,11 +1,14 @@
 use crate::{
-    bounding_box::BoundingBox, camera::Camera, render::sh_coeffs_for_degree,
-    safetensor_utils::safetensor_to_burn, Backend,
+    bounding_box::{BoundingBox, CropBox},
+    camera::Camera,
+    render::{sh_coeffs_for_degree, sh_degree_from_coeffs},
+    safetensor_utils::safetensor_to_burn,
+    Backend,
 };
 use burn::{
     config::Config,
     module::{Module, Param, ParamId},
-    tensor::{activation::sigmoid, Shape, Tensor, TensorData, TensorPrimitive},
+    tensor::{activation::sigmoid, Bool, Int, Shape, Tensor, TensorData, TensorPrimitive},
 };
 use glam::{Quat, Vec3};
 use kiddo::{KdTree, SquaredEuclidean};
@@ -28,7 +31,8 @@ pub struct Splats<B: Backend> {
     pub raw_opacity: Param<Tensor<B, 1>>,
     pub log_scales: Param<Tensor<B, 2>>,
 
-    // Dummy input to track screenspace gradient.
+    // Dummy input to track screenspace gradient. The gradient of this holds
+    // the xy gradient, followed by the summed absolute per-pixel xy gradients.
     pub xys_dummy: Tensor<B, 2>,
 }
 
@@ -196,7 +200,7 @@ impl<B: Backend> Splats<B> {
             rotation: Param::initialized(ParamId::new(), rotation.detach().require_grad()),
             raw_opacity: Param::initialized(ParamId::new(), raw_opacity.detach().require_grad()),
             log_scales: Param::initialized(ParamId::new(), log_scales.detach().require_grad()),
-            xys_dummy: Tensor::zeros([num_points, 2], &device).require_grad(),
+            xys_dummy: Tensor::zeros([num_points, 4], &device).require_grad(),
         }
     }
 
@@ -207,11 +211,191 @@ impl<B: Backend> Splats<B> {
         *tensor = tensor.clone().map(|x| f(x).detach().require_grad());
     }
 
+    /// A new set of splats, holding only the splats at the given indices.
+    pub fn select(&self, indices: Tensor<B, 1, Int>) -> Self {
+        let mut splats = self.clone();
+        Self::map_param(&mut splats.means, |x| x.select(0, indices.clone()));
+        Self::map_param(&mut splats.rotation, |x| x.select(0, indices.clone()));
+        Self::map_param(&mut splats.log_scales, |x| x.select(0, indices.clone()));
+        Self::map_param(&mut splats.sh_coeffs, |x| x.select(0, indices.clone()));
+        Self::map_param(&mut splats.raw_opacity, |x| x.select(0, indices.clone()));
+        splats.xys_dummy = Tensor::zeros([indices.dims()[0], 4], &indices.device()).require_grad();
+        splats
+    }
+
+    /// A [N] mask of the splats with their center inside of the crop box.
+    pub fn crop_mask(&self, crop: &CropBox) -> Tensor<B, 1, Bool> {
+        let device = self.means.device();
+        let n = self.num_splats();
+
+        // Row vectors times the rotation give the positions in the frame of the box.
+        let rotation = Tensor::<B, 2>::from_floats(
+            glam::Mat3::from_quat(crop.rotation.inverse()).to_cols_array_2d(),
+            &device,
+        );
+        let center = Tensor::<B, 1>::from_floats(crop.bounds.center.to_array(), &device);
+        let local = (self.means.val() - center.reshape([1, 3])).matmul(rotation);
+
+        let mut inside = Tensor::<B, 1>::ones([n], &device);
+        for axis in 0..3 {
+            let dist = local.clone().slice([0..n, axis..axis + 1]).squeeze(1).abs();
+            inside = inside * dist.lower_equal_elem(crop.bounds.extent[axis]).float();
+        }
+        inside.greater_elem(0.5)
+    }
+
+    /// Make the splats outside of the crop box fully transparent, without removing them.
+    pub fn hide_outside(&self, crop: &CropBox) -> Self {
+        let outside = self.crop_mask(crop).bool_not();
+        let mut splats = self.clone();
+        Self::map_param(&mut splats.raw_opacity, |x| {
+            x.mask_fill(outside.clone(), f32::NEG_INFINITY)
+        });
+        splats
+    }
+
+    /// Remove the splats outside of the crop box.
+    pub async fn crop(&self, crop: &CropBox) -> Self {
+        let keep = self.crop_mask(crop).argwhere_async().await.squeeze(1);
+        self.select(keep)
+    }
+
+    /// Render the splats from a camera, composited over the `background` colour.
     pub fn render(
         &self,
         camera: &Camera,
         img_size: glam::UVec2,
+        background: glam::Vec3,
+        render_u32_buffer: bool,
+    ) -> (Tensor<B, 3>, crate::RenderAux<B>) {
+        let viewmat = Tensor::from_floats(
+            camera.world_to_local().to_cols_array_2d(),
+            &self.means.device(),
+        );
+        self.render_with_viewmat(
+            camera,
+            viewmat,
+            img_size,
+            self.sh_degree(),
+            background,
+            render_u32_buffer,
+            false,
+        )
+    }
+
+    /// Render the splats like [`Self::render`], along with [H, W] maps of their expected depth
+    /// and median depth in camera space. The median depth is the depth where the transmittance
+    /// drops below 0.5, which gives sharper edges than the expected depth. Both are 0 where the
+    /// splats don't cover the pixel (enough). Only the expected depth has a gradient.
+    pub fn render_depth(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        background: glam::Vec3,
+    ) -> (
+        Tensor<B, 3>,
+        Tensor<B, 2>,
+        Tensor<B, 2>,
+        crate::RenderAux<B>,
+    ) {
+        let viewmat = Tensor::from_floats(
+            camera.world_to_local().to_cols_array_2d(),
+            &self.means.device(),
+        );
+        let (img, aux) = self.render_with_viewmat(
+            camera,
+            viewmat,
+            img_size,
+            self.sh_degree(),
+            background,
+            false,
+            true,
+        );
+
+        let [h, w, _] = img.dims();
+        let rgba = img.clone().slice([0..h, 0..w, 0..4]);
+        let alpha = img.clone().slice([0..h, 0..w, 3..4]);
+        let weighted_depth = img.clone().slice([0..h, 0..w, 4..5]);
+        let expected_depth = (weighted_depth / alpha.clamp_min(1e-6)).squeeze(2);
+        let median_depth = img.slice([0..h, 0..w, 5..6]).squeeze(2);
+
+        (rgba, expected_depth, median_depth, aux)
+    }
+
+    /// Render with a viewmat tensor, to get the gradient of the camera pose. The pose comes from
+    /// the viewmat, only the intrinsics of the camera are used, see
+    /// [`crate::Backend::render_splats`]. Only SH bands up to
+    /// `active_sh_degree` are used, and the splats are composited over `background`. If
+    /// `render_depth` is set, the output has extra weighted depth and median depth channels.
+    pub fn render_with_viewmat(
+        &self,
+        camera: &Camera,
+        viewmat: Tensor<B, 2>,
+        img_size: glam::UVec2,
+        active_sh_degree: u32,
+        background: glam::Vec3,
+        render_u32_buffer: bool,
+        render_depth: bool,
+    ) -> (Tensor<B, 3>, crate::RenderAux<B>) {
+        self.render_inner(
+            camera,
+            viewmat,
+            img_size,
+            None,
+            active_sh_degree,
+            background,
+            render_u32_buffer,
+            render_depth,
+        )
+    }
+
+    /// Render the splats like [`Self::render`], and alpha composite a [N, F] tensor of per splat
+    /// `features` in the same pass, for any number of channels F. The features are composited
+    /// like the colours, but without a background. Returns the [H, W, 4] colours and the
+    /// [H, W, F] features, which are differentiable with respect to both the features and the
+    /// splats. This allows distilling 2D feature maps, eg. segmentation or embeddings, into the
+    /// splats.
+    pub fn render_features(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        background: glam::Vec3,
+        features: Tensor<B, 2>,
+    ) -> (Tensor<B, 3>, Tensor<B, 3>, crate::RenderAux<B>) {
+        let [n, channels] = features.dims();
+        assert_eq!(n, self.num_splats(), "Need features for every splat");
+
+        let viewmat = Tensor::from_floats(
+            camera.world_to_local().to_cols_array_2d(),
+            &self.means.device(),
+        );
+        let (img, aux) = self.render_inner(
+            camera,
+            viewmat,
+            img_size,
+            Some(features),
+            self.sh_degree(),
+            background,
+            false,
+            false,
+        );
+
+        let [h, w, _] = img.dims();
+        let rgba = img.clone().slice([0..h, 0..w, 0..4]);
+        let features = img.slice([0..h, 0..w, 4..4 + channels]);
+        (rgba, features, aux)
+    }
+
+    fn render_inner(
+        &self,
+        camera: &Camera,
+        viewmat: Tensor<B, 2>,
+        img_size: glam::UVec2,
+        features: Option<Tensor<B, 2>>,
+        active_sh_degree: u32,
+        background: glam::Vec3,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Tensor<B, 3>, crate::RenderAux<B>) {
         // TODO: Remove for forward only.
         let rotations = self.rotation.val();
@@ -222,11 +406,16 @@ impl<B: Backend> Splats<B> {
             img_size,
             self.means.val().into_primitive().tensor(),
             self.xys_dummy.clone().into_primitive().tensor(),
+            viewmat.into_primitive().tensor(),
             self.log_scales.val().into_primitive().tensor(),
             norm_rot.into_primitive().tensor(),
             self.sh_coeffs.val().into_primitive().tensor(),
             self.raw_opacity.val().into_primitive().tensor(),
+            features.map(|f| f.into_primitive().tensor()),
+            active_sh_degree,
+            background,
             render_u32_buffer,
+            render_depth,
         );
 
         (Tensor::from_primitive(TensorPrimitive::Float(img)), aux)
@@ -240,6 +429,11 @@ impl<B: Backend> Splats<B> {
         self.log_scales.val().exp()
     }
 
+    /// The degree of the stored SH coefficients.
+    pub fn sh_degree(&self) -> u32 {
+        sh_degree_from_coeffs(self.sh_coeffs.dims()[1] as u32)
+    }
+
     pub fn num_splats(&self) -> usize {
         self.means.dims()[0]
     }
@@ -253,10 +447,48 @@ impl<B: Backend> Splats<B> {
     pub fn from_safetensors(tensors: &SafeTensors, device: &B::Device) -> anyhow::Result<Self> {
         Ok(Self::from_tensor_data(
             safetensor_to_burn::<B, 2>(tensors.tensor("means")?, device),
-            safetensor_to_burn::<B, 2>(tensors.tensor("scales")?, device),
             safetensor_to_burn::<B, 2>(tensors.tensor("quats")?, device),
+            safetensor_to_burn::<B, 2>(tensors.tensor("scales")?, device),
             safetensor_to_burn::<B, 3>(tensors.tensor("coeffs")?, device),
             safetensor_to_burn::<B, 1>(tensors.tensor("opacities")?, device),
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use safetensors::{tensor::TensorView, Dtype};
+    use std::collections::HashMap;
+
+    fn view(data: &[f32], shape: Vec<usize>) -> TensorView<'_> {
+        TensorView::new(Dtype::F32, shape, bytemuck::cast_slice(data)).expect("Invalid tensor")
+    }
+
+    #[test]
+    fn test_from_safetensors() {
+        let num_splats = 2;
+        let means = vec![0.0f32; num_splats * 3];
+        let quats: Vec<f32> = (0..num_splats * 4).map(|i| i as f32).collect();
+        // Distinct shapes and values, so swapping the quats and scales can't go unnoticed.
+        let scales: Vec<f32> = (0..num_splats * 3).map(|i| -(i as f32)).collect();
+        let coeffs = vec![0.5f32; num_splats * 3];
+        let opacities = vec![1.0f32; num_splats];
+        let tensors = HashMap::from([
+            ("means", view(&means, vec![num_splats, 3])),
+            ("quats", view(&quats, vec![num_splats, 4])),
+            ("scales", view(&scales, vec![num_splats, 3])),
+            ("coeffs", view(&coeffs, vec![num_splats, 1, 3])),
+            ("opacities", view(&opacities, vec![num_splats])),
+        ]);
+        let bytes = safetensors::serialize(&tensors, &None).expect("Failed to serialize");
+        let tensors = SafeTensors::deserialize(&bytes).expect("Failed to deserialize");
+
+        let splats = Splats::<NdArray>::from_safetensors(&tensors, &NdArrayDevice::Cpu)
+            .expect("Failed to load");
+        let to_vec = |t: Tensor<NdArray, 2>| t.into_data().to_vec::<f32>().expect("Wrong type");
+        assert_eq!(to_vec(splats.rotation.val()), quats);
+        assert_eq!(to_vec(splats.log_scales.val()), scales);
+    }
+}
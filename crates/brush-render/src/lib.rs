@@ -11,10 +11,13 @@ mod dim_check;
 mod kernels;
 mod safetensor_utils;
 mod shaders;
+mod transform;
 
 pub mod bounding_box;
 pub mod camera;
 pub mod gaussian_splats;
+pub mod picking;
+pub mod reference;
 pub mod render;
 
 #[derive(Debug, Clone)]
@@ -25,6 +28,7 @@ pub struct RenderAux<B: Backend> {
     pub num_intersections: B::IntTensorPrimitive,
     pub num_visible: B::IntTensorPrimitive,
     pub final_index: B::IntTensorPrimitive,
+    pub num_contributing: B::IntTensorPrimitive,
     pub cum_tiles_hit: B::IntTensorPrimitive,
     pub tile_bins: B::IntTensorPrimitive,
     pub compact_gid_from_isect: B::IntTensorPrimitive,
@@ -59,6 +63,34 @@ impl<B: Backend> RenderAux<B> {
         let min = bins.clone().slice([0..ty, 0..tx, 0..1]).squeeze(2);
         max - min
     }
+
+    /// The number of splats in the tile of each pixel, see [`Self::read_tile_depth`].
+    pub fn read_pixel_tile_depth(&self) -> Tensor<B, 2, Int> {
+        let [h, w] = Tensor::<B, 2, Int>::from_primitive(self.final_index.clone()).dims();
+        tiles_to_pixels(self.read_tile_depth(), [h, w])
+    }
+
+    /// The number of splats which contributed to each pixel, that is which were composited
+    /// with an alpha of at least 1/255. This is only available for float renders, not for u32
+    /// buffers.
+    pub fn read_pixel_depth(&self) -> Tensor<B, 2, Int> {
+        Tensor::from_primitive(self.num_contributing.clone())
+    }
+}
+
+// Repeat the value of each tile for all of its pixels.
+fn tiles_to_pixels<B: Backend>(
+    tiles: Tensor<B, 2, Int>,
+    img_size: [usize; 2],
+) -> Tensor<B, 2, Int> {
+    let [ty, tx] = tiles.dims();
+    let tile_width = shaders::helpers::TILE_WIDTH as usize;
+    tiles
+        .reshape([ty, 1, tx, 1])
+        .repeat_dim(1, tile_width)
+        .repeat_dim(3, tile_width)
+        .reshape([ty * tile_width, tx * tile_width])
+        .slice([0..img_size[0], 0..img_size[1]])
 }
 
 pub struct SplatGrads<B: Backend> {
@@ -68,6 +100,8 @@ pub struct SplatGrads<B: Backend> {
     v_coeffs: B::FloatTensorPrimitive,
     v_raw_opac: B::FloatTensorPrimitive,
     v_xy: B::FloatTensorPrimitive,
+    v_viewmat: B::FloatTensorPrimitive,
+    v_features: Option<B::FloatTensorPrimitive>,
 }
 
 #[derive(Debug, Clone)]
@@ -76,8 +110,12 @@ pub struct GaussianBackwardState<B: Backend> {
     log_scales: B::FloatTensorPrimitive,
     quats: B::FloatTensorPrimitive,
     raw_opac: B::FloatTensorPrimitive,
+    sh_coeffs: B::FloatTensorPrimitive,
+    features: Option<B::FloatTensorPrimitive>,
     out_img: B::FloatTensorPrimitive,
     sh_degree: u32,
+    // Whether the output has the depth channels, see [`Backend::render_splats`].
+    render_depth: bool,
     aux: RenderAux<B>,
 }
 
@@ -88,19 +126,45 @@ pub trait Backend: burn::tensor::backend::Backend {
     /// This projects the gaussians, sorts them, and rasterizes them to a buffer, in a
     /// differentiable way.
     /// The arguments are all passed as raw tensors. See [`Splats`] for a convenient Module that wraps this fun
-    /// The ['xy_dummy'] variable is only used to carry screenspace xy gradients.
+    /// The ['xy_dummy'] variable is only used to carry screenspace xy gradients. It has shape
+    /// [N, 4], and its gradient holds the xy gradient followed by the sum of the absolute per-pixel
+    /// xy gradients.
+    /// The ['viewmat'] is the [4, 4] world to camera matrix to render from, in column major order.
+    /// Only the intrinsics of `cam` are used, the pose always comes from this tensor. This way
+    /// the pose can be computed on the device, eg. with learned corrections, without a readback.
+    /// The gradient of the viewmat allows connecting it to learnable pose parameters.
+    /// Only the SH bands up to `active_sh_degree` are used to calculate colors. Higher bands are
+    /// ignored and get a zero gradient. Pass `u32::MAX` to use all bands.
+    /// The splats are composited over the `background` colour, both for the float and the packed
+    /// u32 output. The alpha channel is still the coverage of the splats, and the gradient
+    /// accounts for the background showing through.
     /// This function can optionally render a "u32" buffer, which is a packed RGBA (8 bits per channel)
     /// buffer. This is useful when the results need to be displayed immediatly.
+    /// When `render_depth` is set, the output gets a fifth channel with the camera space depth of
+    /// each splat, weighted by its contribution to the pixel. The alpha channel holds the
+    /// accumulated weight, so dividing by it gives the expected depth. The depth channel is
+    /// differentiable like the colors, and can't be combined with a u32 buffer. A sixth channel
+    /// holds the median depth, the depth of the splat where the transmittance drops below 0.5
+    /// (or 0 if it never does). The median depth has no gradient.
+    /// Optionally, a [N, F] tensor of per splat `features` is composited in the same pass, into
+    /// F channels after all others. These are composited like the colours, but without a
+    /// background, and are differentiable with respect to both the features and the splats.
+    /// They can't be combined with a u32 buffer either.
     fn render_splats(
         cam: &Camera,
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         xy_grad_dummy: Self::FloatTensorPrimitive,
+        viewmat: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        features: Option<Self::FloatTensorPrimitive>,
+        active_sh_degree: u32,
+        background: glam::Vec3,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>);
 
     /// Backward pass for render_splats.
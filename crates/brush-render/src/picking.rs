@@ -0,0 +1,196 @@
+use crate::shaders::helpers::TILE_WIDTH;
+use crate::{Backend, RenderAux};
+use burn::tensor::{Int, Tensor, TensorPrimitive};
+use glam::{UVec2, Vec2};
+use std::collections::HashMap;
+
+/// A splat that contributed to a picked region of a render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickedSplat {
+    /// The index of the splat in the rendered [`crate::gaussian_splats::Splats`].
+    pub global_id: u32,
+    /// The summed compositing weight (alpha * transmittance) over the picked pixels.
+    pub weight: f32,
+}
+
+async fn read_ints<B: Backend>(tensor: Tensor<B, 1, Int>) -> Vec<i32> {
+    tensor
+        .into_data_async()
+        .await
+        .convert::<i32>()
+        .to_vec()
+        .expect("Tensor was converted to i32")
+}
+
+impl<B: Backend> RenderAux<B> {
+    /// Find the splats that contributed to the pixels in `[min, max)`, sorted from the highest to
+    /// the lowest compositing weight.
+    ///
+    /// This replays the rasterizer for the picked pixels, so it works for both float and u32
+    /// renders. Only the intersections of the tiles overlapping the region are read back, all
+    /// at once rather than tile by tile.
+    pub async fn read_picked_splats(&self, min: UVec2, max: UVec2) -> Vec<PickedSplat> {
+        let tile_bins = Tensor::<B, 3, Int>::from_primitive(self.tile_bins.clone());
+        let [ty, tx, _] = tile_bins.dims();
+        let [h, w] = Tensor::<B, 2, Int>::from_primitive(self.final_index.clone()).dims();
+
+        let max = max.min(glam::uvec2(w as u32, h as u32));
+        if min.x >= max.x || min.y >= max.y {
+            return vec![];
+        }
+
+        let tile_bins = read_ints(tile_bins.reshape([ty * tx * 2])).await;
+
+        // The intersections of each tile overlapping the region.
+        let tile_min = min / TILE_WIDTH;
+        let tile_max = (max - 1) / TILE_WIDTH;
+        let tiles: Vec<_> = (tile_min.y..=tile_max.y)
+            .flat_map(|tile_y| (tile_min.x..=tile_max.x).map(move |tile_x| (tile_x, tile_y)))
+            .filter_map(|(tile_x, tile_y)| {
+                let tile_id = (tile_x + tile_y * tx as u32) as usize;
+                let start = tile_bins[tile_id * 2] as usize;
+                let end = tile_bins[tile_id * 2 + 1] as usize;
+                (start < end).then_some((glam::uvec2(tile_x, tile_y), start..end))
+            })
+            .collect();
+
+        let (Some(isect_start), Some(isect_end)) = (
+            tiles.iter().map(|(_, range)| range.start).min(),
+            tiles.iter().map(|(_, range)| range.end).max(),
+        ) else {
+            return vec![];
+        };
+
+        // Read back all intersections of these tiles at once. Tiles are stored in order, so this
+        // is a single range, which can include tiles outside of the region.
+        let compact_gids = Tensor::<B, 1, Int>::from_primitive(self.compact_gid_from_isect.clone())
+            .slice([isect_start..isect_end]);
+        let global_ids = read_ints(
+            Tensor::<B, 1, Int>::from_primitive(self.global_from_compact_gid.clone())
+                .select(0, compact_gids.clone()),
+        )
+        .await;
+        let projected =
+            Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(self.projected_splats.clone()))
+                .select(0, compact_gids)
+                .into_data_async()
+                .await
+                .convert::<f32>()
+                .to_vec::<f32>()
+                .expect("Projected splats were converted to f32");
+        let projected_size = projected.len() / global_ids.len();
+
+        let mut weights = HashMap::new();
+
+        for (tile, range) in tiles {
+            let range = range.start - isect_start..range.end - isect_start;
+            let global_ids = &global_ids[range.clone()];
+            let projected = &projected[range.start * projected_size..range.end * projected_size];
+
+            // The pixels of this tile that are part of the picked region.
+            let pix_min = min.max(tile * TILE_WIDTH);
+            let pix_max = max.min((tile + 1) * TILE_WIDTH);
+
+            for y in pix_min.y..pix_max.y {
+                for x in pix_min.x..pix_max.x {
+                    let pixel_coord = glam::vec2(x as f32, y as f32) + 0.5;
+
+                    // Composite the same way as rasterize.wgsl.
+                    let mut trans = 1.0;
+                    for (splat, &global_id) in
+                        projected.chunks_exact(projected_size).zip(global_ids)
+                    {
+                        let delta = Vec2::new(splat[0], splat[1]) - pixel_coord;
+                        let sigma = 0.5
+                            * (splat[2] * delta.x * delta.x + splat[4] * delta.y * delta.y)
+                            + splat[3] * delta.x * delta.y;
+                        let alpha = (splat[8] * (-sigma).exp()).min(0.999);
+
+                        if sigma < 0.0 || alpha < 1.0 / 255.0 {
+                            continue;
+                        }
+
+                        let next_trans = trans * (1.0 - alpha);
+                        if next_trans <= 1e-4 {
+                            break;
+                        }
+
+                        *weights.entry(global_id as u32).or_insert(0.0) += alpha * trans;
+                        trans = next_trans;
+                    }
+                }
+            }
+        }
+
+        let mut picked: Vec<_> = weights
+            .into_iter()
+            .map(|(global_id, weight)| PickedSplat { global_id, weight })
+            .collect();
+        picked.sort_by(|a, b| {
+            b.weight
+                .total_cmp(&a.weight)
+                .then(a.global_id.cmp(&b.global_id))
+        });
+        picked
+    }
+
+    /// Find the splats that contributed to a single pixel, see [`Self::read_picked_splats`].
+    pub async fn read_picked_pixel(&self, pixel: UVec2) -> Vec<PickedSplat> {
+        self.read_picked_splats(pixel, pixel + 1).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        camera::{focal_to_fov, fov_to_focal, Camera},
+        gaussian_splats::{inverse_sigmoid, Splats},
+    };
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use glam::{vec2, vec3, Quat, Vec3};
+
+    #[tokio::test]
+    async fn test_pick_pixel() {
+        let device = NdArrayDevice::Cpu;
+        let means = vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 0.0, 2.0),
+            vec3(3.0, 0.0, 0.0),
+        ];
+        let splats = Splats::<NdArray>::from_raw(
+            means,
+            None,
+            Some(vec![Vec3::splat(0.5f32.ln()); 3]),
+            Some(vec![0.5; 9]),
+            Some(vec![inverse_sigmoid(0.5); 3]),
+            &device,
+        );
+
+        let size = glam::uvec2(32, 32);
+        let focal = fov_to_focal(std::f64::consts::PI * 0.5, size.x);
+        let cam = Camera::new(
+            vec3(0.0, 0.0, -8.0),
+            Quat::IDENTITY,
+            focal_to_fov(focal, size.x),
+            focal_to_fov(focal, size.y),
+            vec2(0.5, 0.5),
+        );
+
+        let (_, aux) = splats.render(&cam, size, Vec3::ZERO, false);
+        let picked = aux.read_picked_pixel(size / 2).await;
+
+        // The splat in front comes first, the one behind it is partially occluded, and the
+        // one off to the side doesn't cover the pixel.
+        let ids: Vec<_> = picked.iter().map(|p| p.global_id).collect();
+        assert_eq!(ids, vec![0, 1]);
+        assert!(picked[0].weight > picked[1].weight);
+
+        // Picking a region around all splats finds all of them.
+        let picked = aux.read_picked_splats(glam::UVec2::ZERO, size).await;
+        assert_eq!(picked.len(), 3);
+
+        // An empty region picks nothing.
+        let picked = aux.read_picked_splats(size, size).await;
+        assert!(picked.is_empty());
+    }
+}
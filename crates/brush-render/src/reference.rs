@@ -0,0 +1,1301 @@
+// A CPU implementation of the splat renderer.
+//
+// This follows the kernels step by step: project, sort by depth, bin into tiles, rasterize,
+// and the same steps backwards. It is slow, but doesn't need a GPU, which makes it useful to
+// check the kernels against, and to render on machines without any GPU adapter.
+use burn::tensor::Tensor;
+use glam::{vec2, vec3, Mat2, Mat3, Mat4, UVec2, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+
+use crate::{
+    camera::Camera,
+    gaussian_splats::Splats,
+    render::{sh_degree_from_coeffs, SH_C0},
+    shaders::helpers::{RenderUniforms, COV_BLUR, TILE_WIDTH},
+    Backend,
+};
+
+/// Splat parameters on the CPU, laid out like the tensors of [`Splats`].
+#[derive(Clone, Debug, Default)]
+pub struct CpuSplats {
+    pub means: Vec<Vec3>,
+    /// Rotations as (w, x, y, z) quaternions. These don't have to be normalized.
+    pub rotations: Vec<Vec4>,
+    pub log_scales: Vec<Vec3>,
+    /// SH coefficients of all splats, `coeffs_per_splat` for each splat.
+    pub sh_coeffs: Vec<Vec3>,
+    pub coeffs_per_splat: usize,
+    pub raw_opacities: Vec<f32>,
+}
+
+async fn read_floats<B: Backend, const D: usize>(tensor: Tensor<B, D>) -> Vec<f32> {
+    tensor
+        .into_data_async()
+        .await
+        .convert::<f32>()
+        .to_vec::<f32>()
+        .expect("Splat data was converted to f32")
+}
+
+impl CpuSplats {
+    /// Read back the parameters of some splats.
+    pub async fn from_splats<B: Backend>(splats: &Splats<B>) -> Self {
+        let means = read_floats(splats.means.val()).await;
+        let rotations = read_floats(splats.rotation.val()).await;
+        let log_scales = read_floats(splats.log_scales.val()).await;
+        let sh_coeffs = read_floats(splats.sh_coeffs.val()).await;
+
+        Self {
+            means: means.chunks_exact(3).map(Vec3::from_slice).collect(),
+            rotations: rotations.chunks_exact(4).map(Vec4::from_slice).collect(),
+            log_scales: log_scales.chunks_exact(3).map(Vec3::from_slice).collect(),
+            sh_coeffs: sh_coeffs.chunks_exact(3).map(Vec3::from_slice).collect(),
+            coeffs_per_splat: splats.sh_coeffs.dims()[1],
+            raw_opacities: read_floats(splats.raw_opacity.val()).await,
+        }
+    }
+
+    pub fn num_splats(&self) -> usize {
+        self.means.len()
+    }
+}
+
+// The settings the kernels read from their uniforms.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Uniforms {
+    viewmat: Mat4,
+    focal: Vec2,
+    img_size: UVec2,
+    tile_bounds: UVec2,
+    pixel_center: Vec2,
+    sh_degree: u32,
+    background: Vec3,
+}
+
+impl Uniforms {
+    pub(crate) fn new(
+        camera: &Camera,
+        img_size: UVec2,
+        coeffs_per_splat: usize,
+        active_sh_degree: u32,
+        background: Vec3,
+    ) -> Self {
+        Self {
+            viewmat: camera.world_to_local(),
+            focal: camera.focal(img_size),
+            img_size,
+            tile_bounds: glam::uvec2(
+                img_size.x.div_ceil(TILE_WIDTH),
+                img_size.y.div_ceil(TILE_WIDTH),
+            ),
+            pixel_center: camera.center(img_size),
+            sh_degree: sh_degree_from_coeffs(coeffs_per_splat as u32).min(active_sh_degree),
+            background,
+        }
+    }
+
+    // Render from this world to camera matrix rather than the pose of the camera.
+    pub(crate) fn with_viewmat(mut self, viewmat: Mat4) -> Self {
+        self.viewmat = viewmat;
+        self
+    }
+
+    // The uniforms as the kernels see them.
+    pub(crate) fn to_kernel(
+        self,
+        total_splats: u32,
+        num_visible: u32,
+        coeffs_per_splat: u32,
+    ) -> RenderUniforms {
+        RenderUniforms {
+            viewmat: self.viewmat.to_cols_array_2d(),
+            focal: self.focal.into(),
+            img_size: self.img_size.into(),
+            tile_bounds: self.tile_bounds.into(),
+            pixel_center: self.pixel_center.into(),
+            sh_degree: self.sh_degree,
+            num_visible,
+            total_splats,
+            coeffs_per_splat,
+            background: self.background.extend(0.0).into(),
+            num_features: 0,
+            pad_0: 0,
+            pad_1: 0,
+            pad_2: 0,
+        }
+    }
+
+    pub(crate) fn from_kernel(uniforms: &RenderUniforms) -> Self {
+        Self {
+            viewmat: Mat4::from_cols_array_2d(&uniforms.viewmat),
+            focal: uniforms.focal.into(),
+            img_size: uniforms.img_size.into(),
+            tile_bounds: uniforms.tile_bounds.into(),
+            pixel_center: uniforms.pixel_center.into(),
+            sh_degree: uniforms.sh_degree,
+            background: Vec4::from(uniforms.background).xyz(),
+        }
+    }
+
+    fn rotation(&self) -> Mat3 {
+        Mat3::from_mat4(self.viewmat)
+    }
+
+    fn translation(&self) -> Vec3 {
+        self.viewmat.w_axis.xyz()
+    }
+}
+
+// A splat projected to the screen, see ProjectedSplat in helpers.wgsl.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Projected {
+    pub(crate) global_gid: usize,
+    pub(crate) xy: Vec2,
+    pub(crate) conic: Vec3,
+    pub(crate) color: Vec4,
+    pub(crate) depth: f32,
+}
+
+/// The result of [`render`].
+#[derive(Clone, Debug)]
+pub struct CpuRender {
+    pub img_size: UVec2,
+    /// RGBA colors of all pixels, row by row. Like the GPU output, the alpha is the coverage of
+    /// the splats, and the colors are composited over the background.
+    pub image: Vec<Vec4>,
+    /// Camera space depth of each splat, weighted by its contribution to the pixel. Dividing by
+    /// the alpha gives the expected depth.
+    pub depth: Vec<f32>,
+    /// Depth of the splat where the transmittance of the pixel drops below 0.5, or 0 when
+    /// the pixel isn't covered that much.
+    pub median_depth: Vec<f32>,
+    /// Composited features of all pixels, `num_features` for each pixel. Unlike the colors,
+    /// these aren't composited over the background.
+    pub features: Vec<f32>,
+    pub num_features: usize,
+
+    pub(crate) uniforms: Uniforms,
+    // Visible splats, sorted by depth.
+    pub(crate) projected: Vec<Projected>,
+    // For each tile, the (depth sorted) splats which can touch it.
+    pub(crate) tile_splats: Vec<Vec<usize>>,
+    // For each pixel, the number of splats of its tile composited before the pixel was saturated.
+    pub(crate) num_composited: Vec<usize>,
+    // For each pixel, the number of splats which contributed to it.
+    pub(crate) num_contributing: Vec<usize>,
+    // The features of all splats this was rendered with.
+    pub(crate) splat_features: Vec<f32>,
+}
+
+/// Gradients of [`CpuRender::backward`], for each parameter of [`CpuSplats`].
+#[derive(Clone, Debug)]
+pub struct CpuGrads {
+    pub v_means: Vec<Vec3>,
+    pub v_rotations: Vec<Vec4>,
+    pub v_log_scales: Vec<Vec3>,
+    pub v_sh_coeffs: Vec<Vec3>,
+    pub v_raw_opacities: Vec<f32>,
+    /// The screenspace xy gradient of each splat, followed by the summed absolute per pixel
+    /// xy gradients.
+    pub v_xy: Vec<Vec4>,
+    /// Gradient of the column major world to camera matrix. Like the kernels, this ignores the
+    /// view direction of the SH colors.
+    pub v_viewmat: Mat4,
+    /// Gradient of the features of each splat, empty when rendering without features.
+    pub v_features: Vec<f32>,
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn quat_to_mat(quat: Vec4) -> Mat3 {
+    let (w, x, y, z) = (quat.x, quat.y, quat.z, quat.w);
+
+    Mat3::from_cols(
+        vec3(
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + w * z),
+            2.0 * (x * z - w * y),
+        ),
+        vec3(
+            2.0 * (x * y - w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + w * x),
+        ),
+        vec3(
+            2.0 * (x * z + w * y),
+            2.0 * (y * z - w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ),
+    )
+}
+
+fn quat_to_mat_vjp(quat: Vec4, v_r: Mat3) -> Vec4 {
+    let (w, x, y, z) = (quat.x, quat.y, quat.z, quat.w);
+    // Element of v_r at a column and row.
+    let v = |c: usize, r: usize| v_r.col(c)[r];
+
+    2.0 * Vec4::new(
+        x * (v(1, 2) - v(2, 1)) + y * (v(2, 0) - v(0, 2)) + z * (v(0, 1) - v(1, 0)),
+        -2.0 * x * (v(1, 1) + v(2, 2))
+            + y * (v(0, 1) + v(1, 0))
+            + z * (v(0, 2) + v(2, 0))
+            + w * (v(1, 2) - v(2, 1)),
+        x * (v(0, 1) + v(1, 0)) - 2.0 * y * (v(0, 0) + v(2, 2))
+            + z * (v(1, 2) + v(2, 1))
+            + w * (v(2, 0) - v(0, 2)),
+        x * (v(0, 2) + v(2, 0)) + y * (v(1, 2) + v(2, 1)) - 2.0 * z * (v(0, 0) + v(1, 1))
+            + w * (v(0, 1) - v(1, 0)),
+    )
+}
+
+// Returns a * b^T.
+fn outer_product(a: Vec3, b: Vec3) -> Mat3 {
+    Mat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+fn frustum_limits(u: &Uniforms) -> (Vec2, Vec2) {
+    let img_size = u.img_size.as_vec2();
+    let tan_fov = 0.5 * img_size / u.focal;
+    let lims_pos = (img_size - u.pixel_center) / u.focal + 0.3 * tan_fov;
+    let lims_neg = u.pixel_center / u.focal + 0.3 * tan_fov;
+    (lims_pos, lims_neg)
+}
+
+// The Jacobian of the projection, as its two rows.
+fn calc_cam_j(mean_c: Vec3, u: &Uniforms) -> [Vec3; 2] {
+    let (lims_pos, lims_neg) = frustum_limits(u);
+    let rz = 1.0 / mean_c.z;
+    let rz2 = rz * rz;
+
+    // Get ndc coords +- clipped to the frustum.
+    let t = mean_c.z * (mean_c.xy() * rz).clamp(-lims_neg, lims_pos);
+
+    [
+        vec3(u.focal.x * rz, 0.0, -u.focal.x * t.x * rz2),
+        vec3(0.0, u.focal.y * rz, -u.focal.y * t.y * rz2),
+    ]
+}
+
+// Returns the upper triangle of the blurred 2D covariance.
+fn calc_cov2d(covar_c: Mat3, j: &[Vec3; 2]) -> Vec3 {
+    vec3(
+        j[0].dot(covar_c * j[0]) + COV_BLUR,
+        j[0].dot(covar_c * j[1]),
+        j[1].dot(covar_c * j[1]) + COV_BLUR,
+    )
+}
+
+fn inverse_symmetric(mat: Vec3) -> Vec3 {
+    let det = mat.x * mat.z - mat.y * mat.y;
+    vec3(mat.z, -mat.y, mat.x) * (1.0 / det)
+}
+
+fn radius_from_cov(cov2d: Vec3) -> f32 {
+    let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+    let b = 0.5 * (cov2d.x + cov2d.z);
+    let v1 = b + (b * b - det).max(0.01).sqrt();
+    (3.0 * v1.sqrt()).ceil()
+}
+
+// Inclusive min and exclusive max tile of a splat.
+fn get_tile_bbox(xy: Vec2, radius: f32, tile_bounds: UVec2) -> (UVec2, UVec2) {
+    let center = xy / TILE_WIDTH as f32;
+    let dims = Vec2::splat(radius / TILE_WIDTH as f32);
+    let bounds = tile_bounds.as_ivec2();
+    let min = (center - dims).as_ivec2().clamp(glam::IVec2::ZERO, bounds);
+    let max = (center + dims + 1.0)
+        .as_ivec2()
+        .clamp(glam::IVec2::ZERO, bounds);
+    (min.as_uvec2(), max.as_uvec2())
+}
+
+// Like the WGSL sign(), which is zero for zero.
+fn sign(v: Vec2) -> Vec2 {
+    let s = |x: f32| if x == 0.0 { 0.0 } else { x.signum() };
+    vec2(s(v.x), s(v.y))
+}
+
+fn check_edge(p1: Vec2, p2: Vec2, center: Vec2, conic: Mat2) -> bool {
+    let edge = p2 - p1;
+    let f = p1 - center;
+    let a = edge.dot(conic * edge);
+    let b = 2.0 * f.dot(conic * edge);
+    let c = f.dot(conic * f) - 1.0;
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return false;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let t1 = (-b - sqrt_discriminant) / (2.0 * a);
+    let t2 = (-b + sqrt_discriminant) / (2.0 * a);
+    (0.0..=1.0).contains(&t1) || (0.0..=1.0).contains(&t2)
+}
+
+fn ellipse_intersects_aabb(box_pos: Vec2, box_extent: Vec2, center: Vec2, conic: Mat2) -> bool {
+    let d = center - box_pos;
+
+    if d.abs().cmple(box_extent).all() {
+        return true;
+    }
+
+    let corner_sign = sign(d);
+    let nearest_corner = box_pos + corner_sign * box_extent;
+
+    let cp = nearest_corner - center;
+    if cp.dot(conic * cp) <= 1.0 {
+        return true;
+    }
+
+    let edge1_end = nearest_corner - vec2(corner_sign.x * 2.0 * box_extent.x, 0.0);
+    let edge2_end = nearest_corner - vec2(0.0, corner_sign.y * 2.0 * box_extent.y);
+    check_edge(nearest_corner, edge1_end, center, conic)
+        || check_edge(nearest_corner, edge2_end, center, conic)
+}
+
+fn can_be_visible(tile: UVec2, xy: Vec2, conic: Vec3, opac: f32) -> bool {
+    let sigma = (opac * 255.0).ln();
+    if sigma <= 0.0 {
+        return false;
+    }
+    let conic_scaled = conic / (2.0 * sigma);
+    let tile_extent = Vec2::splat(TILE_WIDTH as f32 / 2.0);
+    let tile_center = (tile * TILE_WIDTH).as_vec2() + tile_extent;
+    let conic_mat = Mat2::from_cols(
+        vec2(conic_scaled.x, conic_scaled.y),
+        vec2(conic_scaled.y, conic_scaled.z),
+    );
+    ellipse_intersects_aabb(tile_center, tile_extent, xy, conic_mat)
+}
+
+// Values of the SH basis functions up to a degree, in the order of the coefficients. Uses the
+// same constants as sh_coeffs_to_color in project_visible.wgsl.
+#[allow(clippy::excessive_precision)]
+pub(crate) fn sh_basis(degree: u32, dir: Vec3) -> Vec<f32> {
+    let mut basis = vec![SH_C0];
+    if degree == 0 {
+        return basis;
+    }
+
+    let (x, y, z) = (dir.x, dir.y, dir.z);
+
+    let f_tmp0a = 0.48860251190292;
+    basis.extend([-f_tmp0a * y, f_tmp0a * z, -f_tmp0a * x]);
+    if degree == 1 {
+        return basis;
+    }
+
+    let z2 = z * z;
+    let f_tmp0b = -1.092548430592079 * z;
+    let f_tmp1a = 0.5462742152960395;
+    let f_c1 = x * x - y * y;
+    let f_s1 = 2.0 * x * y;
+    let p_sh6 = 0.9461746957575601 * z2 - 0.3153915652525201;
+    let p_sh7 = f_tmp0b * x;
+    let p_sh5 = f_tmp0b * y;
+    let p_sh8 = f_tmp1a * f_c1;
+    let p_sh4 = f_tmp1a * f_s1;
+    basis.extend([p_sh4, p_sh5, p_sh6, p_sh7, p_sh8]);
+    if degree == 2 {
+        return basis;
+    }
+
+    let f_tmp0c = -2.285228997322329 * z2 + 0.4570457994644658;
+    let f_tmp1b = 1.445305721320277 * z;
+    let f_tmp2a = -0.5900435899266435;
+    let f_c2 = x * f_c1 - y * f_s1;
+    let f_s2 = x * f_s1 + y * f_c1;
+    let p_sh12 = z * (1.865881662950577 * z2 - 1.119528997770346);
+    let p_sh13 = f_tmp0c * x;
+    let p_sh11 = f_tmp0c * y;
+    let p_sh14 = f_tmp1b * f_c1;
+    let p_sh10 = f_tmp1b * f_s1;
+    let p_sh15 = f_tmp2a * f_c2;
+    let p_sh9 = f_tmp2a * f_s2;
+    basis.extend([p_sh9, p_sh10, p_sh11, p_sh12, p_sh13, p_sh14, p_sh15]);
+    if degree == 3 {
+        return basis;
+    }
+
+    let f_tmp0d = z * (-4.683325804901025 * z2 + 2.007139630671868);
+    let f_tmp1c = 3.31161143515146 * z2 - 0.47308734787878;
+    let f_tmp2b = -1.770130769779931 * z;
+    let f_tmp3a = 0.6258357354491763;
+    let f_c3 = x * f_c2 - y * f_s2;
+    let f_s3 = x * f_s2 + y * f_c2;
+    let p_sh20 = 1.984313483298443 * z * p_sh12 - 1.006230589874905 * p_sh6;
+    let p_sh21 = f_tmp0d * x;
+    let p_sh19 = f_tmp0d * y;
+    let p_sh22 = f_tmp1c * f_c1;
+    let p_sh18 = f_tmp1c * f_s1;
+    let p_sh23 = f_tmp2b * f_c2;
+    let p_sh17 = f_tmp2b * f_s2;
+    let p_sh24 = f_tmp3a * f_c3;
+    let p_sh16 = f_tmp3a * f_s3;
+    basis.extend([
+        p_sh16, p_sh17, p_sh18, p_sh19, p_sh20, p_sh21, p_sh22, p_sh23, p_sh24,
+    ]);
+    basis
+}
+
+// Like the kernels, the camera position is taken from the translation of the viewmat.
+fn view_dir(mean: Vec3, u: &Uniforms) -> Vec3 {
+    let camera_pos = -u.translation();
+    (mean - camera_pos).normalize()
+}
+
+// Project all visible splats, see project_forward.wgsl and project_visible.wgsl.
+fn project(splats: &CpuSplats, u: &Uniforms) -> Vec<Projected> {
+    let rotation = u.rotation();
+    let img_size = u.img_size.as_vec2();
+
+    let mut projected = vec![];
+
+    for global_gid in 0..splats.num_splats() {
+        let mean = splats.means[global_gid];
+        let mean_c = rotation * mean + u.translation();
+
+        if mean_c.z < 0.01 || mean_c.z > 1e12 {
+            continue;
+        }
+
+        let scale = splats.log_scales[global_gid].exp();
+        let quat = splats.rotations[global_gid].normalize();
+        let m = quat_to_mat(quat) * Mat3::from_diagonal(scale);
+        let covar_c = rotation * (m * m.transpose()) * rotation.transpose();
+        let cov2d = calc_cov2d(covar_c, &calc_cam_j(mean_c, u));
+        let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+
+        if det <= 0.0 {
+            continue;
+        }
+
+        let xy = u.focal * mean_c.xy() * (1.0 / mean_c.z) + u.pixel_center;
+        let radius = radius_from_cov(cov2d);
+
+        if radius <= 0.0
+            || xy.x + radius <= 0.0
+            || xy.x - radius >= img_size.x
+            || xy.y + radius <= 0.0
+            || xy.y - radius >= img_size.y
+        {
+            continue;
+        }
+
+        let coeffs = &splats.sh_coeffs[global_gid * splats.coeffs_per_splat..];
+        let color = sh_basis(u.sh_degree, view_dir(mean, u))
+            .iter()
+            .zip(coeffs)
+            .map(|(&b, &c)| b * c)
+            .sum::<Vec3>()
+            + 0.5;
+
+        projected.push(Projected {
+            global_gid,
+            xy,
+            conic: inverse_symmetric(cov2d),
+            color: color.extend(sigmoid(splats.raw_opacities[global_gid])),
+            depth: mean_c.z,
+        });
+    }
+
+    projected
+}
+
+// Alpha of a splat at a pixel, and the values needed for its gradient.
+struct PixelAlpha {
+    alpha: f32,
+    vis: f32,
+    delta: Vec2,
+}
+
+fn pixel_alpha(projected: &Projected, pixel_coord: Vec2, max_alpha: f32) -> Option<PixelAlpha> {
+    let conic = projected.conic;
+    let delta = projected.xy - pixel_coord;
+    let sigma = 0.5 * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y)
+        + conic.y * delta.x * delta.y;
+    let vis = (-sigma).exp();
+    let alpha = (projected.color.w * vis).min(max_alpha);
+
+    (sigma >= 0.0 && alpha >= 1.0 / 255.0).then_some(PixelAlpha { alpha, vis, delta })
+}
+
+/// Render splats on the CPU, like [`crate::Backend::render_splats`] renders them on the GPU.
+/// Only SH bands up to `active_sh_degree` are used, and the splats are composited over the
+/// `background` colour.
+pub fn render(
+    splats: &CpuSplats,
+    camera: &Camera,
+    img_size: UVec2,
+    active_sh_degree: u32,
+    background: Vec3,
+) -> CpuRender {
+    let uniforms = Uniforms::new(
+        camera,
+        img_size,
+        splats.coeffs_per_splat,
+        active_sh_degree,
+        background,
+    );
+    render_with_uniforms(splats, uniforms, &[], 0)
+}
+
+/// Render splats like [`render`], and composite the `features` of each splat along with the
+/// colors, see [`crate::Backend::render_splats`]. The features are a row major
+/// [N, `num_features`] array.
+pub fn render_features(
+    splats: &CpuSplats,
+    camera: &Camera,
+    img_size: UVec2,
+    active_sh_degree: u32,
+    background: Vec3,
+    features: &[f32],
+    num_features: usize,
+) -> CpuRender {
+    let uniforms = Uniforms::new(
+        camera,
+        img_size,
+        splats.coeffs_per_splat,
+        active_sh_degree,
+        background,
+    );
+    render_with_uniforms(splats, uniforms, features, num_features)
+}
+
+pub(crate) fn render_with_uniforms(
+    splats: &CpuSplats,
+    uniforms: Uniforms,
+    features: &[f32],
+    num_features: usize,
+) -> CpuRender {
+    let img_size = uniforms.img_size;
+    let tile_bounds = uniforms.tile_bounds;
+    let background = uniforms.background;
+
+    assert!(
+        img_size.x > 0 && img_size.y > 0,
+        "Can't render 0 sized images"
+    );
+
+    // The kernels radix sort the splats by depth, which is stable.
+    let mut projected = project(splats, &uniforms);
+    projected.sort_by(|a, b| a.depth.total_cmp(&b.depth));
+
+    // Bin the splats into tiles. As the splats are visited in depth order, the splats of
+    // each tile stay sorted.
+    let mut tile_splats = vec![vec![]; (tile_bounds.x * tile_bounds.y) as usize];
+    for (compact_gid, splat) in projected.iter().enumerate() {
+        let cov2d = inverse_symmetric(splat.conic);
+        let (tile_min, tile_max) = get_tile_bbox(splat.xy, radius_from_cov(cov2d), tile_bounds);
+
+        for ty in tile_min.y..tile_max.y {
+            for tx in tile_min.x..tile_max.x {
+                if can_be_visible(glam::uvec2(tx, ty), splat.xy, splat.conic, splat.color.w) {
+                    tile_splats[(tx + ty * tile_bounds.x) as usize].push(compact_gid);
+                }
+            }
+        }
+    }
+
+    let num_pixels = (img_size.x * img_size.y) as usize;
+    let mut image = vec![Vec4::ZERO; num_pixels];
+    let mut depth = vec![0.0; num_pixels];
+    let mut median_depth = vec![0.0; num_pixels];
+    let mut num_composited = vec![0; num_pixels];
+    let mut num_contributing = vec![0; num_pixels];
+
+    assert_eq!(
+        features.len(),
+        splats.num_splats() * num_features,
+        "Need features for every splat"
+    );
+    let mut pixel_features = vec![0.0; num_pixels * num_features];
+
+    for y in 0..img_size.y {
+        for x in 0..img_size.x {
+            let pix_id = (x + y * img_size.x) as usize;
+            let tile_id = (x / TILE_WIDTH + (y / TILE_WIDTH) * tile_bounds.x) as usize;
+            let pixel_coord = vec2(x as f32, y as f32) + 0.5;
+
+            let mut t = 1.0;
+            let mut pix_out = Vec3::ZERO;
+            let mut depth_out = 0.0;
+
+            for (i, &compact_gid) in tile_splats[tile_id].iter().enumerate() {
+                let splat = &projected[compact_gid];
+                let Some(PixelAlpha { alpha, .. }) = pixel_alpha(splat, pixel_coord, 0.999) else {
+                    continue;
+                };
+
+                let next_t = t * (1.0 - alpha);
+                if next_t <= 1e-4 {
+                    break;
+                }
+
+                let fac = alpha * t;
+                pix_out += splat.color.xyz() * fac;
+                depth_out += splat.depth * fac;
+
+                let splat_features = &features[splat.global_gid * num_features..][..num_features];
+                let pixel_out = &mut pixel_features[pix_id * num_features..][..num_features];
+                for (out, feature) in pixel_out.iter_mut().zip(splat_features) {
+                    *out += feature * fac;
+                }
+
+                if t > 0.5 && next_t <= 0.5 {
+                    median_depth[pix_id] = splat.depth;
+                }
+
+                t = next_t;
+                num_composited[pix_id] = i + 1;
+                num_contributing[pix_id] += 1;
+            }
+
+            image[pix_id] = (pix_out + t * background).extend(1.0 - t);
+            depth[pix_id] = depth_out;
+        }
+    }
+
+    CpuRender {
+        img_size,
+        image,
+        depth,
+        median_depth,
+        features: pixel_features,
+        num_features,
+        uniforms,
+        projected,
+        tile_splats,
+        num_composited,
+        num_contributing,
+        splat_features: features.to_vec(),
+    }
+}
+
+// Per visible splat gradients of the rasterization.
+#[derive(Clone, Copy, Default)]
+struct RasterGrads {
+    v_xy: Vec4,
+    v_conic: Vec3,
+    v_color: Vec4,
+    v_depth: f32,
+}
+
+impl CpuRender {
+    /// Number of splats that were projected onto the image.
+    pub fn num_visible(&self) -> usize {
+        self.projected.len()
+    }
+
+    /// Calculate the gradients of the splats, given the gradient of the image and optionally
+    /// of the weighted depth and the features. The splats have to be the ones this was
+    /// rendered from.
+    pub fn backward(
+        &self,
+        splats: &CpuSplats,
+        v_image: &[Vec4],
+        v_depth: Option<&[f32]>,
+        v_features: Option<&[f32]>,
+    ) -> CpuGrads {
+        let (raster_grads, v_splat_features) =
+            self.rasterize_backward(v_image, v_depth, v_features);
+        let u = &self.uniforms;
+
+        let num_splats = splats.num_splats();
+        let mut grads = CpuGrads {
+            v_means: vec![Vec3::ZERO; num_splats],
+            v_rotations: vec![Vec4::ZERO; num_splats],
+            v_log_scales: vec![Vec3::ZERO; num_splats],
+            v_sh_coeffs: vec![Vec3::ZERO; splats.sh_coeffs.len()],
+            v_raw_opacities: vec![0.0; num_splats],
+            v_xy: vec![Vec4::ZERO; num_splats],
+            v_viewmat: Mat4::ZERO,
+            v_features: v_splat_features,
+        };
+
+        for (splat, raster) in self.projected.iter().zip(raster_grads) {
+            let global_gid = splat.global_gid;
+            let mean = splats.means[global_gid];
+
+            // See gather_grads.wgsl.
+            let basis = sh_basis(u.sh_degree, view_dir(mean, u));
+            let base = global_gid * splats.coeffs_per_splat;
+            for (i, b) in basis.iter().enumerate() {
+                grads.v_sh_coeffs[base + i] = *b * raster.v_color.xyz();
+            }
+            let opac = splat.color.w;
+            grads.v_raw_opacities[global_gid] = raster.v_color.w * opac * (1.0 - opac);
+            grads.v_xy[global_gid] = raster.v_xy;
+
+            // See project_backwards.wgsl.
+            let rotation = u.rotation();
+            let mean_c = rotation * mean + u.translation();
+
+            let raw_quat = splats.rotations[global_gid];
+            let quat = raw_quat.normalize();
+            let scale = splats.log_scales[global_gid].exp();
+            let rotmat = quat_to_mat(quat);
+            let s = Mat3::from_diagonal(scale);
+            let m = rotmat * s;
+            let covar = m * m.transpose();
+            let covar_c = rotation * covar * rotation.transpose();
+            let j = calc_cam_j(mean_c, u);
+            let conic = inverse_symmetric(calc_cov2d(covar_c, &j));
+
+            let covar2d_inv = Mat2::from_cols(vec2(conic.x, conic.y), vec2(conic.y, conic.z));
+            let v_conic = raster.v_conic;
+            let v_covar2d_inv = Mat2::from_cols(
+                vec2(v_conic.x, v_conic.y * 0.5),
+                vec2(v_conic.y * 0.5, v_conic.z),
+            );
+            let v_covar2d = -(covar2d_inv * v_covar2d_inv * covar2d_inv);
+
+            let mut v_mean_c = persp_proj_vjp(&j, mean_c, covar_c, u, v_covar2d, raster.v_xy.xy());
+            v_mean_c.z += raster.v_depth;
+
+            // v_covar_c = J^T * v_covar2d * J.
+            let g = |r: usize, c: usize| v_covar2d.col(c)[r];
+            let mut v_covar_c = Mat3::ZERO;
+            for (a, j_a) in j.iter().enumerate() {
+                for (b, j_b) in j.iter().enumerate() {
+                    v_covar_c += outer_product(*j_a, *j_b) * g(a, b);
+                }
+            }
+
+            let mut v_r = outer_product(v_mean_c, mean);
+            v_r +=
+                v_covar_c * rotation * covar.transpose() + v_covar_c.transpose() * rotation * covar;
+            let v_mean = rotation.transpose() * v_mean_c;
+            let v_covar = rotation.transpose() * v_covar_c * rotation;
+
+            let v_m = (v_covar + v_covar.transpose()) * m;
+            let v_scale = vec3(
+                rotmat.x_axis.dot(v_m.x_axis),
+                rotmat.y_axis.dot(v_m.y_axis),
+                rotmat.z_axis.dot(v_m.z_axis),
+            );
+            let v_quat = quat_to_mat_vjp(quat, v_m * s);
+
+            grads.v_means[global_gid] = v_mean;
+            grads.v_log_scales[global_gid] = v_scale * scale;
+            // The kernels get normalized quaternions, so also apply the gradient of the
+            // normalization to get the gradient of the stored rotation.
+            grads.v_rotations[global_gid] = (v_quat - quat * quat.dot(v_quat)) / raw_quat.length();
+
+            grads.v_viewmat += Mat4::from_cols(
+                v_r.x_axis.extend(0.0),
+                v_r.y_axis.extend(0.0),
+                v_r.z_axis.extend(0.0),
+                v_mean_c.extend(0.0),
+            );
+        }
+
+        grads
+    }
+
+    // See rasterize_backwards.wgsl. Returns the gradients of the visible splats, and the
+    // gradients of the features of all splats.
+    fn rasterize_backward(
+        &self,
+        v_image: &[Vec4],
+        v_depth: Option<&[f32]>,
+        v_features: Option<&[f32]>,
+    ) -> (Vec<RasterGrads>, Vec<f32>) {
+        let img_size = self.img_size;
+        let background = self.uniforms.background;
+        let tile_bounds = self.uniforms.tile_bounds;
+        let num_features = self.num_features;
+        let mut grads = vec![RasterGrads::default(); self.projected.len()];
+        let mut v_splat_features = vec![0.0; self.splat_features.len()];
+
+        for y in 0..img_size.y {
+            for x in 0..img_size.x {
+                let pix_id = (x + y * img_size.x) as usize;
+                let tile_id = (x / TILE_WIDTH + (y / TILE_WIDTH) * tile_bounds.x) as usize;
+                let pixel_coord = vec2(x as f32, y as f32) + 0.5;
+
+                let t_final = 1.0 - self.image[pix_id].w;
+                let v_out = v_image[pix_id];
+                let v_out_depth = v_depth.map_or(0.0, |v| v[pix_id]);
+                let v_out_features =
+                    v_features.map_or(&[][..], |v| &v[pix_id * num_features..][..num_features]);
+
+                let mut t = t_final;
+                let mut buffer = Vec3::ZERO;
+                let mut buffer_depth = 0.0;
+                // The features behind the splat, dotted with their gradient.
+                let mut buffer_features = 0.0;
+
+                let composited = &self.tile_splats[tile_id][..self.num_composited[pix_id]];
+                for &compact_gid in composited.iter().rev() {
+                    let splat = &self.projected[compact_gid];
+                    // Nb: The backward kernel clamps the alpha a bit lower than the forward pass.
+                    let Some(PixelAlpha { alpha, vis, delta }) =
+                        pixel_alpha(splat, pixel_coord, 0.99)
+                    else {
+                        continue;
+                    };
+
+                    let color = splat.color.xyz();
+                    let ra = 1.0 / (1.0 - alpha);
+                    t *= ra;
+                    let fac = alpha * t;
+
+                    let mut v_alpha = (color * t - buffer * ra).dot(v_out.xyz());
+                    v_alpha += t_final * ra * v_out.w;
+                    v_alpha -= t_final * ra * background.dot(v_out.xyz());
+                    v_alpha += (splat.depth * t - buffer_depth * ra) * v_out_depth;
+
+                    let feature_start = splat.global_gid * num_features;
+                    let splat_features = &self.splat_features[feature_start..][..num_features];
+                    let feature_dot: f32 = splat_features
+                        .iter()
+                        .zip(v_out_features)
+                        .map(|(f, v)| f * v)
+                        .sum();
+                    v_alpha += feature_dot * t - buffer_features * ra;
+                    buffer_features += feature_dot * fac;
+
+                    let v_feature_grads = &mut v_splat_features[feature_start..][..num_features];
+                    for (v_feature, v_out) in v_feature_grads.iter_mut().zip(v_out_features) {
+                        *v_feature += fac * v_out;
+                    }
+
+                    buffer += color * fac;
+                    buffer_depth += splat.depth * fac;
+
+                    let conic = splat.conic;
+                    let v_sigma = -splat.color.w * vis * v_alpha;
+                    let v_xy = v_sigma
+                        * vec2(
+                            conic.x * delta.x + conic.y * delta.y,
+                            conic.y * delta.x + conic.z * delta.y,
+                        );
+
+                    let grad = &mut grads[compact_gid];
+                    grad.v_xy += Vec4::new(v_xy.x, v_xy.y, v_xy.x.abs(), v_xy.y.abs());
+                    grad.v_conic += vec3(
+                        0.5 * v_sigma * delta.x * delta.x,
+                        v_sigma * delta.x * delta.y,
+                        0.5 * v_sigma * delta.y * delta.y,
+                    );
+                    grad.v_color += (fac * v_out.xyz()).extend(vis * v_alpha);
+                    grad.v_depth += fac * v_out_depth;
+                }
+            }
+        }
+
+        (grads, v_splat_features)
+    }
+}
+
+fn persp_proj_vjp(
+    j: &[Vec3; 2],
+    mean_c: Vec3,
+    covar_c: Mat3,
+    u: &Uniforms,
+    v_cov2d: Mat2,
+    v_mean2d: Vec2,
+) -> Vec3 {
+    let focal = u.focal;
+    let (x, y, z) = (mean_c.x, mean_c.y, mean_c.z);
+    let rz = 1.0 / z;
+    let rz2 = rz * rz;
+    let rz3 = rz2 * rz;
+
+    let mut v_mean_c = vec3(
+        focal.x * rz * v_mean2d.x,
+        focal.y * rz * v_mean2d.y,
+        -(focal.x * x * v_mean2d.x + focal.y * y * v_mean2d.y) * rz2,
+    );
+
+    // v_J = v_cov2d * J * covar_c^T + v_cov2d^T * J * covar_c, as rows.
+    let g = |r: usize, c: usize| v_cov2d.col(c)[r];
+    let v_j = [0, 1].map(|a| {
+        let gj = j[0] * g(a, 0) + j[1] * g(a, 1);
+        let gtj = j[0] * g(0, a) + j[1] * g(1, a);
+        covar_c * gj + covar_c.transpose() * gtj
+    });
+
+    let (lims_pos, lims_neg) = frustum_limits(u);
+    let t = z * (mean_c.xy() * rz).clamp(-lims_neg, lims_pos);
+
+    // Clipping to the frustum.
+    if x * rz <= lims_pos.x && x * rz >= -lims_neg.x {
+        v_mean_c.x += -focal.x * rz2 * v_j[0].z;
+    } else {
+        v_mean_c.z += -focal.x * rz3 * v_j[0].z * t.x;
+    }
+    if y * rz <= lims_pos.y && y * rz >= -lims_neg.y {
+        v_mean_c.y += -focal.y * rz2 * v_j[1].z;
+    } else {
+        v_mean_c.z += -focal.y * rz3 * v_j[1].z * t.y;
+    }
+    v_mean_c.z += -focal.x * rz2 * v_j[0].x - focal.y * rz2 * v_j[1].y
+        + 2.0 * focal.x * t.x * rz3 * v_j[0].z
+        + 2.0 * focal.y * t.y * rz3 * v_j[1].z;
+
+    v_mean_c
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        camera::{focal_to_fov, fov_to_focal},
+        safetensor_utils::safetensor_to_burn,
+    };
+    use anyhow::{Context, Result};
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use safetensors::SafeTensors;
+
+    fn read_tensor(tensors: &SafeTensors, name: &str) -> Result<Vec<f32>> {
+        Ok(tensors
+            .tensor(name)?
+            .data()
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+
+    fn assert_close(name: &str, values: &[f32], reference: &[f32], rtol: f32, atol: f32) {
+        assert_eq!(values.len(), reference.len(), "{name} has the wrong size");
+        for (i, (&v, &r)) in values.iter().zip(reference).enumerate() {
+            assert!(
+                (v - r).abs() <= atol + rtol * r.abs(),
+                "{name}[{i}]: {v} != reference {r}"
+            );
+        }
+    }
+
+    fn flatten<const N: usize>(values: impl IntoIterator<Item = [f32; N]>) -> Vec<f32> {
+        values.into_iter().flatten().collect()
+    }
+
+    // Compare against the test cases generated with gsplat, see NerfStudioRefGen.ipynb. This
+    // doesn't need a GPU.
+    #[test]
+    fn test_reference_cases() -> Result<()> {
+        for path in ["tiny_case", "basic_case"] {
+            let mut buffer = Vec::new();
+            let _ =
+                File::open(format!("./test_cases/{path}.safetensors"))?.read_to_end(&mut buffer)?;
+            let tensors = SafeTensors::deserialize(&buffer)?;
+
+            let coeffs = read_tensor(&tensors, "coeffs")?;
+            let splats = CpuSplats {
+                means: read_tensor(&tensors, "means")?
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect(),
+                rotations: read_tensor(&tensors, "quats")?
+                    .chunks_exact(4)
+                    .map(Vec4::from_slice)
+                    .collect(),
+                log_scales: read_tensor(&tensors, "scales")?
+                    .chunks_exact(3)
+                    .map(Vec3::from_slice)
+                    .collect(),
+                coeffs_per_splat: tensors.tensor("coeffs")?.shape()[1],
+                sh_coeffs: coeffs.chunks_exact(3).map(Vec3::from_slice).collect(),
+                raw_opacities: read_tensor(&tensors, "opacities")?,
+            };
+
+            let [h, w] = [0, 1].map(|i| tensors.tensor("out_img").unwrap().shape()[i] as u32);
+            let focal = fov_to_focal(std::f64::consts::PI * 0.5, w);
+            let cam = Camera::new(
+                vec3(0.0, 0.0, -8.0),
+                glam::Quat::IDENTITY,
+                focal_to_fov(focal, w),
+                focal_to_fov(focal, h),
+                vec2(0.5, 0.5),
+            );
+
+            let out = render(&splats, &cam, glam::uvec2(w, h), u32::MAX, Vec3::ZERO);
+            let image = flatten(out.image.iter().map(|c| c.to_array()));
+            assert_close(
+                "image",
+                &image,
+                &read_tensor(&tensors, "out_img")?,
+                1e-4,
+                1e-5,
+            );
+
+            let v_image: Vec<Vec4> = read_tensor(&tensors, "v_out_img")?
+                .chunks_exact(4)
+                .map(Vec4::from_slice)
+                .collect();
+            let grads = out.backward(&splats, &v_image, None, None);
+
+            let v_xy = flatten(grads.v_xy.iter().map(|v| v.xy().to_array()));
+            assert_close("v_xy", &v_xy, &read_tensor(&tensors, "v_xy")?, 1e-3, 1e-7);
+
+            let v_opac = &grads.v_raw_opacities;
+            let v_opac_ref = read_tensor(&tensors, "v_opacities")?;
+            assert_close("v_opacities", v_opac, &v_opac_ref, 1e-3, 1e-7);
+
+            let v_coeffs = flatten(grads.v_sh_coeffs.iter().map(|v| v.to_array()));
+            assert_close(
+                "v_coeffs",
+                &v_coeffs,
+                &read_tensor(&tensors, "v_coeffs")?,
+                1e-3,
+                1e-7,
+            );
+
+            let v_means = flatten(grads.v_means.iter().map(|v| v.to_array()));
+            assert_close(
+                "v_means",
+                &v_means,
+                &read_tensor(&tensors, "v_means")?,
+                1e-3,
+                1e-7,
+            );
+
+            let v_quats = flatten(grads.v_rotations.iter().map(|v| v.to_array()));
+            assert_close(
+                "v_quats",
+                &v_quats,
+                &read_tensor(&tensors, "v_quats")?,
+                1e-3,
+                1e-7,
+            );
+
+            let v_scales = flatten(grads.v_log_scales.iter().map(|v| v.to_array()));
+            assert_close(
+                "v_scales",
+                &v_scales,
+                &read_tensor(&tensors, "v_scales")?,
+                1e-3,
+                1e-7,
+            );
+        }
+        Ok(())
+    }
+
+    // The NdArray backend renders with this reference, check that its gradients make it
+    // through autodiff.
+    #[test]
+    fn test_ndarray_backend() -> Result<()> {
+        type DiffBack = Autodiff<NdArray>;
+        let device = NdArrayDevice::Cpu;
+
+        let mut buffer = Vec::new();
+        let _ = File::open("./test_cases/tiny_case.safetensors")?.read_to_end(&mut buffer)?;
+        let tensors = SafeTensors::deserialize(&buffer)?;
+        let splats = Splats::<DiffBack>::from_safetensors(&tensors, &device)?;
+
+        let img_ref = safetensor_to_burn::<DiffBack, 3>(tensors.tensor("out_img")?, &device);
+        let [h, w, _] = img_ref.dims();
+        let focal = fov_to_focal(std::f64::consts::PI * 0.5, w as u32);
+        let cam = Camera::new(
+            vec3(0.0, 0.0, -8.0),
+            glam::Quat::IDENTITY,
+            focal_to_fov(focal, w as u32),
+            focal_to_fov(focal, h as u32),
+            vec2(0.5, 0.5),
+        );
+
+        let (out, _) = splats.render(&cam, glam::uvec2(w as u32, h as u32), Vec3::ZERO, false);
+        assert!(out.clone().all_close(img_ref, Some(1e-4), Some(1e-5)));
+
+        // Backpropagate the gradient of the image from the test case.
+        let v_out = safetensor_to_burn::<DiffBack, 3>(tensors.tensor("v_out_img")?, &device);
+        let grads = (out * v_out).sum().backward();
+
+        let v_means = splats.means.grad(&grads).context("means grad")?;
+        let v_means_ref =
+            safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_means")?, &device).inner();
+        assert!(v_means.all_close(v_means_ref, Some(1e-3), Some(1e-7)));
+
+        let v_quats = splats.rotation.grad(&grads).context("quats grad")?;
+        let v_quats_ref =
+            safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_quats")?, &device).inner();
+        assert!(v_quats.all_close(v_quats_ref, Some(1e-3), Some(1e-7)));
+
+        let v_scales = splats.log_scales.grad(&grads).context("scales grad")?;
+        let v_scales_ref =
+            safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_scales")?, &device).inner();
+        assert!(v_scales.all_close(v_scales_ref, Some(1e-3), Some(1e-7)));
+
+        let v_coeffs = splats.sh_coeffs.grad(&grads).context("coeffs grad")?;
+        let v_coeffs_ref =
+            safetensor_to_burn::<DiffBack, 3>(tensors.tensor("v_coeffs")?, &device).inner();
+        assert!(v_coeffs.all_close(v_coeffs_ref, Some(1e-3), Some(1e-7)));
+
+        let v_opac = splats.raw_opacity.grad(&grads).context("opacities grad")?;
+        let v_opac_ref =
+            safetensor_to_burn::<DiffBack, 1>(tensors.tensor("v_opacities")?, &device).inner();
+        assert!(v_opac.all_close(v_opac_ref, Some(1e-3), Some(1e-7)));
+
+        Ok(())
+    }
+
+    // Two overlapping splats, with four features each. The splats are large enough to cover
+    // the whole image well above the alpha cutoff, so the render is smooth in their parameters.
+    fn feature_case() -> (CpuSplats, Vec<f32>, Camera) {
+        let splats = CpuSplats {
+            means: vec![Vec3::ZERO, vec3(0.2, -0.1, 0.5)],
+            rotations: vec![Vec4::new(1.0, 0.0, 0.0, 0.0); 2],
+            log_scales: vec![Vec3::splat(0.5); 2],
+            sh_coeffs: vec![Vec3::ZERO; 2],
+            coeffs_per_splat: 1,
+            raw_opacities: vec![0.0, 0.5],
+        };
+        let features = vec![0.3, -1.0, 2.0, 0.5, 1.5, -0.2, 0.0, 1.0];
+        let cam = Camera::new(
+            vec3(0.0, 0.0, -5.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            vec2(0.5, 0.5),
+        );
+        (splats, features, cam)
+    }
+
+    #[test]
+    fn test_feature_grads() {
+        let (splats, features, cam) = feature_case();
+        let img_size = glam::uvec2(16, 16);
+        let weights = [1.0, -0.5, 0.25, 2.0];
+
+        // The features of all pixels, weighted per channel. This is summed in double precision
+        // to keep the differences below accurate.
+        let loss = |splats: &CpuSplats, features: &[f32]| -> f64 {
+            let out = render_features(splats, &cam, img_size, 0, Vec3::ZERO, features, 4);
+            out.features
+                .chunks_exact(4)
+                .flat_map(|f| f.iter().zip(&weights).map(|(f, w)| (f * w) as f64))
+                .sum()
+        };
+
+        let out = render_features(&splats, &cam, img_size, 0, Vec3::ZERO, &features, 4);
+        assert_eq!(out.num_visible(), 2);
+        let v_image = vec![Vec4::ZERO; out.image.len()];
+        let v_features: Vec<_> = weights.iter().copied().cycle().take(16 * 16 * 4).collect();
+        let grads = out.backward(&splats, &v_image, None, Some(&v_features));
+
+        // Compare against central differences.
+        let eps = 1e-2;
+        let check = |name: &str, grad: f32, plus: f64, minus: f64| {
+            let numeric = ((plus - minus) / (2.0 * eps as f64)) as f32;
+            assert!(
+                (grad - numeric).abs() <= 1e-2 + 1e-2 * numeric.abs(),
+                "{name}: {grad} != numeric {numeric}"
+            );
+        };
+
+        for i in 0..features.len() {
+            let [mut plus, mut minus] = [features.clone(), features.clone()];
+            plus[i] += eps;
+            minus[i] -= eps;
+            let numeric = [loss(&splats, &plus), loss(&splats, &minus)];
+            check("v_features", grads.v_features[i], numeric[0], numeric[1]);
+        }
+
+        // The features change how much the opacity matters.
+        for i in 0..splats.num_splats() {
+            let [mut plus, mut minus] = [splats.clone(), splats.clone()];
+            plus.raw_opacities[i] += eps;
+            minus.raw_opacities[i] -= eps;
+            let numeric = [loss(&plus, &features), loss(&minus, &features)];
+            check(
+                "v_opacities",
+                grads.v_raw_opacities[i],
+                numeric[0],
+                numeric[1],
+            );
+        }
+    }
+
+    // The features make it through the NdArray backend, and its autodiff glue.
+    #[test]
+    fn test_ndarray_features() -> Result<()> {
+        type DiffBack = Autodiff<NdArray>;
+        let device = NdArrayDevice::Cpu;
+
+        let (cpu_splats, features, cam) = feature_case();
+        let img_size = glam::uvec2(16, 16);
+        let splats = Splats::<DiffBack>::from_raw(
+            cpu_splats.means.clone(),
+            None,
+            Some(cpu_splats.log_scales.clone()),
+            Some(vec![0.0; 6]),
+            Some(cpu_splats.raw_opacities.clone()),
+            &device,
+        );
+        let features_tens =
+            Tensor::<DiffBack, 1>::from_floats(features.as_slice(), &device).reshape([2, 4]);
+        let features_tens = features_tens.require_grad();
+
+        let (out, _) = splats.render(&cam, img_size, Vec3::ZERO, false);
+        let (rgba, out_features, _) =
+            splats.render_features(&cam, img_size, Vec3::ZERO, features_tens.clone());
+        assert!(rgba.all_close(out, Some(1e-6), Some(1e-6)));
+
+        let cpu_out = render_features(&cpu_splats, &cam, img_size, 0, Vec3::ZERO, &features, 4);
+        let cpu_features = Tensor::<DiffBack, 1>::from_floats(cpu_out.features.as_slice(), &device)
+            .reshape([16, 16, 4]);
+        assert!(out_features
+            .clone()
+            .all_close(cpu_features, Some(1e-6), Some(1e-6)));
+
+        let grads = out_features.sum().backward();
+        let v_image = vec![Vec4::ZERO; cpu_out.image.len()];
+        let v_out_features = vec![1.0; cpu_out.features.len()];
+        let cpu_grads = cpu_out.backward(&cpu_splats, &v_image, None, Some(&v_out_features));
+
+        let v_features = features_tens.grad(&grads).context("features grad")?;
+        let v_features = v_features.into_data().to_vec::<f32>().expect("Wrong type");
+        assert_close("v_features", &v_features, &cpu_grads.v_features, 1e-5, 1e-6);
+
+        let v_opac = splats.raw_opacity.grad(&grads).context("opacities grad")?;
+        let v_opac = v_opac.into_data().to_vec::<f32>().expect("Wrong type");
+        assert_close(
+            "v_opacities",
+            &v_opac,
+            &cpu_grads.v_raw_opacities,
+            1e-5,
+            1e-6,
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_background() {
+        // A single splat in front of the camera.
+        let splats = CpuSplats {
+            means: vec![Vec3::ZERO],
+            rotations: vec![Vec4::new(1.0, 0.0, 0.0, 0.0)],
+            log_scales: vec![Vec3::splat(-1.0)],
+            sh_coeffs: vec![Vec3::ZERO],
+            coeffs_per_splat: 1,
+            raw_opacities: vec![0.0],
+        };
+        let cam = Camera::new(
+            vec3(0.0, 0.0, -5.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(32, 32);
+        let background = vec3(1.0, 0.0, 0.5);
+
+        let out = render(&splats, &cam, img_size, u32::MAX, background);
+        assert_eq!(out.num_visible(), 1);
+
+        // The corners aren't covered, the center is.
+        assert_eq!(out.image[0], background.extend(0.0));
+        let center = out.image[(16 + 16 * img_size.x) as usize];
+        assert!(center.w > 0.3);
+        let expected = Vec3::splat(0.5) * center.w + background * (1.0 - center.w);
+        assert!(center.xyz().abs_diff_eq(expected, 1e-5));
+    }
+}
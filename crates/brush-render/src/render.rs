@@ -50,16 +50,29 @@ pub(crate) fn render_forward(
     camera: &Camera,
     img_size: glam::UVec2,
     means: JitTensor<WgpuRuntime, f32>,
+    viewmat: JitTensor<WgpuRuntime, f32>,
     log_scales: JitTensor<WgpuRuntime, f32>,
     quats: JitTensor<WgpuRuntime, f32>,
     sh_coeffs: JitTensor<WgpuRuntime, f32>,
     raw_opacities: JitTensor<WgpuRuntime, f32>,
+    features: Option<JitTensor<WgpuRuntime, f32>>,
+    active_sh_degree: u32,
+    background: glam::Vec3,
     raster_u32: bool,
+    render_depth: bool,
 ) -> (JitTensor<WgpuRuntime, f32>, RenderAux<InnerWgpu>) {
     assert!(
         img_size[0] > 0 && img_size[1] > 0,
         "Can't render 0 sized images"
     );
+    assert!(
+        !(raster_u32 && render_depth),
+        "Can't render depth to a u32 buffer"
+    );
+    assert!(
+        !(raster_u32 && features.is_some()),
+        "Can't render features to a u32 buffer"
+    );
 
     let device = &means.device.clone();
     let client = means.client.clone();
@@ -70,12 +83,16 @@ pub(crate) fn render_forward(
     let _span = tracing::trace_span!("render_forward", sync_burn = true).entered();
 
     // Check whether dimesions are valid.
-    DimCheck::new()
+    let dim_check = DimCheck::new()
         .check_dims(&means, &["D".into(), 3.into()])
+        .check_dims(&viewmat, &[4.into(), 4.into()])
         .check_dims(&log_scales, &["D".into(), 3.into()])
         .check_dims(&quats, &["D".into(), 4.into()])
         .check_dims(&sh_coeffs, &["D".into(), "C".into(), 3.into()])
         .check_dims(&raw_opacities, &["D".into()]);
+    if let Some(features) = &features {
+        dim_check.check_dims(features, &["D".into(), "F".into()]);
+    }
 
     // Divide screen into tiles.
     let tile_bounds = uvec2(
@@ -96,11 +113,14 @@ pub(crate) fn render_forward(
     //  global_from_compact_gid.
 
     // Tile rendering setup.
-    let sh_degree = sh_degree_from_coeffs(sh_coeffs.shape.dims[1] as u32);
+    let coeffs_per_splat = sh_coeffs.shape.dims[1] as u32;
+    let sh_degree = sh_degree_from_coeffs(coeffs_per_splat).min(active_sh_degree);
     let total_splats = means.shape.dims[0] as u32;
+    let num_features = features.as_ref().map_or(0, |f| f.shape.dims[1]);
     let uniforms_buffer = create_uniform_buffer(
         shaders::helpers::RenderUniforms {
-            viewmat: camera.world_to_local().to_cols_array_2d(),
+            // Filled in from the viewmat tensor below.
+            viewmat: [[0.0; 4]; 4],
             focal: camera.focal(img_size).into(),
             pixel_center: camera.center(img_size).into(),
             img_size: img_size.into(),
@@ -108,12 +128,29 @@ pub(crate) fn render_forward(
             num_visible: 0,
             sh_degree,
             total_splats,
-            padding: 0,
+            coeffs_per_splat,
+            background: background.extend(0.0).into(),
+            num_features: num_features as u32,
+            pad_0: 0,
+            pad_1: 0,
+            pad_2: 0,
         },
         device,
         &client,
     );
 
+    // Copy the pose into the uniforms on the GPU, so a pose computed on the GPU (eg. with
+    // learned corrections) never has to be read back.
+    let viewmat_offset = offset_of!(shaders::helpers::RenderUniforms, viewmat) / 4;
+    let viewmat = InnerWgpu::float_reshape(viewmat, [16].into());
+    let viewmat_words =
+        JitTensor::new_contiguous(client.clone(), device.clone(), [16].into(), viewmat.handle);
+    let uniforms_buffer = InnerWgpu::int_slice_assign(
+        uniforms_buffer,
+        &[viewmat_offset..viewmat_offset + 16],
+        viewmat_words,
+    );
+
     let device = &means.device.clone();
 
     let num_points = means.shape.dims[0];
@@ -268,8 +305,11 @@ pub(crate) fn render_forward(
     let out_dim = if raster_u32 {
         // Channels are packed into 4 bytes aka one float.
         1
+    } else if render_depth {
+        // RGBA followed by the weighted and the median depth, and any features.
+        6 + num_features
     } else {
-        4
+        4 + num_features
     };
 
     let out_img = create_tensor(
@@ -289,17 +329,25 @@ pub(crate) fn render_forward(
         out_img.handle.clone().binding(),
     ];
 
-    // Record the final visible splat per tile.
+    // Record the final visible splat per tile, and how many splats contributed to each pixel.
     let final_index =
         create_tensor::<i32, 2, _>([img_size.y as usize, img_size.x as usize], device, client);
+    let num_contributing =
+        create_tensor::<i32, 2, _>([img_size.y as usize, img_size.x as usize], device, client);
 
     if !raster_u32 {
         handles.push(final_index.handle.clone().binding());
+        handles.push(num_contributing.handle.clone().binding());
+    }
+
+    if let Some(features) = &features {
+        handles.push(global_from_compact_gid.handle.clone().binding());
+        handles.push(features.handle.clone().binding());
     }
 
     unsafe {
         client.execute_unchecked(
-            Rasterize::task(raster_u32),
+            Rasterize::task(raster_u32, render_depth, features.is_some()),
             calc_cube_count([img_size.x, img_size.y], Rasterize::WORKGROUP_SIZE),
             handles,
         );
@@ -315,6 +363,7 @@ pub(crate) fn render_forward(
             cum_tiles_hit,
             projected_splats,
             final_index,
+            num_contributing,
             compact_gid_from_isect,
             global_from_compact_gid,
         },
@@ -326,6 +375,7 @@ pub(crate) fn render_backward(
     quats: JitTensor<WgpuRuntime, f32>,
     log_scales: JitTensor<WgpuRuntime, f32>,
     raw_opac: JitTensor<WgpuRuntime, f32>,
+    features: Option<JitTensor<WgpuRuntime, f32>>,
     out_img: JitTensor<WgpuRuntime, f32>,
     v_output: JitTensor<WgpuRuntime, f32>,
 
@@ -338,6 +388,7 @@ pub(crate) fn render_backward(
     final_index: JitTensor<WgpuRuntime, i32>,
 
     sh_degree: u32,
+    render_depth: bool,
 ) -> SplatGrads<InnerWgpu> {
     let device = &out_img.device;
     let img_dimgs = out_img.shape.dims;
@@ -347,7 +398,7 @@ pub(crate) fn render_backward(
 
     let client = &means.client;
 
-    let (v_xys_local, v_xys_global, v_conics, v_coeffs, v_raw_opac) = {
+    let (v_xys_local, v_xys_global, v_conics, v_depths, v_coeffs, v_raw_opac, v_features) = {
         let tile_bounds = uvec2(
             img_size.x.div_ceil(shaders::helpers::TILE_WIDTH),
             img_size.y.div_ceil(shaders::helpers::TILE_WIDTH),
@@ -356,9 +407,12 @@ pub(crate) fn render_backward(
         let invocations = tile_bounds.x * tile_bounds.y;
 
         // These gradients are atomically added to so important to zero them.
-        let v_xys_local = InnerWgpu::float_zeros([num_points, 2].into(), device);
+        // The xy gradients also hold the summed absolute gradients, see [`SplatGrads`].
+        let v_xys_local = InnerWgpu::float_zeros([num_points, 4].into(), device);
         let v_conics = InnerWgpu::float_zeros([num_points, 3].into(), device);
         let v_colors = InnerWgpu::float_zeros([num_points, 4].into(), device);
+        // Stays zero if depth isn't rendered.
+        let v_depths = InnerWgpu::float_zeros([num_points].into(), device);
 
         // TODO: Properly register hardware atomic floats as a cube feature when
         // https://github.com/gfx-rs/wgpu/pull/6234 lands.
@@ -366,22 +420,36 @@ pub(crate) fn render_backward(
         // On mac, this is needed as our wgpu version doesn't support CAS on metal yet...
         let hard_floats = cfg!(target_os = "macos");
 
+        let mut handles = vec![
+            uniforms_buffer.clone().handle.binding(),
+            compact_gid_from_isect.handle.binding(),
+            tile_bins.handle.binding(),
+            projected_splats.handle.binding(),
+            final_index.handle.binding(),
+            out_img.handle.binding(),
+            v_output.handle.binding(),
+            v_xys_local.clone().handle.binding(),
+            v_conics.clone().handle.binding(),
+            v_colors.clone().handle.binding(),
+        ];
+
+        if render_depth {
+            handles.push(v_depths.clone().handle.binding());
+        }
+
+        let v_features = features.map(|features| {
+            let v_features = InnerWgpu::float_zeros(features.shape.clone(), device);
+            handles.push(global_from_compact_gid.clone().handle.binding());
+            handles.push(features.handle.binding());
+            handles.push(v_features.clone().handle.binding());
+            v_features
+        });
+
         tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| unsafe {
             client.execute_unchecked(
-                RasterizeBackwards::task(hard_floats),
+                RasterizeBackwards::task(hard_floats, render_depth, v_features.is_some()),
                 CubeCount::Static(invocations, 1, 1),
-                vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    compact_gid_from_isect.handle.binding(),
-                    tile_bins.handle.binding(),
-                    projected_splats.handle.binding(),
-                    final_index.handle.binding(),
-                    out_img.handle.binding(),
-                    v_output.handle.binding(),
-                    v_xys_local.clone().handle.binding(),
-                    v_conics.clone().handle.binding(),
-                    v_colors.clone().handle.binding(),
-                ],
+                handles,
             );
         });
 
@@ -393,7 +461,7 @@ pub(crate) fn render_backward(
 
         let num_vis_wg = create_dispatch_buffer(num_visible.clone(), GatherGrads::WORKGROUP_SIZE);
 
-        let v_xys_global = InnerWgpu::float_zeros([num_points, 2].into(), device);
+        let v_xys_global = InnerWgpu::float_zeros([num_points, 4].into(), device);
         unsafe {
             client.execute_unchecked(
                 GatherGrads::task(),
@@ -412,7 +480,15 @@ pub(crate) fn render_backward(
             );
         }
 
-        (v_xys_local, v_xys_global, v_conics, v_coeffs, v_opacities)
+        (
+            v_xys_local,
+            v_xys_global,
+            v_conics,
+            v_depths,
+            v_coeffs,
+            v_opacities,
+            v_features,
+        )
     };
 
     // Create tensors to hold gradients.
@@ -422,6 +498,8 @@ pub(crate) fn render_backward(
     let v_means = InnerWgpu::float_zeros([num_points, 3].into(), device);
     let v_scales = InnerWgpu::float_zeros([num_points, 3].into(), device);
     let v_quats = InnerWgpu::float_zeros([num_points, 4].into(), device);
+    // Per splat contributions to the viewmat gradient, summed below.
+    let v_viewmats = InnerWgpu::float_zeros([num_points, 4, 3].into(), device);
 
     tracing::trace_span!("ProjectBackwards", sync_burn = true).in_scope(|| unsafe {
         client.execute_unchecked(
@@ -435,13 +513,24 @@ pub(crate) fn render_backward(
                 global_from_compact_gid.handle.binding(),
                 v_xys_local.handle.clone().binding(),
                 v_conics.handle.binding(),
+                v_depths.handle.binding(),
                 v_means.handle.clone().binding(),
                 v_scales.handle.clone().binding(),
                 v_quats.handle.clone().binding(),
+                v_viewmats.handle.clone().binding(),
             ],
         );
     });
 
+    // Sum the contributions of all splats, and add the (zero) bottom row to get
+    // the gradient of the full [4, 4] viewmat.
+    let v_viewmat =
+        InnerWgpu::float_reshape(InnerWgpu::float_sum_dim(v_viewmats, 0), [4, 3].into());
+    let v_viewmat = InnerWgpu::float_cat(
+        vec![v_viewmat, InnerWgpu::float_zeros([4, 1].into(), device)],
+        1,
+    );
+
     SplatGrads {
         v_means,
         v_quats,
@@ -449,6 +538,8 @@ pub(crate) fn render_backward(
         v_coeffs,
         v_raw_opac,
         v_xy: v_xys_global,
+        v_viewmat,
+        v_features,
     }
 }
 
@@ -460,6 +551,7 @@ mod tests {
     use crate::{
         camera::{focal_to_fov, fov_to_focal},
         gaussian_splats::Splats,
+        reference::{self, CpuSplats},
         safetensor_utils::safetensor_to_burn,
         Backend,
     };
@@ -479,6 +571,39 @@ mod tests {
 
     const USE_RERUN: bool = false;
 
+    // The splats of basic_case and its reference image, with a camera looking at them.
+    struct BasicCase {
+        splats: Splats<DiffBack>,
+        img_ref: Tensor<DiffBack, 3>,
+        cam: Camera,
+        viewmat: Tensor<DiffBack, 2>,
+    }
+
+    fn basic_case(device: &WgpuDevice) -> Result<BasicCase> {
+        let mut buffer = Vec::new();
+        let _ = File::open("./test_cases/basic_case.safetensors")?.read_to_end(&mut buffer)?;
+        let tensors = SafeTensors::deserialize(&buffer)?;
+        let splats = Splats::<DiffBack>::from_safetensors(&tensors, device)?;
+        let img_ref = safetensor_to_burn::<DiffBack, 3>(tensors.tensor("out_img")?, device);
+
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, -8.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let viewmat =
+            Tensor::<DiffBack, 2>::from_floats(cam.world_to_local().to_cols_array_2d(), device);
+
+        Ok(BasicCase {
+            splats,
+            img_ref,
+            cam,
+            viewmat,
+        })
+    }
+
     #[tokio::test]
     async fn renders_at_all() {
         // Check if rendering doesn't hard crash or anything.
@@ -495,7 +620,7 @@ mod tests {
         let device = WgpuDevice::DefaultDevice;
         let num_points = 8;
         let means = Tensor::<DiffBack, 2>::zeros([num_points, 3], &device);
-        let xy_dummy = Tensor::<DiffBack, 2>::zeros([num_points, 2], &device);
+        let xy_dummy = Tensor::<DiffBack, 2>::zeros([num_points, 4], &device);
         let log_scales = Tensor::<DiffBack, 2>::ones([num_points, 3], &device) * 2.0;
         let quats: Tensor<DiffBack, 2> =
             Tensor::<DiffBack, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
@@ -503,15 +628,22 @@ mod tests {
                 .repeat_dim(0, num_points);
         let sh_coeffs = Tensor::<DiffBack, 3>::ones([num_points, 1, 3], &device);
         let raw_opacity = Tensor::<DiffBack, 1>::zeros([num_points], &device);
+        let viewmat =
+            Tensor::<DiffBack, 2>::from_floats(cam.world_to_local().to_cols_array_2d(), &device);
         let (output, _) = DiffBack::render_splats(
             &cam,
             img_size,
             means.into_primitive().tensor(),
             xy_dummy.into_primitive().tensor(),
+            viewmat.into_primitive().tensor(),
             log_scales.into_primitive().tensor(),
             quats.into_primitive().tensor(),
             sh_coeffs.into_primitive().tensor(),
             raw_opacity.into_primitive().tensor(),
+            None,
+            u32::MAX,
+            glam::Vec3::ZERO,
+            false,
             false,
         );
 
@@ -588,7 +720,12 @@ mod tests {
                 glam::vec2(0.5, 0.5),
             );
 
-            let (out, aux) = splats.render(&cam, glam::uvec2(w as u32, h as u32), false);
+            let (out, aux) = splats.render(
+                &cam,
+                glam::uvec2(w as u32, h as u32),
+                glam::Vec3::ZERO,
+                false,
+            );
 
             if let Some(rec) = rec.as_ref() {
                 rec.set_time_sequence("test case", i as i64);
@@ -636,6 +773,7 @@ mod tests {
             let v_xys_ref =
                 safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_xy")?, &device).inner();
             let v_xys = splats.xys_dummy.grad(&grads).context("no xys grad")?;
+            let v_xys = v_xys.slice([0..splats.num_splats(), 0..2]);
 
             let v_xys_data = v_xys.to_data().to_vec::<f32>().unwrap();
             let v_xys_ref_data = v_xys_ref.to_data().to_vec::<f32>().unwrap();
@@ -674,6 +812,406 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_render_depth() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase {
+            splats,
+            img_ref,
+            cam,
+            viewmat,
+        } = basic_case(&device)?;
+
+        let [h, w, _] = img_ref.dims();
+        let img_size = glam::uvec2(w as u32, h as u32);
+
+        let (out, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let (out_depth, _) = splats.render_with_viewmat(
+            &cam,
+            viewmat,
+            img_size,
+            u32::MAX,
+            glam::Vec3::ZERO,
+            false,
+            true,
+        );
+        assert_eq!(out_depth.dims(), [h, w, 6]);
+
+        // Rendering depth shouldn't change the colors.
+        let rgba = out_depth.clone().slice([0..h, 0..w, 0..4]);
+        assert!(rgba.all_close(out, Some(1e-5), Some(1e-6)));
+
+        // The expected depth of covered pixels lies in front of the camera.
+        let alpha = out_depth.clone().slice([0..h, 0..w, 3..4]);
+        let depth = out_depth.slice([0..h, 0..w, 4..5]);
+        let covered = alpha.clone().greater_elem(0.5);
+        let expected = depth.clone() / alpha.clamp_min(1e-6);
+        let min_depth = expected
+            .mask_fill(covered.bool_not(), f32::MAX)
+            .min()
+            .into_scalar();
+        assert!(min_depth > 0.0);
+
+        // A depth only loss should still give the splats a gradient.
+        let grads = depth.mean().backward();
+        let v_means = splats.means.grad(&grads).context("means grad")?;
+        let v_means_norm = v_means.abs().sum().into_scalar();
+        assert!(v_means_norm > 0.0);
+
+        // The median depth is set exactly where the transmittance drops below 0.5.
+        let (_, expected_depth, median_depth, _) =
+            splats.render_depth(&cam, img_size, glam::Vec3::ZERO);
+        assert_eq!(median_depth.dims(), [h, w]);
+        let covered = covered.squeeze::<2>(2);
+        let median_set = median_depth.clone().greater_elem(0.0);
+        assert!(median_set.equal(covered.clone()).all().into_scalar());
+
+        // Both depths lie within the depth range of the splats.
+        let means_z = splats.means.val().slice([0..splats.num_splats(), 2..3]) + 8.0;
+        let [min_z, max_z] = [means_z.clone().min(), means_z.max()].map(|z| z.into_scalar());
+        for depth in [expected_depth, median_depth] {
+            let depth = depth.mask_fill(covered.clone().bool_not(), min_z);
+            assert!(depth.clone().min().into_scalar() >= min_z - 1e-3);
+            assert!(depth.max().into_scalar() <= max_z + 1e-3);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_active_sh_degree() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase {
+            splats,
+            cam,
+            viewmat,
+            ..
+        } = basic_case(&device)?;
+        let [n, c, _] = splats.sh_coeffs.dims();
+        assert!(c > 4);
+
+        // The same splats, with only the first two SH bands stored.
+        let truncated = Splats::from_tensor_data(
+            splats.means.val(),
+            splats.rotation.val(),
+            splats.log_scales.val(),
+            splats.sh_coeffs.val().slice([0..n, 0..4]),
+            splats.raw_opacity.val(),
+        );
+
+        let img_size = glam::uvec2(64, 64);
+
+        let (out, _) =
+            splats.render_with_viewmat(&cam, viewmat, img_size, 1, glam::Vec3::ZERO, false, false);
+        let (out_ref, _) = truncated.render(&cam, img_size, glam::Vec3::ZERO, false);
+        assert!(out.clone().all_close(out_ref, Some(1e-5), Some(1e-6)));
+
+        // The inactive bands shouldn't get a gradient.
+        let grads = out.mean().backward();
+        let v_coeffs = splats.sh_coeffs.grad(&grads).context("coeffs grad")?;
+        let v_inactive = v_coeffs.slice([0..n, 4..c]).abs().sum().into_scalar();
+        assert_eq!(v_inactive, 0.0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_render_features() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase { splats, cam, .. } = basic_case(&device)?;
+        let n = splats.num_splats();
+
+        // The same splats, with only a constant colour.
+        let splats = Splats::from_tensor_data(
+            splats.means.val(),
+            splats.rotation.val(),
+            splats.log_scales.val(),
+            splats.sh_coeffs.val().slice([0..n, 0..1]),
+            splats.raw_opacity.val(),
+        );
+
+        let img_size = glam::uvec2(64, 64);
+        let (out, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+
+        // Features of the splat colours, followed by a constant 1, should give back the image.
+        // A fifth channel checks the feature count doesn't have to match the colours.
+        let colors = splats.sh_coeffs.val().reshape([n, 3]) * SH_C0 + 0.5;
+        let extra = splats.means.val().slice([0..n, 0..1]);
+        let features =
+            Tensor::cat(vec![colors, Tensor::ones([n, 1], &device), extra], 1).require_grad();
+        let (rgba, out_features, _) =
+            splats.render_features(&cam, img_size, glam::Vec3::ZERO, features.clone());
+        assert_eq!(out_features.dims(), [64, 64, 5]);
+        assert!(rgba.all_close(out.clone(), Some(1e-5), Some(1e-6)));
+        let composited = out_features.clone().slice([0..64, 0..64, 0..4]);
+        assert!(composited.all_close(out, Some(1e-4), Some(1e-5)));
+
+        let grads = out_features.clone().powi_scalar(2.0).mean().backward();
+        let v_features = features.grad(&grads).context("features grad")?;
+        let v_means = splats.means.grad(&grads).context("means grad")?;
+        let v_opac = splats.raw_opacity.grad(&grads).context("opacity grad")?;
+
+        // Compare the features and their gradients to the CPU reference.
+        let cpu_splats = CpuSplats::from_splats(&splats).await;
+        let feature_values = features
+            .clone()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("Wrong type");
+        let cpu_out = reference::render_features(
+            &cpu_splats,
+            &cam,
+            img_size,
+            u32::MAX,
+            glam::Vec3::ZERO,
+            &feature_values,
+            5,
+        );
+
+        let flat =
+            |values: Vec<f32>| Tensor::<DiffBack, 1>::from_floats(values.as_slice(), &device);
+        let cpu_features = flat(cpu_out.features.clone()).reshape([64, 64, 5]);
+        assert!(out_features.all_close(cpu_features, Some(1e-4), Some(1e-5)));
+
+        // Gradient of the same loss for the CPU render.
+        let scale = 2.0 / cpu_out.features.len() as f32;
+        let v_out_features: Vec<_> = cpu_out.features.iter().map(|f| f * scale).collect();
+        let v_image = vec![glam::Vec4::ZERO; cpu_out.image.len()];
+        let cpu_grads = cpu_out.backward(&cpu_splats, &v_image, None, Some(&v_out_features));
+
+        let v_features_cpu = flat(cpu_grads.v_features).inner().reshape([n, 5]);
+        assert!(v_features.all_close(v_features_cpu, Some(1e-3), Some(1e-8)));
+
+        let flatten3 = |v: &[glam::Vec3]| flat(v.iter().flat_map(|v| v.to_array()).collect());
+        let v_means_cpu = flatten3(&cpu_grads.v_means).inner().reshape([n, 3]);
+        assert!(v_means.all_close(v_means_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_opac_cpu = flat(cpu_grads.v_raw_opacities).inner();
+        assert!(v_opac.all_close(v_opac_cpu, Some(1e-3), Some(1e-8)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_render_background() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase { splats, cam, .. } = basic_case(&device)?;
+
+        let img_size = glam::uvec2(64, 64);
+        let background = glam::vec3(0.2, 0.5, 1.0);
+
+        let (out, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let (out_bg, _) = splats.render(&cam, img_size, background, false);
+
+        // Compositing in the kernel should match compositing the render over the background
+        // afterwards, including the gradients.
+        let bg =
+            Tensor::<DiffBack, 1>::from_floats(background.to_array(), &device).reshape([1, 1, 3]);
+        let alpha = out.clone().slice([0..64, 0..64, 3..4]);
+        let expected = out.slice([0..64, 0..64, 0..3]) + (alpha.clone().neg() + 1.0) * bg;
+        let rgb = out_bg.clone().slice([0..64, 0..64, 0..3]);
+        assert!(rgb
+            .clone()
+            .all_close(expected.clone(), Some(1e-5), Some(1e-6)));
+        assert!(out_bg
+            .slice([0..64, 0..64, 3..4])
+            .all_close(alpha, Some(1e-5), Some(1e-6)));
+
+        let grads = (rgb - 0.5).powi_scalar(2.0).mean().backward();
+        let v_opac = splats.raw_opacity.grad(&grads).context("opacity grad")?;
+        let grads_ref = (expected - 0.5).powi_scalar(2.0).mean().backward();
+        let v_opac_ref = splats
+            .raw_opacity
+            .grad(&grads_ref)
+            .context("opacity grad")?;
+        assert!(v_opac.all_close(v_opac_ref, Some(1e-4), Some(1e-9)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pixel_depth() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase { splats, cam, .. } = basic_case(&device)?;
+
+        let img_size = glam::uvec2(64, 64);
+        let (out, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+
+        // Every covered pixel has at least one contributing splat, and empty pixels none.
+        let covered = out
+            .slice([0..64, 0..64, 3..4])
+            .squeeze::<2>(2)
+            .greater_elem(0.0);
+        let contributing = aux.read_pixel_depth().greater_elem(0);
+        assert!(contributing.equal(covered).all().into_scalar());
+
+        // Splats can only contribute to the pixels of tiles they touch.
+        let excess = aux.read_pixel_depth() - aux.read_pixel_tile_depth();
+        assert!(excess.lower_equal_elem(0).all().into_scalar());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_viewmat_grads() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase {
+            splats,
+            img_ref,
+            cam,
+            viewmat,
+        } = basic_case(&device)?;
+
+        let [h, w, _] = img_ref.dims();
+
+        let viewmat = viewmat.require_grad();
+
+        let (out, _) = splats.render_with_viewmat(
+            &cam,
+            viewmat.clone(),
+            glam::uvec2(w as u32, h as u32),
+            u32::MAX,
+            glam::Vec3::ZERO,
+            false,
+            false,
+        );
+        let grads = (out - img_ref).powi_scalar(2.0).mean().backward();
+
+        // Moving the camera is the same as moving all splats the opposite way, so with an
+        // identity rotation the translation gradient is the sum of the mean gradients.
+        let v_viewmat = viewmat.grad(&grads).context("no viewmat grad")?;
+        let v_translation = v_viewmat.slice([3..4, 0..3]).reshape([3]);
+        let v_means = splats.means.grad(&grads).context("means grad")?;
+        let v_means_sum = v_means.sum_dim(0).reshape([3]);
+        assert!(v_translation.all_close(v_means_sum, Some(1e-3), Some(1e-6)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_viewmat_pose() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase { splats, cam, .. } = basic_case(&device)?;
+
+        let moved = Camera::new(
+            glam::vec3(0.5, -0.3, -7.5),
+            glam::Quat::from_rotation_y(0.1),
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 64);
+
+        // The pose comes from the viewmat, only the intrinsics of the camera are used.
+        let viewmat =
+            Tensor::<DiffBack, 2>::from_floats(moved.world_to_local().to_cols_array_2d(), &device);
+        let (out, _) = splats.render_with_viewmat(
+            &cam,
+            viewmat,
+            img_size,
+            u32::MAX,
+            glam::Vec3::ZERO,
+            false,
+            false,
+        );
+        let (out_ref, _) = splats.render(&moved, img_size, glam::Vec3::ZERO, false);
+        assert!(out.all_close(out_ref, Some(1e-5), Some(1e-6)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cpu_reference() -> Result<()> {
+        let device = WgpuDevice::DefaultDevice;
+        let BasicCase {
+            splats,
+            img_ref,
+            cam,
+            viewmat,
+        } = basic_case(&device)?;
+
+        let [h, w, _] = img_ref.dims();
+        let img_size = glam::uvec2(w as u32, h as u32);
+
+        let background = glam::vec3(0.2, 0.6, 1.0);
+        let viewmat = viewmat.require_grad();
+
+        let (out, aux) = splats.render_with_viewmat(
+            &cam,
+            viewmat.clone(),
+            img_size,
+            u32::MAX,
+            background,
+            false,
+            true,
+        );
+
+        let cpu_splats = CpuSplats::from_splats(&splats).await;
+        let cpu_out = reference::render(&cpu_splats, &cam, img_size, u32::MAX, background);
+        assert_eq!(cpu_out.num_visible(), aux.read_num_visible().await as usize);
+
+        let flat =
+            |values: Vec<f32>| Tensor::<DiffBack, 1>::from_floats(values.as_slice(), &device);
+        let cpu_image = cpu_out
+            .image
+            .iter()
+            .zip(cpu_out.depth.iter().zip(&cpu_out.median_depth))
+            .flat_map(|(c, (&d, &m))| [c.x, c.y, c.z, c.w, d, m])
+            .collect::<Vec<_>>();
+        let cpu_image_tens = flat(cpu_image.clone()).reshape([h, w, 6]);
+        assert!(out
+            .clone()
+            .all_close(cpu_image_tens, Some(1e-4), Some(1e-5)));
+
+        let grads = out.powi_scalar(2.0).mean().backward();
+
+        // Gradient of the same loss for the CPU render.
+        let scale = 2.0 / cpu_image.len() as f32;
+        let v_image: Vec<_> = cpu_out.image.iter().map(|c| *c * scale).collect();
+        let v_depth: Vec<_> = cpu_out.depth.iter().map(|d| d * scale).collect();
+        let cpu_grads = cpu_out.backward(&cpu_splats, &v_image, Some(&v_depth), None);
+
+        let flatten3 = |v: &[glam::Vec3]| flat(v.iter().flat_map(|v| v.to_array()).collect());
+        let flatten4 = |v: &[glam::Vec4]| flat(v.iter().flat_map(|v| v.to_array()).collect());
+
+        let v_means = splats.means.grad(&grads).context("means grad")?;
+        let v_means_cpu = flatten3(&cpu_grads.v_means).inner().reshape(v_means.dims());
+        assert!(v_means.all_close(v_means_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_quats = splats.rotation.grad(&grads).context("quats grad")?;
+        let v_quats_cpu = flatten4(&cpu_grads.v_rotations)
+            .inner()
+            .reshape(v_quats.dims());
+        assert!(v_quats.all_close(v_quats_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_scales = splats.log_scales.grad(&grads).context("scales grad")?;
+        let v_scales_cpu = flatten3(&cpu_grads.v_log_scales)
+            .inner()
+            .reshape(v_scales.dims());
+        assert!(v_scales.all_close(v_scales_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_coeffs = splats.sh_coeffs.grad(&grads).context("coeffs grad")?;
+        let v_coeffs_cpu = flatten3(&cpu_grads.v_sh_coeffs)
+            .inner()
+            .reshape(v_coeffs.dims());
+        assert!(v_coeffs.all_close(v_coeffs_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_opac = splats.raw_opacity.grad(&grads).context("opacities grad")?;
+        let v_opac_cpu = flat(cpu_grads.v_raw_opacities.clone()).inner();
+        assert!(v_opac.all_close(v_opac_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_xys = splats.xys_dummy.grad(&grads).context("no xys grad")?;
+        let v_xys_cpu = flatten4(&cpu_grads.v_xy).inner().reshape(v_xys.dims());
+        assert!(v_xys.all_close(v_xys_cpu, Some(1e-3), Some(1e-8)));
+
+        let v_viewmat = viewmat.grad(&grads).context("no viewmat grad")?;
+        let v_viewmat_cpu =
+            Tensor::<Wgpu, 2>::from_floats(cpu_grads.v_viewmat.to_cols_array_2d(), &device);
+        assert!(v_viewmat.all_close(v_viewmat_cpu, Some(1e-3), Some(1e-6)));
+
+        Ok(())
+    }
+
     // #[test]
     // fn test_mean_grads() {
     //     let cam = Camera::new(glam::vec3(0.0, 0.0, -5.0), glam::Quat::IDENTITY, 0.5, 0.5);
@@ -6,11 +6,11 @@
 @group(0) @binding(2) var<storage, read> raw_opacities: array<f32>;
 @group(0) @binding(3) var<storage, read> means: array<helpers::PackedVec3>;
 @group(0) @binding(4) var<storage, read> v_colors: array<vec4f>;
-@group(0) @binding(5) var<storage, read> v_xy_local: array<vec2f>;
+@group(0) @binding(5) var<storage, read> v_xy_local: array<vec4f>;
 
 @group(0) @binding(6) var<storage, read_write> v_coeffs: array<f32>;
 @group(0) @binding(7) var<storage, read_write> v_opacs: array<f32>;
-@group(0) @binding(8) var<storage, read_write> v_xy_global: array<vec2f>;
+@group(0) @binding(8) var<storage, read_write> v_xy_global: array<vec4f>;
 
 const SH_C0: f32 = 0.2820947917738781f;
 
@@ -143,10 +143,6 @@ struct ShCoeffs {
     b4_c8: vec3f,
 }
 
-fn num_sh_coeffs(degree: u32) -> u32 {
-    return (degree + 1) * (degree + 1);
-}
-
 fn write_coeffs(base_id: ptr<function, u32>, val: vec3f) {
     v_coeffs[*base_id + 0] = val.x;
     v_coeffs[*base_id + 1] = val.y;
@@ -184,8 +180,8 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
 
     let sh_degree = uniforms.sh_degree;
     let v_coeff = sh_coeffs_to_color_fast_vjp(sh_degree, viewdir, v_color.xyz);
-    let num_coeffs = num_sh_coeffs(sh_degree);
-    var base_id = global_gid * num_coeffs * 3;
+    // Coefficients of bands above the active degree are left at zero.
+    var base_id = global_gid * uniforms.coeffs_per_splat * 3;
 
     write_coeffs(&base_id, v_coeff.b0_c0);
     if sh_degree > 0 {
@@ -14,7 +14,8 @@ struct RenderUniforms {
     tile_bounds: vec2u,
     // Camera center (cx, cy).
     pixel_center: vec2f,
-    // Degree of sh coeffecients used.
+    // Degree of sh coeffecients used. This can be lower than the degree of
+    // the stored coefficients.
     sh_degree: u32,
 #ifdef UNIFORM_WRITE
     // Number of visible gaussians, written by project_forward.
@@ -26,7 +27,17 @@ struct RenderUniforms {
     num_visible: u32,
 #endif
     total_splats: u32,
-    padding: u32,
+    // Number of sh coefficients stored per splat.
+    coeffs_per_splat: u32,
+    // Colour composited behind the splats. The alpha is unused, the output alpha
+    // is always the coverage of the splats.
+    background: vec4f,
+    // Number of per splat feature channels composited after the colours (and depths).
+    num_features: u32,
+    // Keeps the struct a multiple of 16 bytes without implicit padding.
+    pad_0: u32,
+    pad_1: u32,
+    pad_2: u32,
 }
 
 // nb: this struct has a bunch of padding but that's probably fine.
@@ -40,10 +51,12 @@ struct ProjectedSplat {
     color_g: f32,
     color_b: f32,
     color_a: f32,
+    // Camera space depth.
+    depth: f32,
 }
 
-fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f) -> ProjectedSplat {
-    return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a);
+fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f, depth: f32) -> ProjectedSplat {
+    return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a, depth);
 }
 
 struct PackedVec3 {
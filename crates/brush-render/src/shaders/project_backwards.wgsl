@@ -9,12 +9,17 @@
 
 @group(0) @binding(4) var<storage, read> global_from_compact_gid: array<u32>;
 
-@group(0) @binding(5) var<storage, read> v_xys: array<vec2f>;
+@group(0) @binding(5) var<storage, read> v_xys: array<vec4f>;
 @group(0) @binding(6) var<storage, read> v_conics: array<helpers::PackedVec3>;
+// Gradient of the camera space depth of each splat. All zeros when depth isn't rendered.
+@group(0) @binding(7) var<storage, read> v_depths: array<f32>;
 
-@group(0) @binding(7) var<storage, read_write> v_means: array<helpers::PackedVec3>;
-@group(0) @binding(8) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
-@group(0) @binding(9) var<storage, read_write> v_quats: array<vec4f>;
+@group(0) @binding(8) var<storage, read_write> v_means: array<helpers::PackedVec3>;
+@group(0) @binding(9) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
+@group(0) @binding(10) var<storage, read_write> v_quats: array<vec4f>;
+// Per splat contribution to the gradient of the viewmat. Holds the 3 columns of the rotation
+// followed by the translation, for each compact gid.
+@group(0) @binding(11) var<storage, read_write> v_viewmats: array<helpers::PackedVec3>;
 
 
 // TODO: Deal with unnomralized quats.
@@ -58,12 +63,9 @@ fn inverse_vjp(Minv: mat2x2f, v_Minv: mat2x2f) -> mat2x2f {
     return mat2x2f(-Minv[0], -Minv[1]) * v_Minv * Minv;
 }
 
+// Returns a * b^T.
 fn outer_product(a: vec3<f32>, b: vec3<f32>) -> mat3x3<f32> {
-    return mat3x3f(
-        a.x * b.x, a.x * b.y, a.x * b.z,
-        a.y * b.x, a.y * b.y, a.y * b.z,
-        a.z * b.x, a.z * b.y, a.z * b.z
-    );
+    return mat3x3f(a * b.x, a * b.y, a * b.z);
 }
 
 fn persp_proj_vjp(
@@ -130,10 +132,6 @@ fn persp_proj_vjp(
                   2.f * focal.x * tx * rz3 * v_J[2][0] +
                   2.f * focal.y * ty * rz3 * v_J[2][1];
 
-    // add contribution from v_depths
-    // Disabled as there is no depth supervision currently.
-    // v_mean3d.z += v_depths[0];
-
     return v_mean3d;
 }
 
@@ -156,7 +154,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let quat = quats[global_gid];
 
     let v_conics = helpers::as_vec(v_conics[compact_gid]);
-    let v_mean2d = v_xys[compact_gid];
+    let v_mean2d = v_xys[compact_gid].xy;
 
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
@@ -181,7 +179,9 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
 
     // persp_proj_vjp
     let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center);
-    let v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    var v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    // Add the contribution of the depth.
+    v_mean_c.z += v_depths[compact_gid];
     // cov = J * V * Jt; G = df/dcov = v_cov
     // -> df/dV = Jt * G * J
     // -> df/dJ = G * J * Vt + Gt * J * V
@@ -194,14 +194,12 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // for D = W * X, G = df/dD
     // df/dW = G * XT, df/dX = WT * G
 
-    // TODO: Camera gradient is not done yet.
-    // var v_R = outer_product(v_mean_c, mean);
+    var v_R = outer_product(v_mean_c, mean);
     let v_mean = transpose(R) * v_mean_c;
 
     // covar_world_to_cam_vjp
-    // TODO: Camera gradient is not done yet.
-    // v_R += v_covar_c * R * transpose(covar) +
-    //        transpose(v_covar_c) * R * covar;
+    v_R += v_covar_c * R * transpose(covar) +
+           transpose(v_covar_c) * R * covar;
 
     let v_covar = transpose(R) * v_covar_c * R;
 
@@ -229,4 +227,10 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     v_means[global_gid] = helpers::as_packed(v_mean);
     v_scales[global_gid] = helpers::as_packed(v_scale_exp);
     v_quats[global_gid] = v_quat;
+
+    // The viewmat gradient. This ignores the view direction of the SH colors.
+    v_viewmats[compact_gid * 4 + 0] = helpers::as_packed(v_R[0]);
+    v_viewmats[compact_gid * 4 + 1] = helpers::as_packed(v_R[1]);
+    v_viewmats[compact_gid * 4 + 2] = helpers::as_packed(v_R[2]);
+    v_viewmats[compact_gid * 4 + 3] = helpers::as_packed(v_mean_c);
 }
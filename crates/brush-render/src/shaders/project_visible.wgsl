@@ -150,10 +150,6 @@ fn sigmoid(x: f32) -> f32 {
     return 1.0 / (1.0 + exp(-x));
 }
 
-fn num_sh_coeffs(degree: u32) -> u32 {
-    return (degree + 1) * (degree + 1);
-}
-
 fn read_coeffs(base_id: ptr<function, u32>) -> vec3f {
     let ret = helpers::as_vec(coeffs[*base_id]);
     *base_id += 1u;
@@ -190,8 +186,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let mean2d = uniforms.focal * mean_c.xy * rz + uniforms.pixel_center;
 
     let sh_degree = uniforms.sh_degree;
-    let num_coeffs = num_sh_coeffs(sh_degree);
-    var base_id = global_gid * num_coeffs;
+    var base_id = global_gid * uniforms.coeffs_per_splat;
 
     var sh = ShCoeffs();
     sh.b0_c0 = read_coeffs(&base_id);
@@ -256,7 +251,8 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     projected[compact_gid] = helpers::create_projected_splat(
         mean2d,
         conic,
-        vec4f(color, opac)
+        vec4f(color, opac),
+        mean_c.z
     );
     num_tiles_hit[compact_gid] = u32(tile_area);
 }
@@ -8,11 +8,32 @@
 #ifdef RASTER_U32
     @group(0) @binding(4) var<storage, read_write> out_img: array<u32>;
 #else
-    @group(0) @binding(4) var<storage, read_write> out_img: array<vec4f>;
+    @group(0) @binding(4) var<storage, read_write> out_img: array<f32>;
     @group(0) @binding(5) var<storage, read_write> final_index : array<u32>;
+    // The number of splats which contributed to each pixel.
+    @group(0) @binding(6) var<storage, read_write> num_contributing : array<u32>;
+#endif
+
+#ifdef RENDER_FEATURES
+    @group(0) @binding(7) var<storage, read> global_from_compact_gid: array<u32>;
+    // [N, num_features] features of each splat, composited like the colours but without
+    // a background.
+    @group(0) @binding(8) var<storage, read> features: array<f32>;
+#endif
+
+#ifdef RENDER_DEPTH
+    // RGBA, followed by the depth weighted by the contribution of each splat. Dividing
+    // this by the alpha of the pixel gives the expected depth. The last channel is the
+    // median depth, the depth of the splat where the transmittance drops below 0.5.
+    const BASE_CHANNELS: u32 = 6u;
+#else
+    const BASE_CHANNELS: u32 = 4u;
 #endif
 
 var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
+#ifdef RENDER_FEATURES
+    var<workgroup> local_gid: array<u32, helpers::TILE_SIZE>;
+#endif
 
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
@@ -45,12 +66,26 @@ fn main(
     var T = 1.0;
 
     var pix_out = vec3f(0.0);
+    var depth_out = 0.0;
+    var median_depth = 0.0;
+
+    // Any features are written after the other channels, and accumulated in place.
+    let channels = BASE_CHANNELS + uniforms.num_features;
+    let feature_base = pix_id * channels + BASE_CHANNELS;
+#ifdef RENDER_FEATURES
+    if inside {
+        for (var c = 0u; c < uniforms.num_features; c++) {
+            out_img[feature_base + c] = 0.0;
+        }
+    }
+#endif
 
     // collect and process batches of gaussians
     // each thread loads one gaussian at a time before rasterizing its
     // designated pixel
     var t = 0u;
     var final_idx = 0u;
+    var contributing = 0u;
 
     // each thread loads one gaussian at a time before rasterizing its
     // designated pixel
@@ -65,7 +100,11 @@ fn main(
 
         if local_idx < remaining {
             let load_isect_id = batch_start + local_idx;
-            local_batch[local_idx] = projected_splats[compact_gid_from_isect[load_isect_id]];
+            let load_compact_gid = compact_gid_from_isect[load_isect_id];
+            local_batch[local_idx] = projected_splats[load_compact_gid];
+            #ifdef RENDER_FEATURES
+                local_gid[local_idx] = global_from_compact_gid[load_compact_gid];
+            #endif
         }
         // Wait for all writes to complete.
         workgroupBarrier();
@@ -92,24 +131,47 @@ fn main(
 
                 let fac = alpha * T;
                 pix_out += vec3f(color.r, color.g, color.b) * fac;
+                depth_out += projected.depth * fac;
+
+                #ifdef RENDER_FEATURES
+                    let feature_start = local_gid[t] * uniforms.num_features;
+                    for (var c = 0u; c < uniforms.num_features; c++) {
+                        out_img[feature_base + c] += features[feature_start + c] * fac;
+                    }
+                #endif
+
+                if T > 0.5 && next_T <= 0.5 {
+                    median_depth = projected.depth;
+                }
+
                 T = next_T;
 
                 let isect_id = batch_start + t;
                 final_idx = isect_id;
+                contributing += 1u;
             }
         }
     }
 
     if inside {
         let img_alpha = (1.0 - T);
-        let final_color = vec4f(pix_out, img_alpha);
+        let final_color = vec4f(pix_out + T * uniforms.background.rgb, img_alpha);
         #ifdef RASTER_U32
             let colors_u = vec4u(clamp(final_color * 255.0, vec4f(0.0), vec4f(255.0)));
             let packed: u32 = colors_u.x | (colors_u.y << 8u) | (colors_u.z << 16u) | (colors_u.w << 24u);
             out_img[pix_id] = packed;
         #else
-            out_img[pix_id] = final_color;
+            let base = pix_id * channels;
+            out_img[base + 0] = final_color.r;
+            out_img[base + 1] = final_color.g;
+            out_img[base + 2] = final_color.b;
+            out_img[base + 3] = final_color.a;
+            #ifdef RENDER_DEPTH
+                out_img[base + 4] = depth_out;
+                out_img[base + 5] = median_depth;
+            #endif
             final_index[pix_id] = final_idx;
+            num_contributing[pix_id] = contributing;
         #endif
     }
 }
@@ -8,9 +8,11 @@
 @group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
 
 @group(0) @binding(4) var<storage, read> final_index: array<u32>;
-@group(0) @binding(5) var<storage, read> output: array<vec4f>;
-@group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
+@group(0) @binding(5) var<storage, read> output: array<f32>;
+@group(0) @binding(6) var<storage, read> v_output: array<f32>;
 
+// v_xy holds 4 floats per splat: the xy gradient, followed by the sum of the
+// absolute per-pixel xy gradients.
 #ifdef HARD_FLOAT
     @group(0) @binding(7) var<storage, read_write> v_xy: array<atomic<f32>>;
     @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<f32>>;
@@ -21,6 +23,43 @@
     @group(0) @binding(9) var<storage, read_write> v_colors: array<atomic<u32>>;
 #endif
 
+#ifdef RENDER_DEPTH
+    // The output has extra weighted depth and median depth channels, see rasterize.wgsl.
+    // The median depth doesn't have a gradient.
+    const BASE_CHANNELS: u32 = 6u;
+
+    #ifdef HARD_FLOAT
+        @group(0) @binding(10) var<storage, read_write> v_depths: array<atomic<f32>>;
+    #else
+        @group(0) @binding(10) var<storage, read_write> v_depths: array<atomic<u32>>;
+    #endif
+#else
+    const BASE_CHANNELS: u32 = 4u;
+#endif
+
+// The feature bindings follow the depth gradients, if there are any.
+#ifdef RENDER_FEATURES
+    #ifdef RENDER_DEPTH
+        @group(0) @binding(11) var<storage, read> global_from_compact_gid: array<u32>;
+        @group(0) @binding(12) var<storage, read> features: array<f32>;
+        #ifdef HARD_FLOAT
+            @group(0) @binding(13) var<storage, read_write> v_features: array<atomic<f32>>;
+        #else
+            @group(0) @binding(13) var<storage, read_write> v_features: array<atomic<u32>>;
+        #endif
+    #else
+        @group(0) @binding(10) var<storage, read> global_from_compact_gid: array<u32>;
+        @group(0) @binding(11) var<storage, read> features: array<f32>;
+        #ifdef HARD_FLOAT
+            @group(0) @binding(12) var<storage, read_write> v_features: array<atomic<f32>>;
+        #else
+            @group(0) @binding(12) var<storage, read_write> v_features: array<atomic<u32>>;
+        #endif
+    #endif
+
+    var<workgroup> local_gid: array<u32, helpers::TILE_SIZE>;
+#endif
+
 
 const MIN_WG_SIZE: u32 = 8u;
 const BATCH_SIZE = helpers::TILE_SIZE;
@@ -38,16 +77,19 @@ var<workgroup> local_id: array<u32, BATCH_SIZE>;
 // Current queue of gradients to be flushed.
 var<workgroup> grad_count: atomic<i32>;
 var<workgroup> gather_grads: array<helpers::ProjectedSplat, BATCH_SIZE>;
+var<workgroup> gather_grad_abs: array<vec2f, BATCH_SIZE>;
 var<workgroup> gather_grad_id: array<u32, BATCH_SIZE>;
 
 fn add_bitcast(cur: u32, add: f32) -> u32 {
     return bitcast<u32>(bitcast<f32>(cur) + add);
 }
 
-fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
+fn write_grads_atomic(grads: helpers::ProjectedSplat, xy_abs: vec2f, id: u32) {
 #ifdef HARD_FLOAT
-    atomicAdd(&v_xy[id * 2 + 0], grads.xy_x);
-    atomicAdd(&v_xy[id * 2 + 1], grads.xy_y);
+    atomicAdd(&v_xy[id * 4 + 0], grads.xy_x);
+    atomicAdd(&v_xy[id * 4 + 1], grads.xy_y);
+    atomicAdd(&v_xy[id * 4 + 2], xy_abs.x);
+    atomicAdd(&v_xy[id * 4 + 3], xy_abs.y);
 
     atomicAdd(&v_conics[id * 3 + 0], grads.conic_x);
     atomicAdd(&v_conics[id * 3 + 1], grads.conic_y);
@@ -57,10 +99,14 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
     atomicAdd(&v_colors[id * 4 + 1], grads.color_g);
     atomicAdd(&v_colors[id * 4 + 2], grads.color_b);
     atomicAdd(&v_colors[id * 4 + 3], grads.color_a);
+
+    #ifdef RENDER_DEPTH
+        atomicAdd(&v_depths[id], grads.depth);
+    #endif
 #else
     // Alternatively can run without any atomics and just race:
-    // v_xy[id * 2 + 0] = add_bitcast(v_xy[id * 2 + 0], grads.xy.x);
-    // v_xy[id * 2 + 1] = add_bitcast(v_xy[id * 2 + 1], grads.xy.y);
+    // v_xy[id * 4 + 0] = add_bitcast(v_xy[id * 4 + 0], grads.xy.x);
+    // v_xy[id * 4 + 1] = add_bitcast(v_xy[id * 4 + 1], grads.xy.y);
     // v_conics[id * 3 + 0] = add_bitcast(v_conics[id * 3 + 0], grads.conic.x);
     // v_conics[id * 3 + 1] = add_bitcast(v_conics[id * 3 + 1], grads.conic.y);
     // v_conics[id * 3 + 2] = add_bitcast(v_conics[id * 3 + 2], grads.conic.z);
@@ -76,15 +122,27 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
     let conic = vec3f(grads.conic_x, grads.conic_y, grads.conic_z);
     let color = vec4f(grads.color_r, grads.color_g, grads.color_b, grads.color_a);
 
-    var old_value = atomicLoad(&v_xy[id * 2 + 0]);
+    var old_value = atomicLoad(&v_xy[id * 4 + 0]);
     loop {
-        let cas = atomicCompareExchangeWeak(&v_xy[id * 2 + 0], old_value, add_bitcast(old_value, xy.x));
+        let cas = atomicCompareExchangeWeak(&v_xy[id * 4 + 0], old_value, add_bitcast(old_value, xy.x));
         if cas.exchanged { break; } else { old_value = cas.old_value; }
     }
     // v_xy.y
-    old_value = atomicLoad(&v_xy[id * 2 + 1]);
+    old_value = atomicLoad(&v_xy[id * 4 + 1]);
     loop {
-        let cas = atomicCompareExchangeWeak(&v_xy[id * 2 + 1], old_value, add_bitcast(old_value, xy.y));
+        let cas = atomicCompareExchangeWeak(&v_xy[id * 4 + 1], old_value, add_bitcast(old_value, xy.y));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
+    // |v_xy|.x
+    old_value = atomicLoad(&v_xy[id * 4 + 2]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_xy[id * 4 + 2], old_value, add_bitcast(old_value, xy_abs.x));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
+    // |v_xy|.y
+    old_value = atomicLoad(&v_xy[id * 4 + 3]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_xy[id * 4 + 3], old_value, add_bitcast(old_value, xy_abs.y));
         if cas.exchanged { break; } else { old_value = cas.old_value; }
     }
 
@@ -131,8 +189,33 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
         let cas = atomicCompareExchangeWeak(&v_colors[id * 4 + 3], old_value, add_bitcast(old_value, color.a));
         if cas.exchanged { break; } else { old_value = cas.old_value; }
     }
+
+    #ifdef RENDER_DEPTH
+        // v_depth
+        old_value = atomicLoad(&v_depths[id]);
+        loop {
+            let cas = atomicCompareExchangeWeak(&v_depths[id], old_value, add_bitcast(old_value, grads.depth));
+            if cas.exchanged { break; } else { old_value = cas.old_value; }
+        }
+    #endif
+#endif
+}
+
+#ifdef RENDER_FEATURES
+// The features have a variable number of channels, so they skip the gradient queue, and
+// the first thread of each subgroup adds the summed gradient directly.
+fn write_feature_grad_atomic(id: u32, grad: f32) {
+#ifdef HARD_FLOAT
+    atomicAdd(&v_features[id], grad);
+#else
+    var old_value = atomicLoad(&v_features[id]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_features[id], old_value, add_bitcast(old_value, grad));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
 #endif
 }
+#endif
 
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
@@ -159,8 +242,11 @@ fn main(
     // keep not rasterizing threads around for reading data
     let inside = pixel_coordi.x < img_size.x && pixel_coordi.y < img_size.y;
 
+    let channels = BASE_CHANNELS + uniforms.num_features;
+    let feature_base = pix_id * channels + BASE_CHANNELS;
+
     // this is the T AFTER the last gaussian in this pixel
-    let T_final = 1.0 - output[pix_id].w;
+    let T_final = 1.0 - output[pix_id * channels + 3];
 
     // Have all threads in tile process the same gaussians in batches
     // first collect gaussians between bin_start and bin_final in batches
@@ -174,6 +260,11 @@ fn main(
 
     var final_isect = 0u;
     var buffer = vec3f(0.0);
+    var buffer_depth = 0.0;
+    // Running sum of the features behind the current splat, dotted with their gradient.
+    // As the gradient is the same for all splats of the pixel, this is all the alpha
+    // gradient needs.
+    var buffer_features = 0.0;
 
     if inside {
         final_isect = final_index[pix_id];
@@ -182,9 +273,17 @@ fn main(
     // df/d_out for this pixel
     var v_out = vec4f(0.0);
     if inside {
-        v_out = v_output[pix_id];
+        let base = pix_id * channels;
+        v_out = vec4f(v_output[base + 0], v_output[base + 1], v_output[base + 2], v_output[base + 3]);
     }
 
+    var v_out_depth = 0.0;
+#ifdef RENDER_DEPTH
+    if inside {
+        v_out_depth = v_output[pix_id * channels + 4];
+    }
+#endif
+
     // Make sure all groups start with empty gradient queue.
     atomicStore(&grad_count, 0);
 
@@ -205,6 +304,9 @@ fn main(
             load_compact_gid = compact_gid_from_isect[load_isect_id];
             local_id[local_idx] = load_compact_gid;
             local_batch[local_idx] = projected_splats[load_compact_gid];
+            #ifdef RENDER_FEATURES
+                local_gid[local_idx] = global_from_compact_gid[load_compact_gid];
+            #endif
         }
 
         // wait for all threads to have collected the gaussians.
@@ -223,6 +325,10 @@ fn main(
                 var v_xy = vec2f(0.0);
                 var v_conic = vec3f(0.0);
                 var v_colors = vec4f(0.0);
+                var v_depth = 0.0;
+                // Weight of the splat in the pixel, the gradient of each feature is this times
+                // the gradient of the composited feature.
+                var feature_fac = 0.0;
 
                 var splat_active = false;
 
@@ -252,9 +358,26 @@ fn main(
                         // contribution from this pixel
                         var v_alpha = dot(color.rgb * T - buffer * ra, v_out.rgb);
                         v_alpha += T_final * ra * v_out.a;
+                        // The background is scaled by T_final, which this splat lowers.
+                        v_alpha -= T_final * ra * dot(uniforms.background.rgb, v_out.rgb);
+                        // The depth is composited just like a color channel.
+                        v_alpha += (projected.depth * T - buffer_depth * ra) * v_out_depth;
+
+                        #ifdef RENDER_FEATURES
+                            // The features are composited like colours as well.
+                            let feature_start = local_gid[t] * uniforms.num_features;
+                            var feature_dot = 0.0;
+                            for (var c = 0u; c < uniforms.num_features; c++) {
+                                feature_dot += features[feature_start + c] * v_output[feature_base + c];
+                            }
+                            v_alpha += feature_dot * T - buffer_features * ra;
+                            buffer_features += feature_dot * fac;
+                            feature_fac = fac;
+                        #endif
 
                         // update the running sum
                         buffer += color.xyz * fac;
+                        buffer_depth += projected.depth * fac;
 
                         let v_sigma = -color.a * vis * v_alpha;
 
@@ -268,6 +391,7 @@ fn main(
                                         0.5f * v_sigma * delta.y * delta.y);
 
                         v_colors = vec4f(fac * v_out.rgb, vis * v_alpha);
+                        v_depth = fac * v_out_depth;
                     }
                 }
 
@@ -275,8 +399,11 @@ fn main(
                 // The gradient is sum of all gradients in the subgroup.
                 if subgroupAny(splat_active) {
                     var v_xy_sum = subgroupAdd(v_xy);
+                    // Sum of the absolute per pixel gradients, used for AbsGS style densification.
+                    var v_xy_abs_sum = subgroupAdd(abs(v_xy));
                     var v_conic_sum = subgroupAdd(v_conic);
                     var v_colors_sum = subgroupAdd(v_colors);
+                    var v_depth_sum = subgroupAdd(v_depth);
 
                     // First thread of subgroup writes the gradient. This should be a
                     // subgroupBallot() when it's supported.
@@ -285,17 +412,33 @@ fn main(
                         gather_grads[grad_idx] = helpers::create_projected_splat(
                             v_xy_sum,
                             v_conic_sum,
-                            v_colors_sum
+                            v_colors_sum,
+                            v_depth_sum
                         );
+                        gather_grad_abs[grad_idx] = v_xy_abs_sum;
                         gather_grad_id[grad_idx] = local_id[t];
                     }
+
+                    #ifdef RENDER_FEATURES
+                        let feature_start = local_gid[t] * uniforms.num_features;
+                        for (var c = 0u; c < uniforms.num_features; c++) {
+                            var v_out_feature = 0.0;
+                            if inside {
+                                v_out_feature = v_output[feature_base + c];
+                            }
+                            let v_feature_sum = subgroupAdd(feature_fac * v_out_feature);
+                            if subgroup_invocation_id == 0 {
+                                write_feature_grad_atomic(feature_start + c, v_feature_sum);
+                            }
+                        }
+                    #endif
                 }
             }
 
             // Make sure all threads are done, and flush a batch of gradients.
             workgroupBarrier();
             if local_idx < u32(grad_count) {
-                write_grads_atomic(gather_grads[local_idx], gather_grad_id[local_idx]);
+                write_grads_atomic(gather_grads[local_idx], gather_grad_abs[local_idx], gather_grad_id[local_idx]);
             }
             workgroupBarrier();
             atomicStore(&grad_count, 0);
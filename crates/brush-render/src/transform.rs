@@ -0,0 +1,322 @@
+use crate::{gaussian_splats::Splats, reference::sh_basis, render::sh_coeffs_for_degree, Backend};
+use burn::tensor::Tensor;
+use glam::{Affine3A, Mat3, Quat, Vec3};
+
+// Number of directions the SH rotations are fit with. This is more than the 9 coefficients of
+// the highest band, to keep the fit well conditioned.
+const NUM_FIT_DIRS: usize = 32;
+
+// Directions spread evenly over the sphere.
+fn fibonacci_sphere(count: usize) -> impl Iterator<Item = Vec3> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    (0..count).map(move |i| {
+        let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+        let r = (1.0 - z * z).sqrt();
+        let theta = golden_angle * i as f32;
+        Vec3::new(r * theta.cos(), r * theta.sin(), z)
+    })
+}
+
+// Solve A X = B for a small square A, with Gauss-Jordan elimination. Matrices are row major.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let n = a.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .expect("Matrix is not empty");
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let inv = 1.0 / a[col][col];
+        a[col].iter_mut().for_each(|v| *v *= inv);
+        b[col].iter_mut().for_each(|v| *v *= inv);
+
+        let (pivot_a, pivot_b) = (a[col].clone(), b[col].clone());
+        for (row, (row_a, row_b)) in a.iter_mut().zip(&mut b).enumerate() {
+            let factor = row_a[col];
+            if row == col || factor == 0.0 {
+                continue;
+            }
+            for (v, p) in row_a.iter_mut().zip(&pivot_a) {
+                *v -= factor * p;
+            }
+            for (v, p) in row_b.iter_mut().zip(&pivot_b) {
+                *v -= factor * p;
+            }
+        }
+    }
+    b
+}
+
+// The matrices rotating the coefficients of each SH band from 1 up to `degree`, as row major
+// [2l + 1, 2l + 1] matrices. The rotated coefficients c' = D c give a function with the value at
+// `rotation * dir` that the original has at `dir`. The constant band doesn't change.
+//
+// Rather than building the Wigner D matrices from their closed form, this fits them to the SH
+// basis at a set of directions. Each band maps onto itself under rotation, so the fit is exact up
+// to float precision, and it matches the basis used by the renderer by construction.
+pub(crate) fn sh_rotation(rotation: Quat, degree: u32) -> Vec<Vec<Vec<f32>>> {
+    let inv_rotation = rotation.inverse();
+    let basis: Vec<_> = fibonacci_sphere(NUM_FIT_DIRS)
+        .map(|dir| (sh_basis(degree, dir), sh_basis(degree, inv_rotation * dir)))
+        .collect();
+
+    (1..=degree)
+        .map(|l| {
+            let start = sh_coeffs_for_degree(l - 1) as usize;
+            let k = 2 * l as usize + 1;
+
+            // Least squares solution of Y D = Y_rot, for the basis Y at the directions, and
+            // Y_rot at the inversely rotated directions.
+            let mut yty = vec![vec![0.0; k]; k];
+            let mut yty_rot = vec![vec![0.0; k]; k];
+            for (y, y_rot) in &basis {
+                let y = &y[start..start + k];
+                let y_rot = &y_rot[start..start + k];
+                for ((row, row_rot), &yi) in yty.iter_mut().zip(&mut yty_rot).zip(y) {
+                    for ((v, v_rot), (&yj, &yj_rot)) in
+                        row.iter_mut().zip(row_rot).zip(y.iter().zip(y_rot))
+                    {
+                        *v += yi as f64 * yj as f64;
+                        *v_rot += yi as f64 * yj_rot as f64;
+                    }
+                }
+            }
+
+            solve(yty, yty_rot)
+                .into_iter()
+                .map(|row| row.into_iter().map(|v| v as f32).collect())
+                .collect()
+        })
+        .collect()
+}
+
+// The [4, 4] matrix multiplying row vector quaternions (w, x, y, z) with `rotation` from the left.
+fn quat_left_mul_matrix(rotation: Quat) -> [[f32; 4]; 4] {
+    let Quat { x, y, z, w } = rotation;
+    [[w, x, y, z], [-x, w, z, -y], [-y, -z, w, x], [-z, y, -x, w]]
+}
+
+impl<B: Backend> Splats<B> {
+    /// Transform the splats, eg. to re-orient a scene. Besides moving the means, this rotates and
+    /// scales the gaussians, and rotates the SH coefficients band by band so view dependent colors
+    /// turn along with the scene.
+    ///
+    /// Splats can only represent proper similarity transforms: a rotation, positive uniform scale
+    /// and translation. Any non uniform scale of `transform` is averaged out.
+    ///
+    /// # Panics
+    ///
+    /// If `transform` is a reflection (has a negative determinant), or is degenerate.
+    pub fn transform(&self, transform: Affine3A) -> Self {
+        assert!(
+            transform.matrix3.determinant() > 0.0,
+            "Splats can't be reflected or flattened, the transform needs a positive determinant"
+        );
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+        let scale = (scale.x * scale.y * scale.z).cbrt();
+        let device = self.means.device();
+        let n = self.num_splats();
+        let mut splats = self.clone();
+
+        // The column major matrices are transposed, which is what row vectors need.
+        let matrix = Tensor::<B, 2>::from_floats(
+            (Mat3::from_quat(rotation) * scale).to_cols_array_2d(),
+            &device,
+        );
+        let translation =
+            Tensor::<B, 1>::from_floats(translation.to_array(), &device).reshape([1, 3]);
+        Self::map_param(&mut splats.means, |means| {
+            means.matmul(matrix.clone()) + translation.clone()
+        });
+
+        let quat_matrix = Tensor::<B, 2>::from_floats(quat_left_mul_matrix(rotation), &device);
+        Self::map_param(&mut splats.rotation, |quats| {
+            quats.matmul(quat_matrix.clone())
+        });
+        Self::map_param(&mut splats.log_scales, |log_scales| log_scales + scale.ln());
+
+        let degree = self.sh_degree();
+        if degree > 0 {
+            let bands = sh_rotation(rotation, degree);
+            Self::map_param(&mut splats.sh_coeffs, |coeffs| {
+                let mut rotated = vec![coeffs.clone().slice([0..n, 0..1, 0..3])];
+                for (l, band) in bands.iter().enumerate() {
+                    let start = sh_coeffs_for_degree(l as u32) as usize;
+                    let k = band.len();
+                    let band_t: Vec<f32> = (0..k)
+                        .flat_map(|j| (0..k).map(move |i| band[i][j]))
+                        .collect();
+                    let band_t =
+                        Tensor::<B, 1>::from_floats(band_t.as_slice(), &device).reshape([k, k]);
+
+                    // Rotate the coefficients of all color channels at once.
+                    let band_coeffs = coeffs
+                        .clone()
+                        .slice([0..n, start..start + k, 0..3])
+                        .swap_dims(1, 2)
+                        .reshape([n * 3, k])
+                        .matmul(band_t)
+                        .reshape([n, 3, k])
+                        .swap_dims(1, 2);
+                    rotated.push(band_coeffs);
+                }
+                Tensor::cat(rotated, 1)
+            });
+        }
+
+        splats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use glam::{vec2, vec3, EulerRot};
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_sh_rotation() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let degree = 4;
+        let rotation = Quat::from_euler(EulerRot::YXZ, 0.3, -1.2, 2.5);
+        let bands = sh_rotation(rotation, degree);
+
+        let num_coeffs = sh_coeffs_for_degree(degree) as usize;
+        let coeffs: Vec<f32> = (0..num_coeffs).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let mut rotated = vec![coeffs[0]];
+        for (l, band) in bands.iter().enumerate() {
+            let start = sh_coeffs_for_degree(l as u32) as usize;
+            rotated.extend(band.iter().map(|row| {
+                row.iter()
+                    .zip(&coeffs[start..])
+                    .map(|(d, c)| d * c)
+                    .sum::<f32>()
+            }));
+        }
+
+        let eval = |coeffs: &[f32], dir: Vec3| -> f32 {
+            sh_basis(degree, dir)
+                .iter()
+                .zip(coeffs)
+                .map(|(b, c)| b * c)
+                .sum()
+        };
+
+        for _ in 0..16 {
+            let dir = vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+            .normalize();
+            let original = eval(&coeffs, dir);
+            let moved = eval(&rotated, rotation * dir);
+            assert!(
+                (original - moved).abs() < 1e-4,
+                "{original} != {moved} for {dir}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_transform_render() {
+        let device = NdArrayDevice::Cpu;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+        let num_splats = 32;
+        let means = (0..num_splats)
+            .map(|_| {
+                vec3(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+            })
+            .collect();
+        let rotations = (0..num_splats)
+            .map(|_| {
+                Quat::from_euler(
+                    EulerRot::YXZ,
+                    rng.gen_range(-3.0..3.0),
+                    rng.gen_range(-3.0..3.0),
+                    rng.gen_range(-3.0..3.0),
+                )
+            })
+            .collect();
+        let log_scales = (0..num_splats)
+            .map(|_| {
+                vec3(
+                    rng.gen_range(-3.0..-1.5),
+                    rng.gen_range(-3.0..-1.5),
+                    rng.gen_range(-3.0..-1.5),
+                )
+            })
+            .collect();
+        // Degree 2 SH, so the colors are view dependent.
+        let coeffs = (0..num_splats * 9 * 3)
+            .map(|_| rng.gen_range(-0.5..0.5))
+            .collect();
+        let splats = Splats::<NdArray>::from_raw(
+            means,
+            Some(rotations),
+            Some(log_scales),
+            Some(coeffs),
+            Some(vec![2.0; num_splats]),
+            &device,
+        );
+
+        let size = glam::uvec2(32, 32);
+        let fov = std::f64::consts::PI * 0.5;
+        let cam = Camera::new(
+            vec3(0.5, -0.5, -4.0),
+            Quat::from_rotation_x(0.1),
+            fov,
+            fov,
+            vec2(0.5, 0.5),
+        );
+
+        let transform = Affine3A::from_scale_rotation_translation(
+            Vec3::splat(1.5),
+            Quat::from_euler(EulerRot::YXZ, 0.7, -0.4, 1.9),
+            vec3(0.3, 2.0, -1.0),
+        );
+        let (_, rotation, _) = transform.to_scale_rotation_translation();
+        let moved_cam = Camera::new(
+            transform.transform_point3(cam.position),
+            rotation * cam.rotation,
+            fov,
+            fov,
+            vec2(0.5, 0.5),
+        );
+
+        // Moving the camera along with the splats gives the same image.
+        let (img, _) = splats.render(&cam, size, Vec3::ZERO, false);
+        let (moved_img, _) =
+            splats
+                .transform(transform)
+                .render(&moved_cam, size, Vec3::ZERO, false);
+
+        let img = img.into_data().to_vec::<f32>().expect("Wrong type");
+        let moved_img = moved_img.into_data().to_vec::<f32>().expect("Wrong type");
+        for (a, b) in img.iter().zip(&moved_img) {
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "positive determinant")]
+    fn test_transform_reflection() {
+        let splats = Splats::<NdArray>::from_raw(
+            vec![Vec3::ZERO],
+            None,
+            None,
+            None,
+            None,
+            &NdArrayDevice::Cpu,
+        );
+        splats.transform(Affine3A::from_scale(vec3(-1.0, 1.0, 1.0)));
+    }
+}
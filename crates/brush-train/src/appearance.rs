@@ -0,0 +1,143 @@
+use brush_render::Backend;
+use burn::module::{Module, Param, ParamId};
+use burn::tensor::{Int, Tensor, TensorData};
+
+use crate::param::map_param;
+
+// A learnable affine colour transform for each training view.
+//
+// Captures with auto exposure or white balance have slightly different colours for
+// every image. Without some way to absorb these, they get baked into the SH coefficients
+// (usually as view dependent floaters). The transforms are only used for the training loss,
+// renders of the splats themselves are unaffected.
+#[derive(Module, Debug)]
+pub struct Appearance<B: Backend> {
+    // [num_views, 3, 4] transforms. The first 3 columns are a colour matrix, the last
+    // column an offset.
+    pub transforms: Param<Tensor<B, 3>>,
+}
+
+fn identity_transforms<B: Backend>(num_views: usize, device: &B::Device) -> Tensor<B, 3> {
+    let identity = Tensor::<B, 2>::from_floats(
+        [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+        ],
+        device,
+    );
+    identity.unsqueeze::<3>().repeat_dim(0, num_views)
+}
+
+impl<B: Backend> Appearance<B> {
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        Self::from_tensor(identity_transforms(num_views, device))
+    }
+
+    pub fn from_tensor(transforms: Tensor<B, 3>) -> Self {
+        Self {
+            transforms: Param::initialized(ParamId::new(), transforms.detach().require_grad()),
+        }
+    }
+
+    pub fn num_views(&self) -> usize {
+        self.transforms.dims()[0]
+    }
+
+    // Add identity transforms for views that don't have one yet.
+    pub(crate) fn grow(&mut self, num_views: usize) {
+        let count = num_views.saturating_sub(self.num_views());
+        if count == 0 {
+            return;
+        }
+        let device = self.transforms.device();
+        map_param(&mut self.transforms, |t| {
+            Tensor::cat(vec![t, identity_transforms(count, &device)], 0)
+        });
+    }
+
+    /// Apply the transform of each view to a batch of [N, H, W, C] images. Only the
+    /// RGB channels are changed, any alpha channel is passed through.
+    pub fn apply(&self, images: Tensor<B, 4>, view_ids: &[usize]) -> Tensor<B, 4> {
+        let [n, h, w, c] = images.dims();
+        let device = images.device();
+
+        let ids: Vec<i32> = view_ids.iter().map(|&id| id as i32).collect();
+        let ids = Tensor::<B, 1, Int>::from_data(TensorData::new(ids, [n]), &device);
+        let transforms = self.transforms.val().select(0, ids);
+        let matrix = transforms.clone().slice([0..n, 0..3, 0..3]);
+        let offset = transforms.slice([0..n, 0..3, 3..4]).reshape([n, 1, 3]);
+
+        let rgb = images
+            .clone()
+            .slice([0..n, 0..h, 0..w, 0..3])
+            .reshape([n, h * w, 3]);
+        let rgb = (rgb.matmul(matrix.swap_dims(1, 2)) + offset).reshape([n, h, w, 3]);
+
+        if c > 3 {
+            Tensor::cat(vec![rgb, images.slice([0..n, 0..h, 0..w, 3..c])], 3)
+        } else {
+            rgb
+        }
+    }
+
+    /// Apply the transform of a single view to an [H, W, C] image.
+    pub fn apply_view(&self, image: Tensor<B, 3>, view_id: usize) -> Tensor<B, 3> {
+        self.apply(image.unsqueeze(), &[view_id]).squeeze(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::train::tests::{to_vec, TestBackend};
+    use burn::backend::ndarray::NdArrayDevice;
+
+    const IDENTITY: [[f32; 4]; 3] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ];
+
+    #[test]
+    fn test_apply() {
+        let device = NdArrayDevice::Cpu;
+
+        // The first view keeps its colours, the second doubles the red and offsets the blue.
+        let appearance = Appearance::<TestBackend>::from_tensor(Tensor::from_floats(
+            [
+                IDENTITY,
+                [
+                    [2.0, 0.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0, 0.0],
+                    [0.0, 0.0, 1.0, 0.1],
+                ],
+            ],
+            &device,
+        ));
+        let pixel = [[[0.2, 0.4, 0.6, 0.5]]];
+        let images = Tensor::<TestBackend, 4>::from_floats([pixel, pixel], &device);
+
+        // Each image uses the transform of its view, and the alpha is passed through.
+        let out = to_vec(appearance.apply(images, &[1, 0]));
+        let expected = [0.4, 0.4, 0.7, 0.5, 0.2, 0.4, 0.6, 0.5];
+        for (a, b) in out.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{out:?}");
+        }
+    }
+
+    #[test]
+    fn test_grow() {
+        let device = NdArrayDevice::Cpu;
+        let scaled = IDENTITY.map(|row| row.map(|x| x * 2.0));
+        let mut appearance =
+            Appearance::<TestBackend>::from_tensor(Tensor::from_floats([scaled], &device));
+
+        // New views start with an identity transform, existing ones are kept.
+        appearance.grow(3);
+        appearance.grow(2);
+        assert_eq!(appearance.num_views(), 3);
+        let expected = [scaled, IDENTITY, IDENTITY].concat().concat();
+        assert_eq!(to_vec(appearance.transforms.val()), expected);
+    }
+}
@@ -0,0 +1,386 @@
+// A checkpoint is a safetensors file holding the splats, the Adam moments, the state of the
+// refine strategy and any learned per view appearance and pose corrections. The header metadata
+// holds the TrainConfig (as JSON) and the current iteration, so training can be resumed exactly
+// where it left off.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use brush_render::gaussian_splats::Splats;
+use brush_render::{AutodiffBackend, Backend};
+use burn::config::Config;
+use burn::lr_scheduler::LrScheduler;
+use burn::module::ParamId;
+use burn::optim::record::AdaptorRecord;
+use burn::optim::{AdamState, AdaptiveMomentumState, Optimizer};
+use burn::tensor::{Tensor, TensorData};
+use safetensors::tensor::TensorView;
+use safetensors::{Dtype, SafeTensors};
+
+use crate::appearance::Appearance;
+use crate::pose::PoseCorrection;
+use crate::train::{OptimRecord, SplatTrainer, TrainConfig};
+
+const CONFIG_KEY: &str = "train_config";
+const ITER_KEY: &str = "iter";
+// Prefix of the tensors holding the state of the refine strategy.
+const REFINE_PREFIX: &str = "refine.";
+const APPEARANCE_KEY: &str = "appearance";
+const POSE_KEY: &str = "pose";
+
+struct CheckpointWriter {
+    tensors: Vec<(String, Vec<usize>, Vec<u8>)>,
+    metadata: HashMap<String, String>,
+}
+
+impl CheckpointWriter {
+    async fn add<B: Backend, const D: usize>(&mut self, name: &str, tensor: Tensor<B, D>) {
+        let data = tensor.into_data_async().await.convert::<f32>();
+        self.tensors.push((
+            name.to_owned(),
+            data.shape.clone(),
+            data.as_bytes().to_vec(),
+        ));
+    }
+
+    async fn add_opt_state<B: AutodiffBackend, const D: usize>(
+        &mut self,
+        record: &mut OptimRecord<B>,
+        param_id: ParamId,
+        name: &str,
+    ) {
+        // Parameters that haven't been stepped yet don't have any state.
+        let Some(state) = record.remove(&param_id) else {
+            return;
+        };
+        let state: AdamState<B::InnerBackend, D> = state.into_state();
+        let momentum = state.momentum;
+        self.metadata
+            .insert(format!("adam.{name}.time"), momentum.time.to_string());
+        self.add(&format!("adam.{name}.moment_1"), momentum.moment_1)
+            .await;
+        self.add(&format!("adam.{name}.moment_2"), momentum.moment_2)
+            .await;
+    }
+
+    fn serialize(self) -> Result<Vec<u8>> {
+        let views = self
+            .tensors
+            .iter()
+            .map(|(name, shape, data)| {
+                Ok((name, TensorView::new(Dtype::F32, shape.clone(), data)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(safetensors::serialize(views, &Some(self.metadata))?)
+    }
+}
+
+fn load_tensor<B: Backend, const D: usize>(
+    tensors: &SafeTensors,
+    name: &str,
+    device: &B::Device,
+) -> Result<Tensor<B, D>> {
+    let view = tensors
+        .tensor(name)
+        .with_context(|| format!("Checkpoint is missing tensor {name}"))?;
+    anyhow::ensure!(
+        view.dtype() == Dtype::F32 && view.shape().len() == D,
+        "Checkpoint tensor {name} has an unexpected type or shape"
+    );
+    let values: Vec<f32> = view
+        .data()
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok(Tensor::from_data(
+        TensorData::new(values, view.shape().to_vec()),
+        device,
+    ))
+}
+
+fn load_opt_state<B: AutodiffBackend, const D: usize>(
+    tensors: &SafeTensors,
+    metadata: &HashMap<String, String>,
+    record: &mut OptimRecord<B>,
+    param_id: ParamId,
+    name: &str,
+    device: &B::Device,
+) -> Result<()> {
+    // No saved state means the parameter hadn't been stepped yet.
+    let Some(time) = metadata.get(&format!("adam.{name}.time")) else {
+        return Ok(());
+    };
+    let state = AdamState {
+        momentum: AdaptiveMomentumState {
+            time: time.parse()?,
+            moment_1: load_tensor::<B, D>(tensors, &format!("adam.{name}.moment_1"), device)?
+                .inner(),
+            moment_2: load_tensor::<B, D>(tensors, &format!("adam.{name}.moment_2"), device)?
+                .inner(),
+        },
+    };
+    record.insert(param_id, AdaptorRecord::from_state(state));
+    Ok(())
+}
+
+impl<B: AutodiffBackend> SplatTrainer<B>
+where
+    B::InnerBackend: Backend,
+{
+    /// Serialize the full training state, together with the current splats, to a checkpoint.
+    pub async fn to_checkpoint(&self, splats: &Splats<B>) -> Result<Vec<u8>> {
+        let mut writer = CheckpointWriter {
+            tensors: vec![],
+            metadata: HashMap::new(),
+        };
+        writer
+            .metadata
+            .insert(CONFIG_KEY.to_owned(), self.config.to_string());
+        writer
+            .metadata
+            .insert(ITER_KEY.to_owned(), self.iter.to_string());
+
+        writer.add("means", splats.means.val()).await;
+        writer.add("rotation", splats.rotation.val()).await;
+        writer.add("log_scales", splats.log_scales.val()).await;
+        writer.add("sh_coeffs", splats.sh_coeffs.val()).await;
+        writer.add("raw_opacity", splats.raw_opacity.val()).await;
+
+        let mut record = self.optim.to_record();
+        writer
+            .add_opt_state::<B, 2>(&mut record, splats.means.id, "means")
+            .await;
+        writer
+            .add_opt_state::<B, 2>(&mut record, splats.rotation.id, "rotation")
+            .await;
+        writer
+            .add_opt_state::<B, 2>(&mut record, splats.log_scales.id, "log_scales")
+            .await;
+        writer
+            .add_opt_state::<B, 3>(&mut record, splats.sh_coeffs.id, "sh_coeffs")
+            .await;
+        writer
+            .add_opt_state::<B, 1>(&mut record, splats.raw_opacity.id, "raw_opacity")
+            .await;
+
+        for (name, tensor) in self.strategy.state() {
+            writer.add(&format!("{REFINE_PREFIX}{name}"), tensor).await;
+        }
+
+        if let Some(appearance) = &self.appearance {
+            writer
+                .add(APPEARANCE_KEY, appearance.transforms.val())
+                .await;
+            let mut record = self.appearance_optim.to_record();
+            writer
+                .add_opt_state::<B, 3>(&mut record, appearance.transforms.id, APPEARANCE_KEY)
+                .await;
+        }
+
+        if let Some(pose) = &self.pose {
+            writer.add(POSE_KEY, pose.deltas.val()).await;
+            let mut record = self.pose_optim.to_record();
+            writer
+                .add_opt_state::<B, 2>(&mut record, pose.deltas.id, POSE_KEY)
+                .await;
+        }
+
+        writer.serialize()
+    }
+
+    /// Restore a trainer and its splats from a checkpoint created by [`Self::to_checkpoint`].
+    pub fn from_checkpoint(data: &[u8], device: &B::Device) -> Result<(Self, Splats<B>)> {
+        let (_, header) = SafeTensors::read_metadata(data)?;
+        let metadata = header
+            .metadata()
+            .clone()
+            .context("Checkpoint is missing its metadata")?;
+
+        let config = metadata
+            .get(CONFIG_KEY)
+            .context("Checkpoint is missing the train config")?;
+        let config = TrainConfig::load_binary(config.as_bytes())
+            .map_err(|e| anyhow::anyhow!("Invalid train config in checkpoint: {e:?}"))?;
+        let iter: u32 = metadata
+            .get(ITER_KEY)
+            .context("Checkpoint is missing the iteration")?
+            .parse()?;
+
+        let tensors = SafeTensors::deserialize(data)?;
+        let splats = Splats::from_tensor_data(
+            load_tensor(&tensors, "means", device)?,
+            load_tensor(&tensors, "rotation", device)?,
+            load_tensor(&tensors, "log_scales", device)?,
+            load_tensor(&tensors, "sh_coeffs", device)?,
+            load_tensor(&tensors, "raw_opacity", device)?,
+        );
+
+        // The splats have new parameter IDs, so rebuild the optimizer record for these.
+        let mut record = OptimRecord::<B>::new();
+        load_opt_state::<B, 2>(
+            &tensors,
+            &metadata,
+            &mut record,
+            splats.means.id,
+            "means",
+            device,
+        )?;
+        load_opt_state::<B, 2>(
+            &tensors,
+            &metadata,
+            &mut record,
+            splats.rotation.id,
+            "rotation",
+            device,
+        )?;
+        load_opt_state::<B, 2>(
+            &tensors,
+            &metadata,
+            &mut record,
+            splats.log_scales.id,
+            "log_scales",
+            device,
+        )?;
+        load_opt_state::<B, 3>(
+            &tensors,
+            &metadata,
+            &mut record,
+            splats.sh_coeffs.id,
+            "sh_coeffs",
+            device,
+        )?;
+        load_opt_state::<B, 1>(
+            &tensors,
+            &metadata,
+            &mut record,
+            splats.raw_opacity.id,
+            "raw_opacity",
+            device,
+        )?;
+
+        let mut trainer = Self::new(splats.num_splats(), &config, device);
+        trainer.iter = iter;
+        trainer.optim = trainer.opt_config.init().load_record(record);
+        for name in tensors.names() {
+            if let Some(state_name) = name.strip_prefix(REFINE_PREFIX) {
+                let tensor = load_tensor(&tensors, name, device)?;
+                trainer.strategy.load_state(state_name, tensor)?;
+            }
+        }
+
+        if tensors.tensor(APPEARANCE_KEY).is_ok() {
+            let appearance =
+                Appearance::from_tensor(load_tensor(&tensors, APPEARANCE_KEY, device)?);
+            let mut record = OptimRecord::<B>::new();
+            load_opt_state::<B, 3>(
+                &tensors,
+                &metadata,
+                &mut record,
+                appearance.transforms.id,
+                APPEARANCE_KEY,
+                device,
+            )?;
+            trainer.appearance_optim = trainer.opt_config.init().load_record(record);
+            trainer.appearance = Some(appearance);
+        }
+
+        if tensors.tensor(POSE_KEY).is_ok() {
+            let pose = PoseCorrection::from_tensor(load_tensor(&tensors, POSE_KEY, device)?);
+            let mut record = OptimRecord::<B>::new();
+            load_opt_state::<B, 2>(
+                &tensors,
+                &metadata,
+                &mut record,
+                pose.deltas.id,
+                POSE_KEY,
+                device,
+            )?;
+            trainer.pose_optim = trainer.opt_config.init().load_record(record);
+            trainer.pose = Some(pose);
+        }
+
+        // The exponential schedule only depends on the number of steps taken, so just replay it.
+        for _ in 0..iter {
+            trainer.sched_mean.step();
+        }
+
+        Ok((trainer, splats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::train::tests::{
+        test_batch, test_splats, test_view, to_vec, train_steps, TestBackend,
+    };
+
+    type Inner = <TestBackend as AutodiffBackend>::InnerBackend;
+
+    fn mean_moments(
+        trainer: &SplatTrainer<TestBackend>,
+        splats: &Splats<TestBackend>,
+    ) -> (usize, Vec<f32>, Vec<f32>) {
+        let mut record = trainer.optim.to_record();
+        let state: AdamState<Inner, 2> = record
+            .remove(&splats.means.id)
+            .expect("Means have been stepped")
+            .into_state();
+        let momentum = state.momentum;
+        (
+            momentum.time,
+            to_vec(momentum.moment_1),
+            to_vec(momentum.moment_2),
+        )
+    }
+
+    fn refine_state(trainer: &SplatTrainer<TestBackend>) -> Vec<(String, Vec<f32>)> {
+        trainer
+            .strategy
+            .state()
+            .into_iter()
+            .map(|(name, tensor)| (name, to_vec(tensor)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_round_trip() {
+        // Accumulate densification statistics, without refining.
+        let config = TrainConfig::new()
+            .with_warmup_steps(0)
+            .with_max_refine_step(0);
+        let batch = test_batch(vec![test_view()], vec![0]);
+        let device = batch.gt_images.device();
+
+        let splats = test_splats(16);
+        let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &device);
+        let splats = train_steps(&mut trainer, splats, &batch, 3).await;
+
+        let data = trainer
+            .to_checkpoint(&splats)
+            .await
+            .expect("Failed to save");
+        let (mut loaded, loaded_splats) =
+            SplatTrainer::<TestBackend>::from_checkpoint(&data, &device).expect("Failed to load");
+
+        assert_eq!(loaded.iter, trainer.iter);
+        assert_eq!(
+            to_vec(loaded_splats.means.val()),
+            to_vec(splats.means.val())
+        );
+        assert_eq!(
+            mean_moments(&loaded, &loaded_splats),
+            mean_moments(&trainer, &splats)
+        );
+        assert_eq!(refine_state(&loaded), refine_state(&trainer));
+        assert!(refine_state(&trainer)[0].1.iter().any(|&g| g > 0.0));
+
+        // Training continues exactly as it would have without the checkpoint.
+        let splats = train_steps(&mut trainer, splats, &batch, 1).await;
+        let loaded_splats = train_steps(&mut loaded, loaded_splats, &batch, 1).await;
+        assert_eq!(
+            to_vec(loaded_splats.means.val()),
+            to_vec(splats.means.val())
+        );
+    }
+}
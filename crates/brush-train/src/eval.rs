@@ -4,7 +4,7 @@ use burn::tensor::{ElementConversion, Tensor};
 use image::DynamicImage;
 use rand::seq::IteratorRandom;
 
-use crate::image::image_to_tensor;
+use crate::image::{image_to_tensor, mask_to_tensor};
 use crate::scene::{Scene, SceneView};
 use crate::ssim::Ssim;
 
@@ -50,18 +50,35 @@ pub async fn eval_stats<B: Backend>(
         let res = glam::uvec2(ground_truth.width(), ground_truth.height());
 
         let gt_tensor = image_to_tensor::<B>(&ground_truth, device);
-        let (rendered, aux) = splats.render(&view.camera, res, false);
+        let (rendered, aux) = splats.render(&view.camera, res, glam::Vec3::ZERO, false);
 
         let render_rgb = rendered.slice([0..res.y as usize, 0..res.x as usize, 0..3]);
-        let mse = (render_rgb.clone() - gt_tensor.clone())
-            .powf_scalar(2.0)
-            .mean();
+        let sq_err = (render_rgb.clone() - gt_tensor.clone()).powf_scalar(2.0);
+
+        // Only measure the pixels inside the mask, if any.
+        let mask = view
+            .mask
+            .as_ref()
+            .map(|mask| mask_to_tensor::<B>(mask, device));
+        let mse = match &mask {
+            Some(mask) => {
+                (sq_err * mask.clone()).sum() / (mask.clone().sum() * 3.0).clamp_min(1e-6)
+            }
+            None => sq_err.mean(),
+        };
 
         let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
         let psnr = psnr.into_scalar_async().await.elem::<f32>();
 
         let ssim_measure = Ssim::new(11, 3, device);
-        let ssim = ssim_measure.ssim(render_rgb.clone().unsqueeze(), gt_tensor.unsqueeze());
+        let ssim = match mask {
+            Some(mask) => ssim_measure.masked_ssim(
+                render_rgb.clone().unsqueeze(),
+                gt_tensor.unsqueeze(),
+                mask.unsqueeze(),
+            ),
+            None => ssim_measure.ssim(render_rgb.clone().unsqueeze(), gt_tensor.unsqueeze()),
+        };
         let ssim = ssim.into_scalar_async().await.elem::<f32>();
 
         ret.push(EvalView {
@@ -4,6 +4,8 @@ use burn::{
 };
 use image::{DynamicImage, Rgb32FImage, Rgba32FImage};
 
+use crate::scene::DepthImage;
+
 // Converts an image to a tensor. The tensor will be a floating point image with a [0, 1] image.
 pub fn image_to_tensor<B: Backend>(image: &DynamicImage, device: &B::Device) -> Tensor<B, 3> {
     let (w, h) = (image.width(), image.height());
@@ -17,6 +19,20 @@ pub fn image_to_tensor<B: Backend>(image: &DynamicImage, device: &B::Device) ->
     Tensor::from_data(tensor_data, device)
 }
 
+// Converts a mask to a [H, W, 1] tensor with values in [0, 1].
+pub fn mask_to_tensor<B: Backend>(mask: &DynamicImage, device: &B::Device) -> Tensor<B, 3> {
+    let (w, h) = (mask.width(), mask.height());
+    let tensor_data = TensorData::new(mask.to_luma32f().into_vec(), [h as usize, w as usize, 1]);
+    Tensor::from_data(tensor_data, device)
+}
+
+// Converts a depth map to a [H, W, 1] tensor.
+pub fn depth_to_tensor<B: Backend>(depth: &DepthImage, device: &B::Device) -> Tensor<B, 3> {
+    let (w, h) = (depth.width(), depth.height());
+    let tensor_data = TensorData::new(depth.as_raw().clone(), [h as usize, w as usize, 1]);
+    Tensor::from_data(tensor_data, device)
+}
+
 pub trait TensorDataToImage {
     fn into_image(self) -> DynamicImage;
 }
@@ -1,6 +1,12 @@
+pub mod appearance;
+pub mod checkpoint;
 pub mod eval;
+pub mod pose;
+pub mod refine;
 pub mod ssim;
 pub mod train;
 
+mod param;
+
 pub mod image;
 pub mod scene;
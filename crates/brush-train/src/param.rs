@@ -0,0 +1,12 @@
+use burn::module::Param;
+use burn::prelude::Backend;
+use burn::tensor::Tensor;
+
+// Replace the value of a learnable parameter, keeping its id so the optimizer state still
+// belongs to it. The new value is a fresh leaf that requires a gradient.
+pub(crate) fn map_param<B: Backend, const D: usize>(
+    param: &mut Param<Tensor<B, D>>,
+    f: impl FnOnce(Tensor<B, D>) -> Tensor<B, D>,
+) {
+    *param = param.clone().map(|x| f(x).detach().require_grad());
+}
@@ -0,0 +1,191 @@
+use brush_render::camera::Camera;
+use brush_render::Backend;
+use burn::module::{Module, Param, ParamId};
+use burn::tensor::{Int, Tensor, TensorData};
+
+use crate::param::map_param;
+
+// A learnable correction of the camera pose of each training view.
+//
+// Each correction is a small rigid transform applied in camera space, stored as a translation
+// followed by an axis-angle rotation. The corrected world to camera matrix is
+// `correction * viewmat`.
+#[derive(Module, Debug)]
+pub struct PoseCorrection<B: Backend> {
+    // [num_views, 6] corrections.
+    pub deltas: Param<Tensor<B, 2>>,
+}
+
+fn skew<B: Backend>(v: Tensor<B, 2>) -> Tensor<B, 3> {
+    let n = v.dims()[0];
+    let [x, y, z] = [0, 1, 2].map(|i| v.clone().slice([0..n, i..i + 1]));
+    let zero = x.zeros_like();
+    Tensor::cat(
+        vec![
+            zero.clone(),
+            -z.clone(),
+            y.clone(),
+            z,
+            zero.clone(),
+            -x.clone(),
+            -y,
+            x,
+            zero,
+        ],
+        1,
+    )
+    .reshape([n, 3, 3])
+}
+
+// Converts a (non normalized) rotation axis scaled by the angle to a rotation matrix, using the
+// Rodrigues formula.
+fn axis_angle_to_matrix<B: Backend>(axis_angle: Tensor<B, 2>) -> Tensor<B, 3> {
+    let n = axis_angle.dims()[0];
+    let device = axis_angle.device();
+
+    // Offset by a small epsilon to keep the gradient defined for zero rotations.
+    let theta = (axis_angle.clone().powf_scalar(2.0).sum_dim(1) + 1e-12)
+        .sqrt()
+        .reshape([n, 1, 1]);
+    let a = theta.clone().sin() / theta.clone();
+    let b = (theta.clone().cos().neg() + 1.0) / theta.powf_scalar(2.0);
+
+    let k = skew(axis_angle);
+    let identity = Tensor::<B, 2>::eye(3, &device)
+        .unsqueeze::<3>()
+        .repeat_dim(0, n);
+    identity + k.clone() * a + k.clone().matmul(k) * b
+}
+
+impl<B: Backend> PoseCorrection<B> {
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        Self::from_tensor(Tensor::zeros([num_views, 6], device))
+    }
+
+    pub fn from_tensor(deltas: Tensor<B, 2>) -> Self {
+        Self {
+            deltas: Param::initialized(ParamId::new(), deltas.detach().require_grad()),
+        }
+    }
+
+    pub fn num_views(&self) -> usize {
+        self.deltas.dims()[0]
+    }
+
+    // Add zero corrections for views that don't have one yet.
+    pub(crate) fn grow(&mut self, num_views: usize) {
+        let count = num_views.saturating_sub(self.num_views());
+        if count == 0 {
+            return;
+        }
+        let device = self.deltas.device();
+        map_param(&mut self.deltas, |d| {
+            Tensor::cat(vec![d, Tensor::zeros([count, 6], &device)], 0)
+        });
+    }
+
+    fn select(&self, view_ids: &[usize]) -> Tensor<B, 2> {
+        let ids: Vec<i32> = view_ids.iter().map(|&id| id as i32).collect();
+        let ids = Tensor::<B, 1, Int>::from_data(
+            TensorData::new(ids, [view_ids.len()]),
+            &self.deltas.device(),
+        );
+        self.deltas.val().select(0, ids)
+    }
+
+    /// Apply the corrections of the given views to their [N, 4, 4] column major world to
+    /// camera matrices.
+    pub fn correct_viewmats(&self, viewmats: Tensor<B, 3>, view_ids: &[usize]) -> Tensor<B, 3> {
+        let n = view_ids.len();
+        let device = viewmats.device();
+
+        let deltas = self.select(view_ids);
+        let translation = deltas.clone().slice([0..n, 0..3]).reshape([n, 3, 1]);
+        let rotation = axis_angle_to_matrix(deltas.slice([0..n, 3..6]));
+
+        let bottom = Tensor::<B, 2>::from_floats([[0.0, 0.0, 0.0, 1.0]], &device)
+            .unsqueeze::<3>()
+            .repeat_dim(0, n);
+        let correction = Tensor::cat(vec![Tensor::cat(vec![rotation, translation], 2), bottom], 1);
+
+        // The matrices are column major, ie. transposed, so (C * V)^T = V^T * C^T.
+        viewmats.matmul(correction.swap_dims(1, 2))
+    }
+
+    /// Read back the corrections of the given views.
+    pub async fn read_corrections(&self, view_ids: &[usize]) -> Vec<glam::Affine3A> {
+        let data = self
+            .select(view_ids)
+            .into_data_async()
+            .await
+            .convert::<f32>()
+            .to_vec::<f32>()
+            .expect("Pose corrections were converted to f32");
+
+        data.chunks_exact(6)
+            .map(|d| {
+                glam::Affine3A::from_rotation_translation(
+                    glam::Quat::from_scaled_axis(glam::vec3(d[3], d[4], d[5])),
+                    glam::vec3(d[0], d[1], d[2]),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Apply a camera space correction to a camera.
+pub fn correct_camera(camera: &Camera, correction: glam::Affine3A) -> Camera {
+    let world_to_cam = glam::Mat4::from(correction) * camera.world_to_local();
+    let (_, rotation, position) = world_to_cam.inverse().to_scale_rotation_translation();
+
+    let mut camera = camera.clone();
+    camera.position = position;
+    camera.rotation = rotation;
+    camera
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::train::tests::{to_vec, TestBackend};
+    use burn::backend::ndarray::NdArrayDevice;
+    use glam::{vec2, vec3, Quat};
+
+    #[tokio::test]
+    async fn test_correct_viewmats() {
+        let device = NdArrayDevice::Cpu;
+        let camera = Camera::new(
+            vec3(1.0, 2.0, -4.0),
+            Quat::from_rotation_y(0.3),
+            0.8,
+            0.8,
+            vec2(0.5, 0.5),
+        );
+
+        // Only the second view has a correction.
+        let pose = PoseCorrection::<TestBackend>::from_tensor(Tensor::from_floats(
+            [[0.0; 6], [0.1, -0.2, 0.3, 0.05, 0.1, -0.15]],
+            &device,
+        ));
+        let viewmat = Tensor::<TestBackend, 2>::from_floats(
+            camera.world_to_local().to_cols_array_2d(),
+            &device,
+        );
+        let corrected =
+            to_vec(pose.correct_viewmats(viewmat.unsqueeze::<3>().repeat_dim(0, 2), &[0, 1]));
+
+        // The corrected viewmats match the cameras corrected on the CPU, which for the
+        // first view is the original camera.
+        let corrections = pose.read_corrections(&[0, 1]).await;
+        let expected = [
+            camera.world_to_local(),
+            correct_camera(&camera, corrections[1]).world_to_local(),
+        ];
+        for (viewmat, expected) in corrected.chunks_exact(16).zip(expected) {
+            for (a, b) in viewmat.iter().zip(expected.to_cols_array()) {
+                assert!((a - b).abs() < 1e-4, "{viewmat:?} != {expected:?}");
+            }
+        }
+        assert_ne!(corrected[..16], corrected[16..]);
+    }
+}
@@ -0,0 +1,398 @@
+use anyhow::Result;
+use brush_render::bounding_box::CropBox;
+use brush_render::gaussian_splats::{inverse_sigmoid, Splats};
+use brush_render::AutodiffBackend;
+use burn::tensor::{Distribution, ElementConversion, Int, Tensor};
+
+use super::{quaternion_vec_multiply, RefineFuture, RefineStrategy, ViewGrads};
+use crate::train::{
+    append_opt_state, concat_splats, map_opt_state, prune_points, OptimRecord, RefineStats,
+    TrainConfig,
+};
+
+// Adaptive density control, as in the original 3DGS paper.
+//
+// Splats with a large average screenspace gradient are either cloned (when small) or
+// split (when large). Transparent and huge splats are pruned, and the opacity of all splats
+// is periodically reset.
+pub struct AdcStrategy<B: AutodiffBackend> {
+    config: TrainConfig,
+
+    // Helper tensors for accumulating the viewspace_xy gradients and the number
+    // of observations per gaussian. Used in pruning and densification.
+    grad_2d_accum: Tensor<B, 1>,
+    xy_grad_counts: Tensor<B, 1, Int>,
+}
+
+impl<B: AutodiffBackend> AdcStrategy<B> {
+    pub fn new(config: &TrainConfig, num_points: usize, device: &B::Device) -> Self {
+        Self {
+            config: config.clone(),
+            grad_2d_accum: Tensor::zeros([num_points], device),
+            xy_grad_counts: Tensor::zeros([num_points], device),
+        }
+    }
+
+    fn reset_stats(&mut self, num_points: usize, device: &B::Device) {
+        self.grad_2d_accum = Tensor::zeros([num_points], device);
+        self.xy_grad_counts = Tensor::zeros([num_points], device);
+    }
+
+    fn reset_opacity(&self, splats: &mut Splats<B>, record: &mut OptimRecord<B>) {
+        Splats::map_param(&mut splats.raw_opacity, |op| {
+            Tensor::zeros_like(&op) + inverse_sigmoid(self.config.reset_alpha_value)
+        });
+        // The old moments don't make sense for the new opacities.
+        map_opt_state::<B, 1>(record, splats.raw_opacity.id, |x| x.zeros_like());
+    }
+
+    async fn refine_splats(
+        &mut self,
+        iter: u32,
+        splats: Splats<B>,
+        record: &mut OptimRecord<B>,
+    ) -> (Splats<B>, RefineStats) {
+        let mut splats = splats;
+        let device = splats.means.device();
+
+        let grads = self.grad_2d_accum.clone() / self.xy_grad_counts.clone().clamp_min(1).float();
+
+        let mut big_grad_mask = grads
+            .clone()
+            .greater_equal_elem(self.config.densify_grad_thresh);
+
+        // Every candidate adds one splat, so only keep as many as fit in the budget.
+        let mut num_over_budget = 0;
+        if let Some(max_splats) = self.config.max_splats {
+            let num_candidates = big_grad_mask
+                .clone()
+                .int()
+                .sum()
+                .into_scalar_async()
+                .await
+                .elem::<i64>() as usize;
+            let available = max_splats.saturating_sub(splats.num_splats());
+
+            if num_candidates > available {
+                num_over_budget = num_candidates - available;
+
+                // Prioritize the candidates with the highest gradients.
+                let mut keep = Tensor::<B, 1>::zeros([splats.num_splats()], &device);
+                if available > 0 {
+                    let (_, top_inds) = grads
+                        .mask_fill(big_grad_mask.bool_not(), -1.0)
+                        .topk_with_indices(available, 0);
+                    keep = keep.select_assign(0, top_inds, Tensor::ones([available], &device));
+                }
+                big_grad_mask = keep.greater_elem(0.0);
+            }
+        }
+
+        let split_clone_size_mask = splats
+            .scales()
+            .max_dim(1)
+            .squeeze(1)
+            .lower_elem(self.config.densify_size_thresh);
+
+        let mut append_means = vec![];
+        let mut append_rots = vec![];
+        let mut append_coeffs = vec![];
+        let mut append_opac = vec![];
+        let mut append_scales = vec![];
+
+        let clone_inds = Tensor::stack::<2>(
+            vec![split_clone_size_mask.clone(), big_grad_mask.clone()],
+            1,
+        )
+        .all_dim(1)
+        .squeeze::<1>(1)
+        .argwhere_async()
+        .await;
+
+        // Clone splats
+        let clone_count = clone_inds.dims()[0];
+        if clone_count > 0 {
+            let clone_inds = clone_inds.squeeze(1);
+            append_means.push(splats.means.val().select(0, clone_inds.clone()));
+            append_rots.push(splats.rotation.val().select(0, clone_inds.clone()));
+            append_coeffs.push(splats.sh_coeffs.val().select(0, clone_inds.clone()));
+            append_opac.push(splats.raw_opacity.val().select(0, clone_inds.clone()));
+            append_scales.push(splats.log_scales.val().select(0, clone_inds.clone()));
+        }
+
+        // Split splats.
+        let split_mask =
+            Tensor::stack::<2>(vec![split_clone_size_mask.bool_not(), big_grad_mask], 1).all_dim(1);
+        let split_inds = split_mask.clone().squeeze::<1>(1).argwhere_async().await;
+        let split_count = split_inds.dims()[0];
+        if split_count > 0 {
+            let split_inds = split_inds.squeeze(1);
+
+            // Some parts can be straightforwardly copied to the new splats.
+            let cur_coeff = splats.sh_coeffs.val().select(0, split_inds.clone());
+            let cur_raw_opac = splats.raw_opacity.val().select(0, split_inds.clone());
+            let cur_rots = splats.rotation.val().select(0, split_inds.clone());
+            append_rots.push(cur_rots.clone());
+            append_coeffs.push(cur_coeff.clone());
+            append_opac.push(cur_raw_opac);
+
+            // Change current scale to be lower.
+            let cur_scale = splats.scales().select(0, split_inds.clone());
+            Splats::map_param(&mut splats.log_scales, |m| {
+                let div_scales = Tensor::zeros_like(&m).select_assign(
+                    0,
+                    split_inds.clone(),
+                    (cur_scale.clone() / 1.6).log(),
+                );
+                m.mask_where(split_mask.clone(), div_scales)
+            });
+            // Append newer smaller scales.
+            append_scales.push((cur_scale.clone() / 1.6).log());
+
+            // Sample new position for splits.
+            let cur_means = splats.means.val().select(0, split_inds.clone());
+            let samples = quaternion_vec_multiply(
+                cur_rots.clone(),
+                Tensor::random([split_count, 3], Distribution::Normal(0.0, 0.5), &device)
+                    * cur_scale.clone(),
+            );
+            // Assign new means to current values.
+            Splats::map_param(&mut splats.means, |m| {
+                let offset_means = Tensor::zeros_like(&m).select_assign(
+                    0,
+                    split_inds.clone(),
+                    cur_means.clone() - samples.clone(),
+                );
+                m.mask_where(split_mask.clone(), offset_means)
+            });
+
+            // Append new means with offset sample.
+            let samples_new = quaternion_vec_multiply(
+                cur_rots.clone(),
+                Tensor::random([split_count, 3], Distribution::Normal(0.0, 0.5), &device)
+                    * cur_scale,
+            );
+            append_means.push(cur_means.clone() + samples_new);
+        }
+
+        if !append_means.is_empty() {
+            // New splats start without any momentum.
+            append_opt_state(record, &splats, clone_count + split_count);
+
+            let append_means = Tensor::cat(append_means, 0);
+            let append_rots = Tensor::cat(append_rots, 0);
+            let append_coeffs = Tensor::cat(append_coeffs, 0);
+            let append_opac = Tensor::cat(append_opac, 0);
+            let append_scales = Tensor::cat(append_scales, 0);
+
+            concat_splats(
+                &mut splats,
+                append_means,
+                append_rots,
+                append_coeffs,
+                append_opac,
+                append_scales,
+            );
+        }
+
+        // Do some more processing. Important to do this last as otherwise you might mess up the correspondence
+        // of gradient <-> splat.
+        let start_count = splats.num_splats();
+
+        // Remove barely visible gaussians.
+        let alpha_mask = splats.opacity().lower_elem(self.config.cull_alpha_thresh);
+        prune_points(&mut splats, record, alpha_mask).await;
+
+        let alpha_pruned = start_count - splats.num_splats();
+
+        // Delete Gaussians with too large of a radius in world-units.
+        let scale_mask = splats
+            .scales()
+            .max_dim(1)
+            .squeeze(1)
+            .greater_elem(self.config.cull_scale_thresh);
+        prune_points(&mut splats, record, scale_mask).await;
+
+        let scale_pruned = start_count - splats.num_splats();
+
+        // Remove everything outside of the region of interest.
+        let crop_pruned = if let Some(crop_box) = &self.config.crop_box {
+            let count = splats.num_splats();
+            let crop_mask = splats.crop_mask(crop_box).bool_not();
+            prune_points(&mut splats, record, crop_mask).await;
+            count - splats.num_splats()
+        } else {
+            0
+        };
+
+        let refine_step = iter / self.config.refine_every;
+        if refine_step % self.config.reset_alpha_every_refine == 0 {
+            self.reset_opacity(&mut splats, record);
+        }
+
+        // Stats don't line up anymore so have to reset them.
+        self.reset_stats(splats.num_splats(), &device);
+
+        let stats = RefineStats {
+            num_split: split_count,
+            num_cloned: clone_count,
+            num_transparent_pruned: alpha_pruned,
+            num_scale_pruned: scale_pruned,
+            num_crop_pruned: crop_pruned,
+            num_relocated: 0,
+            num_over_budget,
+            max_splats: self.config.max_splats,
+        };
+
+        (splats, stats)
+    }
+}
+
+impl<B: AutodiffBackend> RefineStrategy<B> for AdcStrategy<B> {
+    fn observe(&mut self, iter: u32, views: &[ViewGrads<B>], img_size: glam::UVec2) {
+        if iter <= self.config.warmup_steps {
+            return;
+        }
+
+        let device = self.grad_2d_accum.device();
+        let batch_size = views.len() as f32;
+
+        // The loss is averaged over the batch, so scale the gradients back up to keep
+        // the densification threshold independent of the batch size. The gradients are also
+        // converted from pixels to NDC using the size of this step's images, so the threshold
+        // doesn't change when the resolution schedule switches resolution.
+        let grad_scale = Tensor::<_, 1>::from_floats(
+            [
+                img_size.x as f32 / 2.0 * batch_size,
+                img_size.y as f32 / 2.0 * batch_size,
+            ],
+            &device,
+        )
+        .reshape([1, 2]);
+
+        // TODO: Burn really should implement +=
+        for view in views {
+            let gs_ids = Tensor::from_primitive(view.aux.global_from_compact_gid.clone());
+
+            let num_points = view.xy_grads.dims()[0];
+            let grad_cols = if self.config.densify_abs_grad {
+                2..4
+            } else {
+                0..2
+            };
+            let xys_grad = view.xy_grads.clone().slice([0..num_points, grad_cols]);
+            let xys_grad = xys_grad * grad_scale.clone();
+            let xys_grad_norm = xys_grad.powi_scalar(2).sum_dim(1).squeeze(1).sqrt();
+
+            let num_vis = Tensor::from_primitive(view.aux.num_visible.clone());
+            let valid = Tensor::arange(0..num_points as i64, &device).lower(num_vis);
+
+            self.xy_grad_counts = self
+                .xy_grad_counts
+                .clone()
+                .select_assign(0, gs_ids, valid.int());
+
+            self.grad_2d_accum = self.grad_2d_accum.clone() + xys_grad_norm;
+        }
+    }
+
+    fn set_crop_box(&mut self, crop_box: Option<CropBox>) {
+        self.config.crop_box = crop_box;
+    }
+
+    fn refine<'a>(
+        &'a mut self,
+        iter: u32,
+        splats: Splats<B>,
+        record: &'a mut OptimRecord<B>,
+    ) -> RefineFuture<'a, (Splats<B>, RefineStats)> {
+        Box::pin(self.refine_splats(iter, splats, record))
+    }
+
+    fn state(&self) -> Vec<(String, Tensor<B, 1>)> {
+        vec![
+            ("grad_2d_accum".to_owned(), self.grad_2d_accum.clone()),
+            (
+                "xy_grad_counts".to_owned(),
+                self.xy_grad_counts.clone().float(),
+            ),
+        ]
+    }
+
+    fn load_state(&mut self, name: &str, tensor: Tensor<B, 1>) -> Result<()> {
+        match name {
+            "grad_2d_accum" => self.grad_2d_accum = tensor,
+            "xy_grad_counts" => self.xy_grad_counts = tensor.int(),
+            _ => anyhow::bail!("Unknown refine state {name}"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::train::tests::{test_splats, test_view, to_vec, TestBackend};
+    use burn::backend::ndarray::NdArrayDevice;
+    use glam::{Quat, Vec3};
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_split() {
+        let device = NdArrayDevice::Cpu;
+
+        // A splat that is long along its local x axis, turned to lie along the world y axis.
+        let splats = Splats::<TestBackend>::from_raw(
+            vec![Vec3::ZERO],
+            Some(vec![Quat::from_rotation_z(std::f32::consts::FRAC_PI_2)]),
+            Some(vec![Vec3::new(0.0, -6.0, -6.0)]),
+            None,
+            Some(vec![inverse_sigmoid(0.5)]),
+            &device,
+        );
+
+        let mut strategy = AdcStrategy::new(&TrainConfig::new(), 1, &device);
+        strategy.grad_2d_accum = Tensor::ones([1], &device);
+        strategy.xy_grad_counts = Tensor::ones([1], &device);
+
+        let mut record = HashMap::new();
+        let (splats, stats) = strategy.refine_splats(1, splats, &mut record).await;
+        assert_eq!(stats.num_split, 1);
+        assert_eq!(splats.num_splats(), 2);
+
+        // Both the original and the new splat shrink.
+        let expected = (1.0f32 / 1.6).ln();
+        for scale in to_vec(splats.log_scales.val()).chunks(3) {
+            assert!((scale[0] - expected).abs() < 1e-5, "{scale:?}");
+        }
+
+        // Both move away from the old mean, along the long axis of the splat.
+        let means = to_vec(splats.means.val());
+        for mean in means.chunks(3) {
+            assert!(mean[0].abs() < 0.01 && mean[2].abs() < 0.01, "{mean:?}");
+            assert_ne!(mean[1], 0.0);
+        }
+    }
+
+    #[test]
+    fn test_grads_resolution_independent() {
+        let device = NdArrayDevice::Cpu;
+        let splats = test_splats(16);
+        let camera = test_view().camera;
+        let config = TrainConfig::new().with_warmup_steps(0);
+
+        let mut accum = vec![];
+        for size in [32, 64] {
+            let img_size = glam::uvec2(size, size);
+            let (_, aux) = splats.render(&camera, img_size, Vec3::ZERO, false);
+            // Gradients in pixels shrink as the resolution goes up.
+            let xy_grads = Tensor::ones([16, 4], &device) / size as f32;
+            let mut strategy = AdcStrategy::new(&config, 16, &device);
+            strategy.observe(1, &[ViewGrads { xy_grads, aux }], img_size);
+            accum.push(to_vec(strategy.grad_2d_accum));
+        }
+
+        // Converted to NDC they're the same, so the threshold works for every resolution.
+        assert_eq!(accum[0], accum[1]);
+    }
+}
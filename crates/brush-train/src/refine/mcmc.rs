@@ -0,0 +1,368 @@
+use brush_render::bounding_box::CropBox;
+use brush_render::gaussian_splats::{inverse_sigmoid, Splats};
+use brush_render::AutodiffBackend;
+use burn::config::Config;
+use burn::tensor::activation::sigmoid;
+use burn::tensor::{Bool, Int, Tensor, TensorData};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::{quaternion_vec_multiply, RefineFuture, RefineStrategy, ViewGrads};
+use crate::train::{append_opt_state, concat_splats, map_opt_state, OptimRecord, RefineStats};
+
+// Max number of copies made of a single splat in one refine step.
+const MAX_COPIES: u32 = 51;
+
+// MCMC keeps growing until it hits the budget, so it always needs one. This is used
+// when the TrainConfig doesn't set max_splats.
+const DEFAULT_MAX_SPLATS: usize = 1_000_000;
+
+#[derive(Config, Debug)]
+pub struct McmcConfig {
+    // Fraction of splats to add on each refine step.
+    #[config(default = 0.05)]
+    grow_rate: f32,
+
+    // Splats with a lower opacity than this are considered dead, and are relocated.
+    #[config(default = 0.005)]
+    min_opacity: f32,
+
+    // Scale of the noise added to the means. This is multiplied by the mean learning rate.
+    #[config(default = 5e5)]
+    noise_lr: f64,
+
+    // Weight of the L1 regularization on the opacity.
+    #[config(default = 0.01)]
+    opacity_reg: f32,
+
+    // Weight of the L1 regularization on the scales.
+    #[config(default = 0.01)]
+    scale_reg: f32,
+}
+
+// Densification from "3D Gaussian Splatting as Markov Chain Monte Carlo".
+//
+// Splats are treated as samples. Instead of pruning, dead splats are moved onto live ones
+// (sampled by opacity), and new splats are added the same way until the budget is reached.
+// The means are perturbed with noise that mostly affects low opacity splats, to help explore.
+pub struct McmcStrategy {
+    config: McmcConfig,
+    max_splats: usize,
+    seed: u64,
+    // Re-seeded from the seed and the step in every post_step, so a run resumed from a
+    // checkpoint draws the same samples without having to store the state of the rng.
+    rng: StdRng,
+    crop_box: Option<CropBox>,
+}
+
+fn binomial(n: u32, k: u32) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+// Standard normal samples, using the Box-Muller transform.
+fn normal_samples(rng: &mut StdRng, count: usize) -> Vec<f32> {
+    (0..count)
+        .map(|_| {
+            // Keep u1 in (0, 1] so the log is finite.
+            let u1 = 1.0 - rng.gen::<f32>();
+            let u2 = rng.gen::<f32>();
+            (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+        })
+        .collect()
+}
+
+impl McmcStrategy {
+    pub fn new(config: &McmcConfig, max_splats: Option<usize>, seed: u64) -> Self {
+        Self {
+            config: config.clone(),
+            max_splats: max_splats.unwrap_or(DEFAULT_MAX_SPLATS),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            crop_box: None,
+        }
+    }
+
+    // The opacity and scale multiplier for n copies of a splat, such that together they
+    // render (approximately) the same as the original splat.
+    fn split_opacity(&self, opacity: f32, n: u32) -> (f32, f32) {
+        let n = n.min(MAX_COPIES);
+        let opacity = opacity as f64;
+        let new_opac = (1.0 - (1.0 - opacity).powf(1.0 / n as f64))
+            .clamp(self.config.min_opacity as f64, 1.0 - 1e-6);
+
+        let mut denom = 0.0;
+        for i in 1..=n {
+            for k in 0..i {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                denom += binomial(i - 1, k) * sign / ((k + 1) as f64).sqrt()
+                    * new_opac.powi(k as i32 + 1);
+            }
+        }
+        let scale = opacity / denom.max(1e-12);
+        (new_opac as f32, scale as f32)
+    }
+
+    // Make each target splat a copy of its source splat, and adjust the opacity and scale of
+    // the copies and sources. The optimizer state of all these splats is reset.
+    fn copy_splats<B: AutodiffBackend>(
+        &self,
+        splats: &mut Splats<B>,
+        record: &mut OptimRecord<B>,
+        opacities: &[f32],
+        sources: &[usize],
+        targets: &[usize],
+    ) {
+        let num_splats = splats.num_splats();
+        let device = splats.means.device();
+
+        let mut copies = vec![0; num_splats];
+        for &s in sources {
+            copies[s] += 1;
+        }
+
+        let mut gather: Vec<i32> = (0..num_splats as i32).collect();
+        for (&t, &s) in targets.iter().zip(sources) {
+            gather[t] = s as i32;
+        }
+
+        let mut changed = vec![false; num_splats];
+        let mut raw_opac = vec![0.0; num_splats];
+        let mut log_scale_offset = vec![0.0; num_splats];
+        for &i in sources.iter().chain(targets) {
+            let src = gather[i] as usize;
+            let (opac, scale) = self.split_opacity(opacities[src], copies[src] + 1);
+            changed[i] = true;
+            raw_opac[i] = inverse_sigmoid(opac);
+            log_scale_offset[i] = scale.ln();
+        }
+
+        let gather = Tensor::<B, 1, Int>::from_data(TensorData::new(gather, [num_splats]), &device);
+        let changed =
+            Tensor::<B, 1, Bool>::from_data(TensorData::new(changed, [num_splats]), &device);
+        let raw_opac = Tensor::<B, 1>::from_data(TensorData::new(raw_opac, [num_splats]), &device);
+        let log_scale_offset =
+            Tensor::<B, 1>::from_data(TensorData::new(log_scale_offset, [num_splats]), &device)
+                .unsqueeze_dim(1);
+
+        Splats::map_param(&mut splats.means, |x| x.select(0, gather.clone()));
+        Splats::map_param(&mut splats.rotation, |x| x.select(0, gather.clone()));
+        Splats::map_param(&mut splats.sh_coeffs, |x| x.select(0, gather.clone()));
+        Splats::map_param(&mut splats.raw_opacity, |x| {
+            x.select(0, gather.clone())
+                .mask_where(changed.clone(), raw_opac.clone())
+        });
+        Splats::map_param(&mut splats.log_scales, |x| {
+            x.select(0, gather.clone()) + log_scale_offset.clone()
+        });
+
+        let keep = changed.bool_not().float().inner();
+        map_opt_state::<B, 2>(record, splats.means.id, |x| {
+            x * keep.clone().unsqueeze_dim(1)
+        });
+        map_opt_state::<B, 2>(record, splats.rotation.id, |x| {
+            x * keep.clone().unsqueeze_dim(1)
+        });
+        map_opt_state::<B, 3>(record, splats.sh_coeffs.id, |x| {
+            x * keep.clone().reshape([num_splats, 1, 1])
+        });
+        map_opt_state::<B, 1>(record, splats.raw_opacity.id, |x| x * keep.clone());
+        map_opt_state::<B, 2>(record, splats.log_scales.id, |x| {
+            x * keep.clone().unsqueeze_dim(1)
+        });
+    }
+
+    async fn refine_splats<B: AutodiffBackend>(
+        &mut self,
+        splats: Splats<B>,
+        record: &mut OptimRecord<B>,
+    ) -> (Splats<B>, RefineStats) {
+        let mut splats = splats;
+        let device = splats.means.device();
+
+        // Move dead splats onto live ones.
+        let opacities = read_opacities(&splats, self.crop_box.as_ref()).await;
+        let dead: Vec<usize> = (0..opacities.len())
+            .filter(|&i| opacities[i] <= self.config.min_opacity)
+            .collect();
+        let weights = opacities
+            .iter()
+            .map(|&o| if o > self.config.min_opacity { o } else { 0.0 });
+
+        let mut num_relocated = 0;
+        if !dead.is_empty() {
+            // If there are no live splats at all, there's nothing to relocate to.
+            if let Ok(dist) = WeightedIndex::new(weights) {
+                let sources: Vec<usize> = dead.iter().map(|_| dist.sample(&mut self.rng)).collect();
+                self.copy_splats(&mut splats, record, &opacities, &sources, &dead);
+                num_relocated = dead.len();
+            }
+        }
+
+        // Grow the number of splats up to the budget.
+        let num_splats = splats.num_splats();
+        let target =
+            ((num_splats as f32 * (1.0 + self.config.grow_rate)) as usize).min(self.max_splats);
+        let num_added = target.saturating_sub(num_splats);
+
+        if num_added > 0 {
+            let mut opacities = read_opacities(&splats, self.crop_box.as_ref()).await;
+
+            if let Ok(dist) = WeightedIndex::new(&opacities) {
+                let sources: Vec<usize> =
+                    (0..num_added).map(|_| dist.sample(&mut self.rng)).collect();
+                let source_inds = Tensor::<B, 1, Int>::from_data(
+                    TensorData::new(
+                        sources.iter().map(|&s| s as i32).collect::<Vec<_>>(),
+                        [num_added],
+                    ),
+                    &device,
+                );
+
+                let means = splats.means.val().select(0, source_inds.clone());
+                let rotations = splats.rotation.val().select(0, source_inds.clone());
+                let sh_coeffs = splats.sh_coeffs.val().select(0, source_inds.clone());
+                let raw_opacities = splats.raw_opacity.val().select(0, source_inds.clone());
+                let log_scales = splats.log_scales.val().select(0, source_inds);
+
+                // New splats start without any momentum.
+                append_opt_state(record, &splats, num_added);
+                concat_splats(
+                    &mut splats,
+                    means,
+                    rotations,
+                    sh_coeffs,
+                    raw_opacities,
+                    log_scales,
+                );
+
+                let copied: Vec<f32> = sources.iter().map(|&s| opacities[s]).collect();
+                opacities.extend(copied);
+                let targets: Vec<usize> = (num_splats..num_splats + num_added).collect();
+                self.copy_splats(&mut splats, record, &opacities, &sources, &targets);
+            }
+        }
+
+        let stats = RefineStats {
+            num_split: 0,
+            num_cloned: splats.num_splats() - num_splats,
+            num_transparent_pruned: 0,
+            num_scale_pruned: 0,
+            num_crop_pruned: 0,
+            num_relocated,
+            num_over_budget: 0,
+            max_splats: Some(self.max_splats),
+        };
+
+        (splats, stats)
+    }
+}
+
+// Splats outside of the crop box count as transparent, so they get relocated into the box.
+async fn read_opacities<B: AutodiffBackend>(
+    splats: &Splats<B>,
+    crop_box: Option<&CropBox>,
+) -> Vec<f32> {
+    let mut opacity = splats.opacity();
+    if let Some(crop_box) = crop_box {
+        opacity = opacity * splats.crop_mask(crop_box).float();
+    }
+    opacity
+        .into_data_async()
+        .await
+        .convert::<f32>()
+        .to_vec()
+        .expect("Opacities were converted to f32")
+}
+
+impl<B: AutodiffBackend> RefineStrategy<B> for McmcStrategy {
+    fn regularization(&self, splats: &Splats<B>) -> Option<Tensor<B, 1>> {
+        Some(
+            splats.opacity().mean() * self.config.opacity_reg
+                + splats.scales().mean() * self.config.scale_reg,
+        )
+    }
+
+    fn observe(&mut self, _iter: u32, _views: &[ViewGrads<B>], _img_size: glam::UVec2) {}
+
+    fn post_step(&mut self, iter: u32, splats: Splats<B>, lr_mean: f64) -> Splats<B> {
+        let mut splats = splats;
+        let num_splats = splats.num_splats();
+        let device = splats.means.device();
+
+        // This runs every step before any refinement, so the refinement of this step draws
+        // from the same stream.
+        self.rng = StdRng::seed_from_u64(self.seed ^ iter as u64);
+
+        // Only really perturb splats that are close to transparent.
+        let gate =
+            sigmoid((splats.opacity().neg() + self.config.min_opacity) * 100.0).unsqueeze_dim(1);
+        // The samples are drawn on the CPU and uploaded, 3 floats per splat every step, which is
+        // about 12MB per step at a million splats. The device rng is shared by all random
+        // tensors, so it can't give each step its own stream, which resumed runs rely on.
+        let noise = Tensor::<B, 2>::from_data(
+            TensorData::new(
+                normal_samples(&mut self.rng, num_splats * 3),
+                [num_splats, 3],
+            ),
+            &device,
+        ) * gate
+            * (self.config.noise_lr * lr_mean);
+
+        // Shape the noise by the covariance of each splat, R S^2 R^T.
+        let rotation = splats.rotation.val();
+        let conjugate = rotation.clone()
+            * Tensor::<B, 1>::from_floats([1.0, -1.0, -1.0, -1.0], &device).unsqueeze();
+        let scales = splats.scales();
+        let noise = quaternion_vec_multiply(
+            rotation,
+            quaternion_vec_multiply(conjugate, noise) * scales.clone() * scales,
+        );
+
+        Splats::map_param(&mut splats.means, |m| m + noise.clone());
+        splats
+    }
+
+    fn set_crop_box(&mut self, crop_box: Option<CropBox>) {
+        self.crop_box = crop_box;
+    }
+
+    fn refine<'a>(
+        &'a mut self,
+        _iter: u32,
+        splats: Splats<B>,
+        record: &'a mut OptimRecord<B>,
+    ) -> RefineFuture<'a, (Splats<B>, RefineStats)> {
+        Box::pin(self.refine_splats(splats, record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::train::tests::{test_splats, to_vec, TestBackend};
+
+    #[test]
+    fn test_noise_depends_on_step() {
+        let config = McmcConfig::new();
+        let mut splats = test_splats(16);
+        // Nearly transparent, so the noise isn't gated off.
+        Splats::map_param(&mut splats.raw_opacity, |x| {
+            x.zeros_like() + inverse_sigmoid(0.001)
+        });
+
+        let mut strategy = McmcStrategy::new(&config, None, 42);
+        let step = |strategy: &mut McmcStrategy, iter| {
+            let stepped: Splats<TestBackend> = strategy.post_step(iter, splats.clone(), 1e-5);
+            to_vec(stepped.means.val())
+        };
+        let first = step(&mut strategy, 3);
+        let second = step(&mut strategy, 4);
+        assert_ne!(first, to_vec(splats.means.val()));
+        assert_ne!(first, second);
+
+        // A new strategy, like one restored from a checkpoint, draws the same noise at a step.
+        let mut resumed = McmcStrategy::new(&config, None, 42);
+        assert_eq!(step(&mut resumed, 4), second);
+    }
+}
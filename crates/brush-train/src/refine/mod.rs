@@ -0,0 +1,131 @@
+// Densification strategies. A strategy owns whatever statistics it needs, and decides
+// which splats to add, move or remove during refinement.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::Result;
+use brush_render::bounding_box::CropBox;
+use brush_render::gaussian_splats::Splats;
+use brush_render::{AutodiffBackend, Backend, RenderAux};
+use burn::config::Config;
+use burn::tensor::Tensor;
+
+use crate::train::{OptimRecord, RefineStats, TrainConfig};
+
+pub mod adc;
+pub mod mcmc;
+
+pub use adc::AdcStrategy;
+pub use mcmc::{McmcConfig, McmcStrategy};
+
+pub type RefineFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Config, Debug)]
+pub enum RefineStrategyConfig {
+    // Adaptive density control from the original 3DGS paper. This clones and splits
+    // splats with large screenspace gradients, and is configured by the densify_* and cull_*
+    // fields of the TrainConfig.
+    Adc,
+    // Treat the splats as MCMC samples (3DGS-MCMC). This keeps a fixed budget of splats,
+    // relocating dead splats rather than pruning them.
+    Mcmc(McmcConfig),
+}
+
+/// The screenspace gradients of the splats for a single rendered view.
+pub struct ViewGrads<B: Backend> {
+    /// The [N, 4] gradient of the xy dummy, see [`brush_render::Backend::render_splats`].
+    pub xy_grads: Tensor<B, 2>,
+    pub aux: RenderAux<B>,
+}
+
+pub trait RefineStrategy<B: AutodiffBackend>: Send {
+    /// Extra loss term to regularize the splats, added to the loss of every step.
+    fn regularization(&self, _splats: &Splats<B>) -> Option<Tensor<B, 1>> {
+        None
+    }
+
+    /// Called every step after the backward pass, with the gradients of each view in the batch.
+    fn observe(&mut self, iter: u32, views: &[ViewGrads<B>], img_size: glam::UVec2);
+
+    /// Called every step after the optimizer has updated the splats.
+    fn post_step(&mut self, _iter: u32, splats: Splats<B>, _lr_mean: f64) -> Splats<B> {
+        splats
+    }
+
+    /// Add, move and remove splats. The optimizer state in `record` needs to be kept
+    /// in sync with the returned splats.
+    fn refine<'a>(
+        &'a mut self,
+        iter: u32,
+        splats: Splats<B>,
+        record: &'a mut OptimRecord<B>,
+    ) -> RefineFuture<'a, (Splats<B>, RefineStats)>;
+
+    /// Called when the crop box of the config changes. Splats outside of it should be removed
+    /// (or relocated) on the next refinement.
+    fn set_crop_box(&mut self, _crop_box: Option<CropBox>) {}
+
+    /// Named tensors holding the state of this strategy, for checkpointing.
+    fn state(&self) -> Vec<(String, Tensor<B, 1>)> {
+        vec![]
+    }
+
+    /// Restore a tensor previously returned by [`Self::state`].
+    fn load_state(&mut self, name: &str, _tensor: Tensor<B, 1>) -> Result<()> {
+        anyhow::bail!("Unknown refine state {name}")
+    }
+}
+
+pub fn create_strategy<B: AutodiffBackend>(
+    config: &TrainConfig,
+    num_points: usize,
+    device: &B::Device,
+) -> Box<dyn RefineStrategy<B>> {
+    let mut strategy: Box<dyn RefineStrategy<B>> = match &config.refine_strategy {
+        RefineStrategyConfig::Adc => Box::new(AdcStrategy::new(config, num_points, device)),
+        RefineStrategyConfig::Mcmc(mcmc) => {
+            Box::new(McmcStrategy::new(mcmc, config.max_splats, config.seed))
+        }
+    };
+    strategy.set_crop_box(config.crop_box);
+    strategy
+}
+
+fn cross<B: Backend>(a: Tensor<B, 2>, b: Tensor<B, 2>) -> Tensor<B, 2> {
+    let n = a.dims()[0];
+    let [ax, ay, az] = [0, 1, 2].map(|i| a.clone().slice([0..n, i..i + 1]));
+    let [bx, by, bz] = [0, 1, 2].map(|i| b.clone().slice([0..n, i..i + 1]));
+    Tensor::cat(
+        vec![
+            ay.clone() * bz.clone() - az.clone() * by.clone(),
+            az * bx.clone() - ax.clone() * bz,
+            ax * by - ay * bx,
+        ],
+        1,
+    )
+}
+
+// Rotates each vector by its corresponding (w, x, y, z) quaternion.
+pub(crate) fn quaternion_vec_multiply<B: Backend>(
+    quaternions: Tensor<B, 2>,
+    vectors: Tensor<B, 2>,
+) -> Tensor<B, 2> {
+    let num_points = quaternions.dims()[0];
+
+    // Raw rotations aren't guaranteed to be normalized.
+    let norm = quaternions
+        .clone()
+        .powf_scalar(2.0)
+        .sum_dim(1)
+        .sqrt()
+        .clamp_min(1e-6);
+    let quaternions = quaternions / norm;
+
+    let w = quaternions.clone().slice([0..num_points, 0..1]);
+    let q = quaternions.slice([0..num_points, 1..4]);
+
+    // v' = v + w * t + q x t, where t = 2 * (q x v).
+    let t = cross(q.clone(), vectors.clone()) * 2.0;
+    vectors + w * t.clone() + cross(q, t)
+}
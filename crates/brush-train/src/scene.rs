@@ -15,8 +15,31 @@ pub struct SceneView {
     pub name: String,
     pub camera: Camera,
     pub image: Arc<image::DynamicImage>,
+    // Optional grayscale mask, the same size as the image. Pixels where the mask is zero
+    // are excluded from the loss.
+    pub mask: Option<Arc<image::DynamicImage>>,
+    // Optional depth map, the same size as the image. Pixels with a depth of zero
+    // have no valid depth.
+    pub depth: Option<Arc<DepthImage>>,
+    // Optional sparse points seen in this view, eg. from structure from motion.
+    pub sparse_points: Option<Arc<Vec<SparsePoint>>>,
 }
 
+// A 3D point and where it was observed in an image.
+#[derive(Debug, Clone, Copy)]
+pub struct SparsePoint {
+    // Position in the image in [0, 1] uv coordinates, so it doesn't depend on the image
+    // resolution.
+    pub uv: glam::Vec2,
+    // World space position.
+    pub xyz: glam::Vec3,
+    // Reprojection error of the point, in pixels.
+    pub error: f32,
+}
+
+// Camera space depth per pixel, in world units.
+pub type DepthImage = image::ImageBuffer<image::Luma<f32>, Vec<f32>>;
+
 // Encapsulates a multi-view scene including cameras and the splats.
 // Also provides methods for checkpointing the training process.
 #[derive(Debug, Clone)]
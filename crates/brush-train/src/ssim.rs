@@ -40,12 +40,31 @@ impl<B: Backend> Ssim<B> {
     }
 
     pub fn ssim(&self, img1: Tensor<B, 4>, img2: Tensor<B, 4>) -> Tensor<B, 1> {
+        self.ssim_map(img1, img2).mean()
+    }
+
+    // SSIM averaged over the pixels where the [N, H, W, 1] mask is set.
+    pub fn masked_ssim(
+        &self,
+        img1: Tensor<B, 4>,
+        img2: Tensor<B, 4>,
+        mask: Tensor<B, 4>,
+    ) -> Tensor<B, 1> {
+        let channels = img1.dims()[3];
+        let mask = mask.permute([0, 3, 1, 2]);
+        let ssim_map = self.ssim_map(img1, img2) * mask.clone();
+        ssim_map.sum() / (mask.sum() * channels as f32).clamp_min(1e-6)
+    }
+
+    // Per pixel SSIM, as an [N, C, H, W] tensor.
+    pub fn ssim_map(&self, img1: Tensor<B, 4>, img2: Tensor<B, 4>) -> Tensor<B, 4> {
         // Images are [N, H, W, C], need them as [N, C, H, W].
         let img1 = img1.permute([0, 3, 1, 2]);
         let img2 = img2.permute([0, 3, 1, 2]);
 
         let [channels, _, _, window_size] = self.weights.dims();
-        let padding = window_size.div_ceil(2);
+        // Keep the output the same size as the input, so it lines up with masks.
+        let padding = window_size / 2;
         let conv_options = ConvOptions::new([1, 1], [padding, padding], [1, 1], channels);
         let mu_x = conv2d(
             img1.clone(),
@@ -96,8 +115,49 @@ impl<B: Backend> Ssim<B> {
         let c1: f32 = 0.01f32.powf(2.0);
         let c2: f32 = 0.03f32.powf(2.0);
 
-        let ssim_map = ((mu_xy * 2.0 + c1) * (sigma_xy * 2.0 + c2))
-            / ((mu_xx + mu_yy + c1) * (sigma_xx + sigma_yy + c2));
-        ssim_map.mean()
+        ((mu_xy * 2.0 + c1) * (sigma_xy * 2.0 + c2))
+            / ((mu_xx + mu_yy + c1) * (sigma_xx + sigma_yy + c2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use burn::tensor::{Distribution, Int};
+
+    #[test]
+    fn test_masked_ssim() {
+        let device = NdArrayDevice::Cpu;
+        let ssim = Ssim::<NdArray>::new(11, 3, &device);
+
+        let img1 = Tensor::<NdArray, 4>::random([1, 32, 32, 3], Distribution::Default, &device);
+        let img2 = Tensor::<NdArray, 4>::random([1, 32, 32, 3], Distribution::Default, &device);
+        // Change the columns on the right, more than a window away from the masked in columns.
+        let changed = img2.clone().slice_assign(
+            [0..1, 0..32, 24..32, 0..3],
+            Tensor::ones([1, 32, 8, 3], &device),
+        );
+        let mask = Tensor::<NdArray, 1, Int>::arange(0..32, &device)
+            .lower_elem(8)
+            .float()
+            .reshape([1, 1, 32, 1])
+            .repeat_dim(1, 32);
+
+        // Pixels outside of the mask don't change the masked SSIM, unlike the plain SSIM.
+        let masked = ssim
+            .masked_ssim(img1.clone(), img2.clone(), mask.clone())
+            .into_scalar();
+        let masked_changed = ssim
+            .masked_ssim(img1.clone(), changed.clone(), mask)
+            .into_scalar();
+        assert!((masked - masked_changed).abs() < 1e-6);
+        let full = ssim.ssim(img1.clone(), img2.clone()).into_scalar();
+        assert!((full - ssim.ssim(img1.clone(), changed).into_scalar()).abs() > 1e-3);
+
+        // A full mask is the same as no mask.
+        let ones = Tensor::ones([1, 32, 32, 1], &device);
+        let masked_full = ssim.masked_ssim(img1, img2, ones).into_scalar();
+        assert!((masked_full - full).abs() < 1e-5);
     }
 }
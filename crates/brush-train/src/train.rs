@@ -1,57 +1,92 @@
 use anyhow::Result;
-use brush_render::gaussian_splats::{inverse_sigmoid, Splats};
+use brush_render::bounding_box::CropBox;
+use brush_render::gaussian_splats::Splats;
 use brush_render::{AutodiffBackend, Backend, RenderAux};
 use burn::lr_scheduler::exponential::{ExponentialLrScheduler, ExponentialLrSchedulerConfig};
 use burn::lr_scheduler::LrScheduler;
+use burn::module::ParamId;
 use burn::optim::adaptor::OptimizerAdaptor;
-use burn::optim::Adam;
-use burn::tensor::{Bool, Distribution, Int};
+use burn::optim::record::AdaptorRecord;
+use burn::optim::{Adam, AdamState};
+use burn::tensor::{Bool, Int, TensorData};
 use burn::{
     config::Config,
     optim::{AdamConfig, GradientsParams, Optimizer},
     tensor::Tensor,
 };
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashMap;
 use tracing::trace_span;
 
-use crate::scene::SceneView;
+use crate::appearance::Appearance;
+use crate::pose::{correct_camera, PoseCorrection};
+use crate::refine::{create_strategy, RefineStrategy, RefineStrategyConfig, ViewGrads};
+use crate::scene::{Scene, SceneView};
 use crate::ssim::Ssim;
 
+pub type OptimRecord<B> =
+    HashMap<ParamId, AdaptorRecord<Adam<<B as AutodiffBackend>::InnerBackend>, B>>;
+
+// Mixed into the seed of the random backgrounds. The MCMC noise is seeded by the step as
+// well, and this keeps the two from drawing the same numbers.
+const BACKGROUND_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
 #[derive(Config)]
 pub struct TrainConfig {
+    // Number of views rendered and averaged per training step. All images in a batch
+    // need to have the same resolution.
+    #[config(default = 1)]
+    pub batch_size: usize,
+
     // period of steps where refinement is turned off
     #[config(default = 500)]
-    warmup_steps: u32,
+    pub(crate) warmup_steps: u32,
 
     // period of steps where gaussians are culled and densified
     #[config(default = 100)]
-    refine_every: u32,
+    pub(crate) refine_every: u32,
 
     #[config(default = 15000)]
-    max_refine_step: u32,
+    pub(crate) max_refine_step: u32,
 
     #[config(default = 0.01)]
-    reset_alpha_value: f32,
+    pub(crate) reset_alpha_value: f32,
 
     // threshold of opacity for culling gaussians. One can set it to a lower value (e.g. 0.005) for higher quality
     #[config(default = 0.005)]
-    cull_alpha_thresh: f32,
+    pub(crate) cull_alpha_thresh: f32,
 
     // threshold of scale for culling huge gaussians
     #[config(default = 5.0)]
-    cull_scale_thresh: f32,
+    pub(crate) cull_scale_thresh: f32,
 
     // Every this many refinement steps, reset the alpha
     #[config(default = 30)]
-    reset_alpha_every_refine: u32,
+    pub(crate) reset_alpha_every_refine: u32,
 
     // threshold of positional gradient norm for densifying gaussians
-    // TODO: Abs grad.
     #[config(default = 0.0002)]
-    densify_grad_thresh: f32,
+    pub(crate) densify_grad_thresh: f32,
+
+    // Use the summed absolute per-pixel gradients for densification (AbsGS), rather than the
+    // norm of the summed gradient. These are larger, so this needs a higher threshold (e.g. 0.0008).
+    #[config(default = false)]
+    pub(crate) densify_abs_grad: bool,
 
     // below this size, gaussians are *duplicated*, otherwise split.
     #[config(default = 0.005)]
-    densify_size_thresh: f32,
+    pub(crate) densify_size_thresh: f32,
+
+    // Max number of splats refinement is allowed to create. When more splats would be
+    // added, only the candidates with the highest gradients are kept.
+    pub(crate) max_splats: Option<usize>,
+
+    // How splats are added and removed during refinement.
+    #[config(default = "RefineStrategyConfig::Adc")]
+    pub(crate) refine_strategy: RefineStrategyConfig,
+
+    // Splats outside of this box are removed during refinement (or relocated, with MCMC).
+    pub crop_box: Option<CropBox>,
 
     #[config(default = 0.1)]
     ssim_weight: f32,
@@ -59,10 +94,37 @@ pub struct TrainConfig {
     #[config(default = 11)]
     ssim_window_size: usize,
 
+    // Steps at which the training resolution doubles. Training starts at full resolution
+    // divided by 2 for each entry, eg. [500, 1000] trains at 1/4 resolution until step 500,
+    // then at 1/2 until step 1000, and at full resolution after that.
+    #[config(default = "vec![]")]
+    pub(crate) resolution_schedule: Vec<u32>,
+
+    // Weight of the L1 loss between the rendered expected depth and the depth maps of
+    // the dataset, if it has any. Pixels without a valid depth are ignored.
+    #[config(default = 0.0)]
+    pub(crate) depth_loss_weight: f32,
+
+    // Weight of the loss between the rendered expected depth and the depth of the sparse
+    // points seen in each view (eg. the COLMAP points). Points with a large reprojection
+    // error count less.
+    #[config(default = 0.0)]
+    pub(crate) sparse_depth_loss_weight: f32,
+
+    // For views with alpha, composite both the render and the ground truth over a background
+    // colour before comparing them, rather than comparing RGBA. This stops splats from
+    // learning dark semi-transparent halos around objects.
+    #[config(default = false)]
+    pub(crate) composite_background: bool,
+
+    // The colour to composite over. When not set, a random colour is picked every step.
+    pub(crate) background_color: Option<[f32; 3]>,
+
     #[config(default = true)]
     scale_mean_lr_by_extent: bool,
 
-    // Learning rates.
+    // Learning rates. The mean learning rate decays by 10x over 30k steps.
+    #[config(default = "ExponentialLrSchedulerConfig::new(3e-4, 1e-1f64.powf(1.0 / 30000.0))")]
     lr_mean: ExponentialLrSchedulerConfig,
 
     #[config(default = 0.9999)]
@@ -72,6 +134,11 @@ pub struct TrainConfig {
     #[config(default = 0.006)]
     lr_coeffs_dc: f64,
 
+    // Train with only the base SH band at first, and unlock one more band every this many
+    // steps, up to the degree of the splats (eg. 1000). With 0, all bands are used from the start.
+    #[config(default = 0)]
+    pub(crate) sh_degree_interval: u32,
+
     // How much to divide the learning rate by for higher SH orders.
     #[config(default = 15.0)]
     lr_coeffs_sh_scale: f64,
@@ -85,23 +152,54 @@ pub struct TrainConfig {
     #[config(default = 0.01)]
     lr_rotation: f64,
 
+    // Learn an affine colour transform for each training view, to compensate for exposure
+    // and white balance changes between the images.
+    #[config(default = false)]
+    pub(crate) per_view_appearance: bool,
+
+    #[config(default = 0.001)]
+    lr_appearance: f64,
+
+    // Learn a correction of the camera pose of each training view.
+    #[config(default = false)]
+    pub(crate) optimize_poses: bool,
+
+    #[config(default = 1e-5)]
+    lr_pose: f64,
+
     #[config(default = 42)]
-    seed: u64,
+    pub seed: u64,
+}
+
+impl TrainConfig {
+    /// How much the training images are downscaled at the given step, see `resolution_schedule`.
+    pub fn resolution_downscale(&self, iter: u32) -> u32 {
+        let coarse_levels = self
+            .resolution_schedule
+            .iter()
+            .filter(|&&step| iter < step)
+            .count();
+        1 << coarse_levels
+    }
 }
 
 impl Default for TrainConfig {
     fn default() -> Self {
-        let decay_steps = 30000;
-        let lr_max = 3e-4;
-        let decay = 1e-1f64.powf(1.0 / decay_steps as f64);
-        TrainConfig::new(ExponentialLrSchedulerConfig::new(lr_max, decay))
+        TrainConfig::new()
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct SceneBatch<B: Backend> {
     pub gt_images: Tensor<B, 4>,
+    // [N, H, W, 1] masks of the pixels to train on. None if no view in the batch has a mask.
+    pub gt_masks: Option<Tensor<B, 4>>,
+    // [N, H, W, 1] depth maps, zero where there is no valid depth. None if no view in the
+    // batch has a depth map.
+    pub gt_depths: Option<Tensor<B, 4>>,
     pub gt_views: Vec<SceneView>,
+    // Index of each view in the training scene.
+    pub gt_view_ids: Vec<usize>,
     pub scene_extent: f64,
 }
 
@@ -111,6 +209,12 @@ pub struct RefineStats {
     pub num_cloned: usize,
     pub num_transparent_pruned: usize,
     pub num_scale_pruned: usize,
+    // Number of splats removed for being outside of the crop box.
+    pub num_crop_pruned: usize,
+    pub num_relocated: usize,
+    // Number of candidates that weren't added because of the splat budget.
+    pub num_over_budget: usize,
+    pub max_splats: Option<usize>,
 }
 
 #[derive(Clone)]
@@ -135,57 +239,24 @@ where
 {
     pub iter: u32,
 
-    config: TrainConfig,
+    pub(crate) config: TrainConfig,
 
-    sched_mean: ExponentialLrScheduler,
-    optim: OptimizerAdaptor<Adam<B::InnerBackend>, Splats<B>, B>,
-    opt_config: AdamConfig,
+    pub(crate) sched_mean: ExponentialLrScheduler,
+    pub(crate) optim: OptimizerAdaptor<Adam<B::InnerBackend>, Splats<B>, B>,
+    pub(crate) opt_config: AdamConfig,
 
-    // Helper tensors for accumulating the viewspace_xy gradients and the number
-    // of observations per gaussian. Used in pruning and densification.
-    grad_2d_accum: Tensor<B, 1>,
-    xy_grad_counts: Tensor<B, 1, Int>,
+    pub(crate) strategy: Box<dyn RefineStrategy<B>>,
 
-    ssim: Ssim<B>,
-}
+    // Only used when per_view_appearance is enabled. Created on the first step, as that's
+    // when the view ids are known.
+    pub(crate) appearance: Option<Appearance<B>>,
+    pub(crate) appearance_optim: OptimizerAdaptor<Adam<B::InnerBackend>, Appearance<B>, B>,
+
+    // Only used when optimize_poses is enabled, created on the first step like the appearance.
+    pub(crate) pose: Option<PoseCorrection<B>>,
+    pub(crate) pose_optim: OptimizerAdaptor<Adam<B::InnerBackend>, PoseCorrection<B>, B>,
 
-fn quaternion_vec_multiply<B: Backend>(
-    quaternions: Tensor<B, 2>,
-    vectors: Tensor<B, 2>,
-) -> Tensor<B, 2> {
-    let num_points = quaternions.dims()[0];
-
-    // Extract components of quaternions
-    let qw = quaternions.clone().slice([0..num_points, 0..1]);
-    let qx = quaternions.clone().slice([0..num_points, 1..2]);
-    let qy = quaternions.clone().slice([0..num_points, 2..3]);
-    let qz = quaternions.clone().slice([0..num_points, 3..4]);
-
-    // Extract components of vectors
-    let vx = vectors.clone().slice([0..num_points, 0..1]);
-    let vy = vectors.clone().slice([0..num_points, 1..2]);
-    let vz = vectors.clone().slice([0..num_points, 2..3]);
-
-    // Compute intermediate terms
-    let term1 = qw.clone() * vx.clone() + qy.clone() * vz.clone() - qz.clone() * vy.clone();
-    let term2 = qw.clone() * vy.clone() - qx.clone() * vz.clone() + qz.clone() * vx.clone();
-    let term3 = qw.clone() * vz.clone() + qx.clone() * vy.clone() - qy.clone() * vx.clone();
-    let term4 = qx.clone() * vx.clone() + qy.clone() * vy.clone() + qz.clone() * vz.clone();
-
-    // Compute final result
-    let rx = vx
-        + (qw.clone() * term1.clone() + qx.clone() * term4.clone() - qy.clone() * term3.clone()
-            + qz.clone() * term2.clone())
-            * 2.0;
-    let ry = vy
-        + (qw.clone() * term2.clone() - qx.clone() * term3.clone()
-            + qy.clone() * term4.clone()
-            + qz.clone() * term1.clone())
-            * 2.0;
-    let rz = vz
-        + (qw * term3.clone() + qx * term2.clone() - qy * term1.clone() + qz * term4.clone()) * 2.0;
-
-    Tensor::cat(vec![rx, ry, rz], 1)
+    ssim: Ssim<B>,
 }
 
 impl<B: AutodiffBackend> SplatTrainer<B>
@@ -203,21 +274,105 @@ where
             sched_mean: config.lr_mean.init(),
             optim,
             opt_config,
-            grad_2d_accum: Tensor::zeros([num_points], device),
-            xy_grad_counts: Tensor::zeros([num_points], device),
+            strategy: create_strategy(config, num_points, device),
+            appearance: None,
+            appearance_optim: opt_config.init(),
+            pose: None,
+            pose_optim: opt_config.init(),
             ssim,
         }
     }
 
-    fn reset_stats(&mut self, num_points: usize, device: &B::Device) {
-        self.grad_2d_accum = Tensor::zeros([num_points], device);
-        self.xy_grad_counts = Tensor::zeros([num_points], device);
+    pub fn config(&self) -> &TrainConfig {
+        &self.config
     }
 
-    pub(crate) fn reset_opacity(&self, splats: &mut Splats<B>) {
-        Splats::map_param(&mut splats.raw_opacity, |op| {
-            Tensor::zeros_like(&op) + inverse_sigmoid(self.config.reset_alpha_value)
-        });
+    /// The learned per view colour transforms, if enabled. These are indexed by the
+    /// position of the view in the training scene.
+    pub fn appearance(&self) -> Option<&Appearance<B>> {
+        self.appearance.as_ref()
+    }
+
+    // Make sure there is a colour transform for every view up to num_views.
+    fn grow_appearance(&mut self, num_views: usize, device: &B::Device) {
+        match &mut self.appearance {
+            None => self.appearance = Some(Appearance::new(num_views, device)),
+            Some(appearance) if appearance.num_views() < num_views => {
+                let count = num_views - appearance.num_views();
+                let mut record = self.appearance_optim.to_record();
+                map_opt_state::<B, 3>(&mut record, appearance.transforms.id, |x| {
+                    append_zero_rows(x, count)
+                });
+                appearance.grow(num_views);
+                self.appearance_optim = self.opt_config.init().load_record(record);
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// The learned camera pose corrections, if enabled. These are indexed by the position
+    /// of the view in the training scene.
+    pub fn pose_correction(&self) -> Option<&PoseCorrection<B>> {
+        self.pose.as_ref()
+    }
+
+    /// The training scene, with the learned pose corrections applied to its cameras.
+    pub async fn refined_scene(&self, scene: &Scene) -> Scene {
+        let Some(pose) = &self.pose else {
+            return scene.clone();
+        };
+        let ids: Vec<usize> = (0..pose.num_views().min(scene.views.len())).collect();
+        let corrections = pose.read_corrections(&ids).await;
+
+        let views = scene
+            .views
+            .iter()
+            .enumerate()
+            .map(|(i, view)| {
+                let mut view = view.clone();
+                // Views that haven't been trained on yet don't have a correction.
+                if let Some(&correction) = corrections.get(i) {
+                    view.camera = correct_camera(&view.camera, correction);
+                }
+                view
+            })
+            .collect();
+        Scene::new(views)
+    }
+
+    fn grow_pose(&mut self, num_views: usize, device: &B::Device) {
+        match &mut self.pose {
+            None => self.pose = Some(PoseCorrection::new(num_views, device)),
+            Some(pose) if pose.num_views() < num_views => {
+                let count = num_views - pose.num_views();
+                let mut record = self.pose_optim.to_record();
+                map_opt_state::<B, 2>(&mut record, pose.deltas.id, |x| append_zero_rows(x, count));
+                pose.grow(num_views);
+                self.pose_optim = self.opt_config.init().load_record(record);
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// The SH degree used for rendering at the current step, see `sh_degree_interval`.
+    pub fn active_sh_degree(&self) -> u32 {
+        if self.config.sh_degree_interval == 0 {
+            u32::MAX
+        } else {
+            self.iter / self.config.sh_degree_interval
+        }
+    }
+
+    /// Change the crop box of the config. Splats outside of it are removed from the next
+    /// refinement on.
+    pub fn set_crop_box(&mut self, crop_box: Option<CropBox>) {
+        self.config.crop_box = crop_box;
+        self.strategy.set_crop_box(crop_box);
+    }
+
+    /// Use a custom refine strategy, instead of the one from the config.
+    pub fn set_strategy(&mut self, strategy: Box<dyn RefineStrategy<B>>) {
+        self.strategy = strategy;
     }
 
     pub async fn step(
@@ -225,62 +380,203 @@ where
         batch: SceneBatch<B>,
         splats: Splats<B>,
     ) -> Result<(Splats<B>, TrainStepStats<B>), anyhow::Error> {
-        assert!(
-            batch.gt_views.len() == 1,
-            "Bigger batches aren't yet supported"
-        );
-
         let mut splats = splats;
 
         let [batch_size, img_h, img_w, _] = batch.gt_images.dims();
+        let device = batch.gt_images.device();
 
-        let (pred_images, auxes, loss) = {
-            let mut renders = vec![];
-            let mut auxes = vec![];
+        let num_views = batch.gt_view_ids.iter().max().map_or(0, |&id| id + 1);
+        if self.config.per_view_appearance {
+            self.grow_appearance(num_views, &device);
+        }
+        if self.config.optimize_poses {
+            self.grow_pose(num_views, &device);
+        }
+
+        // The renders take their pose from the viewmats. The pose corrections are applied to
+        // these on the device, so they don't have to be read back every step, and get a
+        // gradient through them.
+        let viewmats = batch
+            .gt_views
+            .iter()
+            .map(|view| {
+                Tensor::<B, 2>::from_floats(
+                    view.camera.world_to_local().to_cols_array_2d(),
+                    &device,
+                )
+            })
+            .collect();
+        let viewmats = Tensor::stack(viewmats, 0);
+        let viewmats = match &self.pose {
+            Some(pose) => pose.correct_viewmats(viewmats, &batch.gt_view_ids),
+            None => viewmats,
+        };
 
-            for i in 0..batch.gt_views.len() {
-                let camera = &batch.gt_views[i].camera;
+        let depth_target = batch
+            .gt_depths
+            .clone()
+            .filter(|_| self.config.depth_loss_weight > 0.0);
+
+        let sparse_target = if self.config.sparse_depth_loss_weight > 0.0 {
+            sparse_depth_target(
+                viewmats.clone().detach(),
+                &batch.gt_views,
+                glam::uvec2(img_w as u32, img_h as u32),
+            )
+        } else {
+            None
+        };
+        let render_depth = depth_target.is_some() || sparse_target.is_some();
+        let sh_degree = self.active_sh_degree();
+
+        // This is wrong if the batch has mixed transparent and non-transparent images,
+        // but that's ok for now.
+        let has_alpha = batch.gt_views[0].image.color().has_alpha();
+        let background = (has_alpha && self.config.composite_background).then(|| {
+            self.config.background_color.map_or_else(
+                || {
+                    // Seeded by the step, so resuming from a checkpoint picks the same colours.
+                    let seed = self.config.seed ^ BACKGROUND_SEED ^ self.iter as u64;
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    glam::vec3(rng.gen(), rng.gen(), rng.gen())
+                },
+                glam::Vec3::from,
+            )
+        });
 
-                let (pred_image, aux) =
-                    splats.render(camera, glam::uvec2(img_w as u32, img_h as u32), false);
+        let (pred_images, auxes, xys_dummies, loss) = {
+            let mut renders = vec![];
+            let mut auxes = vec![];
+            let mut xys_dummies = vec![];
+
+            for (i, view) in batch.gt_views.iter().enumerate() {
+                // Give each view its own screenspace dummy, so the gradient statistics
+                // of each view can be read back separately.
+                let mut view_splats = splats.clone();
+                view_splats.xys_dummy =
+                    Tensor::zeros([splats.num_splats(), 4], &device).require_grad();
+
+                let img_size = glam::uvec2(img_w as u32, img_h as u32);
+                let viewmat = viewmats.clone().slice([i..i + 1, 0..4, 0..4]).squeeze(0);
+                let (pred_image, aux) = view_splats.render_with_viewmat(
+                    &view.camera,
+                    viewmat,
+                    img_size,
+                    sh_degree,
+                    background.unwrap_or(glam::Vec3::ZERO),
+                    false,
+                    render_depth,
+                );
 
                 renders.push(pred_image);
                 auxes.push(aux);
+                xys_dummies.push(view_splats.xys_dummy);
             }
 
             let pred_images = Tensor::stack(renders, 0);
 
+            // Split off the weighted depth channel if it was rendered, and normalize it
+            // to get the expected depth.
+            let pred_depths = render_depth.then(|| {
+                let alpha = pred_images
+                    .clone()
+                    .slice([0..batch_size, 0..img_h, 0..img_w, 3..4]);
+                let weighted_depth =
+                    pred_images
+                        .clone()
+                        .slice([0..batch_size, 0..img_h, 0..img_w, 4..5]);
+                weighted_depth / alpha.clamp_min(1e-6)
+            });
+            let pred_images = pred_images.slice([0..batch_size, 0..img_h, 0..img_w, 0..4]);
+
+            // Match the colours of each view before comparing to the ground truth.
+            let pred_images = match &self.appearance {
+                Some(appearance) => appearance.apply(pred_images, &batch.gt_view_ids),
+                None => pred_images,
+            };
+
             let _span = trace_span!("Calculate losses", sync_burn = true).entered();
 
             let pred_rgb = pred_images
                 .clone()
                 .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
 
-            // This is wrong if the batch has mixed transparent and non-transparent images,
-            // but that's ok for now.
-            let pred_compare = if batch.gt_views[0].image.color().has_alpha() {
+            // The render is already composited over the background, so do the same for the
+            // ground truth.
+            let gt_images = match background {
+                Some(background) => {
+                    let gt_alpha =
+                        batch
+                            .gt_images
+                            .clone()
+                            .slice([0..batch_size, 0..img_h, 0..img_w, 3..4]);
+                    let gt_rgb =
+                        batch
+                            .gt_images
+                            .clone()
+                            .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
+                    let background = Tensor::<B, 1>::from_floats(background.to_array(), &device)
+                        .reshape([1, 1, 1, 3]);
+                    gt_rgb * gt_alpha.clone() + (gt_alpha.neg() + 1.0) * background
+                }
+                None => batch.gt_images.clone(),
+            };
+
+            let pred_compare = if has_alpha && background.is_none() {
                 pred_images.clone()
             } else {
                 pred_rgb.clone()
             };
 
-            let loss = (pred_compare - batch.gt_images.clone()).abs().mean();
+            let l1 = (pred_compare - gt_images.clone()).abs();
+            let loss = match &batch.gt_masks {
+                Some(masks) => masked_mean(l1, masks.clone()),
+                None => l1.mean(),
+            };
 
             // Disabled on WASM for now. On WebGPU + Metal this unfortunately has glitches.
             let loss = if self.config.ssim_weight > 0.0 && !cfg!(target_family = "wasm") {
-                let gt_rgb =
-                    batch
-                        .gt_images
-                        .clone()
-                        .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
+                let gt_rgb = gt_images.slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
 
-                let ssim_loss = self.ssim.ssim(pred_rgb, gt_rgb);
+                let ssim_loss = match &batch.gt_masks {
+                    Some(masks) => self.ssim.masked_ssim(pred_rgb, gt_rgb, masks.clone()),
+                    None => self.ssim.ssim(pred_rgb, gt_rgb),
+                };
                 loss * (1.0 - self.config.ssim_weight) - ssim_loss * self.config.ssim_weight
             } else {
                 loss
             };
 
-            (pred_images, auxes, loss)
+            let loss = if let (Some(pred_depths), Some(gt_depths)) = (&pred_depths, depth_target) {
+                let valid = gt_depths.clone().greater_elem(0.0).float();
+                let valid = match &batch.gt_masks {
+                    Some(masks) => valid * masks.clone(),
+                    None => valid,
+                };
+                let depth_loss = masked_mean((pred_depths.clone() - gt_depths).abs(), valid);
+                loss + depth_loss * self.config.depth_loss_weight
+            } else {
+                loss
+            };
+
+            let loss = if let (Some(pred_depths), Some(sparse)) = (pred_depths, sparse_target) {
+                let pred = pred_depths
+                    .reshape([batch_size * img_h * img_w])
+                    .select(0, sparse.pixels);
+                let err = (pred - sparse.depths).abs() * sparse.weights.clone();
+                let sparse_loss = err.sum() / sparse.weights.sum().clamp_min(1e-6);
+                loss + sparse_loss * self.config.sparse_depth_loss_weight
+            } else {
+                loss
+            };
+
+            let loss = if let Some(reg) = self.strategy.regularization(&splats) {
+                loss + reg
+            } else {
+                loss
+            };
+
+            (pred_images, auxes, xys_dummies, loss)
         };
 
         let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
@@ -305,37 +601,22 @@ where
         );
 
         trace_span!("Housekeeping", sync_burn = true).in_scope(|| {
-            // TODO: Burn really should implement +=
-            if self.iter > self.config.warmup_steps {
-                // Get the xy gradient norm from the dummy tensor.
-                let xys_grad = Tensor::from_inner(
-                    splats
-                        .xys_dummy
-                        .grad_remove(&mut grads)
-                        .expect("XY gradients need to be calculated."),
-                );
-
-                let aux = &auxes[0];
-                let gs_ids = Tensor::from_primitive(aux.global_from_compact_gid.clone());
-
-                let [_, h, w, _] = pred_images.dims();
-                let device = batch.gt_images.device();
-                let xys_grad = xys_grad
-                    * Tensor::<_, 1>::from_floats([w as f32 / 2.0, h as f32 / 2.0], &device)
-                        .reshape([1, 2]);
-
-                let xys_grad_norm = xys_grad.powi_scalar(2).sum_dim(1).squeeze(1).sqrt();
-
-                let num_vis = Tensor::from_primitive(aux.num_visible.clone());
-                let valid = Tensor::arange(0..splats.num_splats() as i64, &device).lower(num_vis);
-
-                self.xy_grad_counts =
-                    self.xy_grad_counts
-                        .clone()
-                        .select_assign(0, gs_ids.clone(), valid.int());
-
-                self.grad_2d_accum = self.grad_2d_accum.clone() + xys_grad_norm;
-            }
+            let views: Vec<_> = xys_dummies
+                .iter()
+                .zip(&auxes)
+                .map(|(xys_dummy, aux)| ViewGrads {
+                    // Get the xy gradients from the dummy tensor.
+                    xy_grads: Tensor::from_inner(
+                        xys_dummy
+                            .grad_remove(&mut grads)
+                            .expect("XY gradients need to be calculated."),
+                    ),
+                    aux: aux.clone(),
+                })
+                .collect();
+
+            self.strategy
+                .observe(self.iter, &views, glam::uvec2(img_w as u32, img_h as u32));
         });
 
         let post_step_splat = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
@@ -381,6 +662,25 @@ where
             splats
         });
 
+        if let Some(appearance) = self.appearance.take() {
+            let grad_appearance =
+                GradientsParams::from_params(&mut grads, &appearance, &[appearance.transforms.id]);
+            let lr_appearance = self.config.lr_appearance * global_decay;
+            self.appearance = Some(self.appearance_optim.step(
+                lr_appearance,
+                appearance,
+                grad_appearance,
+            ));
+        }
+
+        if let Some(pose) = self.pose.take() {
+            let grad_pose = GradientsParams::from_params(&mut grads, &pose, &[pose.deltas.id]);
+            let lr_pose = self.config.lr_pose * global_decay;
+            self.pose = Some(self.pose_optim.step(lr_pose, pose, grad_pose));
+        }
+
+        let post_step_splat = self.strategy.post_step(self.iter, post_step_splat, lr_mean);
+
         let mut refine_stats = None;
 
         let do_refine = self.iter < self.config.max_refine_step
@@ -391,7 +691,13 @@ where
             // If not refining, update splat to step with gradients applied.
             post_step_splat
         } else {
-            let (splats, refine) = self.refine_splats(splats, post_step_splat).await;
+            let mut record = self.optim.to_record();
+            let (splats, refine) = self
+                .strategy
+                .refine(self.iter, post_step_splat, &mut record)
+                .await;
+            // Carry over the optimizer state, now matching the refined splats.
+            self.optim = self.opt_config.init().load_record(record);
             refine_stats = Some(refine);
             splats
         };
@@ -414,198 +720,128 @@ where
 
         Ok((splats, stats))
     }
+}
 
-    async fn refine_splats(
-        &mut self,
-        splats: Splats<B>,
-        post_step_splat: Splats<B>,
-    ) -> (Splats<B>, RefineStats) {
-        let device = splats.means.device();
-        let mut splats_pre_step = splats;
-        let mut splats_post_step = post_step_splat.clone();
-
-        // Otherwise, do refinement, but do the split/clone on gaussians with no grads applied.
-        let grads = self.grad_2d_accum.clone() / self.xy_grad_counts.clone().clamp_min(1).float();
-
-        let big_grad_mask = grads.greater_equal_elem(self.config.densify_grad_thresh);
-        let split_clone_size_mask = splats_post_step
-            .scales()
-            .max_dim(1)
-            .squeeze(1)
-            .lower_elem(self.config.densify_size_thresh);
-
-        let mut append_means = vec![];
-        let mut append_rots = vec![];
-        let mut append_coeffs = vec![];
-        let mut append_opac = vec![];
-        let mut append_scales = vec![];
-
-        let clone_inds = Tensor::stack::<2>(
-            vec![split_clone_size_mask.clone(), big_grad_mask.clone()],
-            1,
-        )
-        .all_dim(1)
-        .squeeze::<1>(1)
-        .argwhere_async()
-        .await;
-
-        // Clone splats
-        let clone_count = clone_inds.dims()[0];
-        if clone_count > 0 {
-            let clone_inds = clone_inds.squeeze(1);
-            append_means.push(splats_pre_step.means.val().select(0, clone_inds.clone()));
-            append_rots.push(splats_pre_step.rotation.val().select(0, clone_inds.clone()));
-            append_coeffs.push(
-                splats_pre_step
-                    .sh_coeffs
-                    .val()
-                    .select(0, clone_inds.clone()),
-            );
-            append_opac.push(
-                splats_pre_step
-                    .raw_opacity
-                    .val()
-                    .select(0, clone_inds.clone()),
-            );
-            append_scales.push(
-                splats_pre_step
-                    .log_scales
-                    .val()
-                    .select(0, clone_inds.clone()),
-            );
-        }
+// Depths of the sparse points seen in a batch of views.
+struct SparseDepthTarget<B: Backend> {
+    // Indices of the observed pixels in the flattened [N, H, W] batch.
+    pixels: Tensor<B, 1, Int>,
+    depths: Tensor<B, 1>,
+    weights: Tensor<B, 1>,
+}
 
-        // Split splats.
-        let split_mask =
-            Tensor::stack::<2>(vec![split_clone_size_mask.bool_not(), big_grad_mask], 1).all_dim(1);
-        let split_inds = split_mask.clone().squeeze::<1>(1).argwhere_async().await;
-        let split_count = split_inds.dims()[0];
-        if split_count > 0 {
-            let split_inds = split_inds.squeeze(1);
-
-            // Some parts can be straightforwardly copied to the new splats.
-            let cur_coeff = splats_post_step
-                .sh_coeffs
-                .val()
-                .select(0, split_inds.clone());
-            let cur_raw_opac = splats_post_step
-                .raw_opacity
-                .val()
-                .select(0, split_inds.clone());
-            let cur_rots = splats_post_step
-                .rotation
-                .val()
-                .select(0, split_inds.clone());
-            append_rots.push(cur_rots.clone());
-            append_coeffs.push(cur_coeff.clone());
-            append_opac.push(cur_raw_opac);
-
-            // Change current scale to be lower.
-            let cur_scale = splats_post_step.scales().select(0, split_inds.clone());
-            Splats::map_param(&mut splats_post_step.log_scales, |m| {
-                let div_scales = Tensor::zeros_like(&m).select_assign(
-                    0,
-                    split_inds.clone(),
-                    (cur_scale.clone() / 1.6).log(),
-                );
-                m.mask_where(split_mask.clone(), div_scales)
-            });
-            // Append newer smaller scales.
-            append_scales.push((cur_scale.clone() / 1.6).log());
-
-            // Sample new position for splits.
-            let cur_means = splats_pre_step.means.val().select(0, split_inds.clone());
-            let samples = quaternion_vec_multiply(
-                cur_rots.clone(),
-                Tensor::random([split_count, 3], Distribution::Normal(0.0, 0.5), &device)
-                    * cur_scale.clone(),
-            );
-            // Assign new means to current values.
-            Splats::map_param(&mut splats_pre_step.means, |m| {
-                let offset_means = Tensor::zeros_like(&m).select_assign(
-                    0,
-                    split_inds.clone(),
-                    cur_means.clone() - samples.clone(),
-                );
-                m.mask_where(split_mask.clone(), offset_means)
-            });
+// The depths are calculated from the [N, 4, 4] viewmats the views are rendered with, so they
+// follow the pose corrections.
+fn sparse_depth_target<B: Backend>(
+    viewmats: Tensor<B, 3>,
+    views: &[SceneView],
+    img_size: glam::UVec2,
+) -> Option<SparseDepthTarget<B>> {
+    let device = viewmats.device();
+    let mut pixels = vec![];
+    let mut depths = vec![];
+    let mut weights = vec![];
+
+    for (i, view) in views.iter().enumerate() {
+        let Some(points) = &view.sparse_points else {
+            continue;
+        };
 
-            // Append new means with offset sample.
-            let samples_new = quaternion_vec_multiply(
-                cur_rots.clone(),
-                Tensor::random([split_count, 3], Distribution::Normal(0.0, 0.5), &device)
-                    * cur_scale,
-            );
-            append_means.push(cur_means.clone() + samples_new);
+        let mut positions = vec![];
+        for point in points.iter() {
+            let pixel = (point.uv * img_size.as_vec2()).as_uvec2();
+            if pixel.x >= img_size.x || pixel.y >= img_size.y {
+                continue;
+            }
+            let offset = i as u32 * img_size.x * img_size.y;
+            pixels.push((offset + pixel.y * img_size.x + pixel.x) as i32);
+            positions.extend(point.xyz.extend(1.0).to_array());
+            // Points with a large reprojection error are less reliable.
+            weights.push(1.0 / (1.0 + point.error));
         }
 
-        // Do processing on splat post step.
-        let mut splats = post_step_splat;
-
-        if !append_means.is_empty() {
-            let append_means = Tensor::cat(append_means, 0);
-            let append_rots = Tensor::cat(append_rots, 0);
-            let append_coeffs = Tensor::cat(append_coeffs, 0);
-            let append_opac = Tensor::cat(append_opac, 0);
-            let append_scales = Tensor::cat(append_scales, 0);
-
-            concat_splats(
-                &mut splats,
-                append_means,
-                append_rots,
-                append_coeffs,
-                append_opac,
-                append_scales,
-            );
+        let count = positions.len() / 4;
+        if count == 0 {
+            continue;
         }
+        // The viewmats are column major, so they transform row vectors from the right.
+        let positions = Tensor::<B, 2>::from_data(TensorData::new(positions, [count, 4]), &device);
+        let viewmat = viewmats.clone().slice([i..i + 1, 0..4, 0..4]).squeeze(0);
+        depths.push(positions.matmul(viewmat).slice([0..count, 2..3]).squeeze(1));
+    }
 
-        // Do some more processing. Important to do this last as otherwise you might mess up the correspondence
-        // of gradient <-> splat.
-        let start_count = splats.num_splats();
-
-        // Remove barely visible gaussians.
-        let alpha_mask = splats.opacity().lower_elem(self.config.cull_alpha_thresh);
-        prune_points(&mut splats, alpha_mask).await;
-
-        let alpha_pruned = start_count - splats.num_splats();
-
-        // Delete Gaussians with too large of a radius in world-units.
-        let scale_mask = splats
-            .scales()
-            .max_dim(1)
-            .squeeze(1)
-            .greater_elem(self.config.cull_scale_thresh);
-        prune_points(&mut splats, scale_mask).await;
-
-        let scale_pruned = start_count - splats.num_splats();
+    if pixels.is_empty() {
+        return None;
+    }
 
-        let refine_step = self.iter / self.config.refine_every;
-        if refine_step % self.config.reset_alpha_every_refine == 0 {
-            self.reset_opacity(&mut splats);
-        }
+    let count = pixels.len();
+    let depths = Tensor::cat(depths, 0);
+    // Points behind the camera aren't seen, so don't count.
+    let weights = Tensor::<B, 1>::from_data(TensorData::new(weights, [count]), &device)
+        * depths.clone().greater_elem(0.0).float();
+    Some(SparseDepthTarget {
+        pixels: Tensor::from_data(TensorData::new(pixels, [count]), &device),
+        depths,
+        weights,
+    })
+}
 
-        // Stats don't line up anymore so have to reset them.
-        self.reset_stats(splats.num_splats(), &device);
+// Mean of an [N, H, W, C] tensor over the pixels where the [N, H, W, 1] mask is set.
+fn masked_mean<B: Backend>(x: Tensor<B, 4>, mask: Tensor<B, 4>) -> Tensor<B, 1> {
+    let channels = x.dims()[3];
+    (x * mask.clone()).sum() / (mask.sum() * channels as f32).clamp_min(1e-6)
+}
 
-        // TODO: Want to do state surgery and keep momenta for splats.
-        self.optim = self.opt_config.init();
+// Applies a function to both Adam moments of a parameter.
+pub(crate) fn map_opt_state<B: AutodiffBackend, const D: usize>(
+    record: &mut OptimRecord<B>,
+    param_id: ParamId,
+    map: impl Fn(Tensor<B::InnerBackend, D>) -> Tensor<B::InnerBackend, D>,
+) {
+    // Parameters that haven't been stepped yet don't have any state.
+    let Some(state) = record.remove(&param_id) else {
+        return;
+    };
+    let mut state: AdamState<B::InnerBackend, D> = state.into_state();
+    state.momentum.moment_1 = map(state.momentum.moment_1);
+    state.momentum.moment_2 = map(state.momentum.moment_2);
+    record.insert(param_id, AdaptorRecord::from_state(state));
+}
 
-        let stats = RefineStats {
-            num_split: split_count,
-            num_cloned: clone_count,
-            num_transparent_pruned: alpha_pruned,
-            num_scale_pruned: scale_pruned,
-        };
+pub(crate) fn append_zero_rows<B: Backend, const D: usize>(
+    x: Tensor<B, D>,
+    count: usize,
+) -> Tensor<B, D> {
+    let mut shape = x.dims();
+    shape[0] = count;
+    let zeros = Tensor::zeros(shape, &x.device());
+    Tensor::cat(vec![x, zeros], 0)
+}
 
-        (splats, stats)
-    }
+// Appends zeroed moments for `count` new splats.
+pub(crate) fn append_opt_state<B: AutodiffBackend>(
+    record: &mut OptimRecord<B>,
+    splats: &Splats<B>,
+    count: usize,
+) {
+    map_opt_state::<B, 2>(record, splats.means.id, |x| append_zero_rows(x, count));
+    map_opt_state::<B, 3>(record, splats.sh_coeffs.id, |x| append_zero_rows(x, count));
+    map_opt_state::<B, 2>(record, splats.rotation.id, |x| append_zero_rows(x, count));
+    map_opt_state::<B, 1>(record, splats.raw_opacity.id, |x| {
+        append_zero_rows(x, count)
+    });
+    map_opt_state::<B, 2>(record, splats.log_scales.id, |x| append_zero_rows(x, count));
 }
 
-// Prunes points based on the given mask.
+// Prunes points based on the given mask, along with their optimizer state.
 //
 // Args:
 //   mask: bool[n]. If True, prune this Gaussian.
-pub async fn prune_points<B: AutodiffBackend>(splats: &mut Splats<B>, prune: Tensor<B, 1, Bool>) {
+pub async fn prune_points<B: AutodiffBackend>(
+    splats: &mut Splats<B>,
+    record: &mut OptimRecord<B>,
+    prune: Tensor<B, 1, Bool>,
+) {
     // bool[n]. If True, delete these Gaussians.
     let prune_count = prune.dims()[0];
 
@@ -638,6 +874,21 @@ pub async fn prune_points<B: AutodiffBackend>(splats: &mut Splats<B>, prune: Ten
             .log_scales
             .clone()
             .map(|x| Tensor::from_inner(x.select(0, valid_inds.clone()).inner()).require_grad());
+
+        let valid_inds = valid_inds.inner();
+        map_opt_state::<B, 2>(record, splats.means.id, |x| x.select(0, valid_inds.clone()));
+        map_opt_state::<B, 3>(record, splats.sh_coeffs.id, |x| {
+            x.select(0, valid_inds.clone())
+        });
+        map_opt_state::<B, 2>(record, splats.rotation.id, |x| {
+            x.select(0, valid_inds.clone())
+        });
+        map_opt_state::<B, 1>(record, splats.raw_opacity.id, |x| {
+            x.select(0, valid_inds.clone())
+        });
+        map_opt_state::<B, 2>(record, splats.log_scales.id, |x| {
+            x.select(0, valid_inds.clone())
+        });
     }
 }
 
@@ -665,3 +916,221 @@ pub fn concat_splats<B: AutodiffBackend>(
         Tensor::cat(vec![x, log_scales.clone()], 0)
     });
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::image::image_to_tensor;
+    use crate::scene::SparsePoint;
+    use brush_render::camera::Camera;
+    use brush_render::gaussian_splats::inverse_sigmoid;
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use glam::{vec2, vec3, Quat, Vec3};
+    use std::sync::Arc;
+
+    pub(crate) type TestBackend = Autodiff<NdArray>;
+
+    pub(crate) const IMG_SIZE: u32 = 16;
+
+    // Random splats around the origin, in view of the camera of `test_batch`.
+    pub(crate) fn test_splats(num_splats: usize) -> Splats<TestBackend> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let means = (0..num_splats)
+            .map(|_| {
+                vec3(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+            })
+            .collect();
+        Splats::from_raw(
+            means,
+            None,
+            Some(vec![Vec3::splat(-1.5); num_splats]),
+            None,
+            Some(vec![inverse_sigmoid(0.5); num_splats]),
+            &NdArrayDevice::Cpu,
+        )
+    }
+
+    // A view looking at the origin, with a flat colour as ground truth.
+    pub(crate) fn test_view() -> SceneView {
+        let image = image::Rgb32FImage::from_pixel(IMG_SIZE, IMG_SIZE, image::Rgb([0.2, 0.4, 0.6]));
+        SceneView {
+            name: "test".to_owned(),
+            camera: Camera::new(
+                vec3(0.0, 0.0, -4.0),
+                Quat::IDENTITY,
+                0.8,
+                0.8,
+                vec2(0.5, 0.5),
+            ),
+            image: Arc::new(image.into()),
+            mask: None,
+            depth: None,
+            sparse_points: None,
+        }
+    }
+
+    pub(crate) fn test_batch(
+        views: Vec<SceneView>,
+        view_ids: Vec<usize>,
+    ) -> SceneBatch<TestBackend> {
+        let device = NdArrayDevice::Cpu;
+        let gt_images = views
+            .iter()
+            .map(|view| image_to_tensor(&view.image, &device))
+            .collect();
+        SceneBatch {
+            gt_images: Tensor::stack(gt_images, 0),
+            gt_masks: None,
+            gt_depths: None,
+            gt_views: views,
+            gt_view_ids: view_ids,
+            scene_extent: 1.0,
+        }
+    }
+
+    pub(crate) async fn train_steps(
+        trainer: &mut SplatTrainer<TestBackend>,
+        splats: Splats<TestBackend>,
+        batch: &SceneBatch<TestBackend>,
+        steps: u32,
+    ) -> Splats<TestBackend> {
+        let mut splats = splats;
+        for _ in 0..steps {
+            let (new_splats, _) = trainer
+                .step(batch.clone(), splats)
+                .await
+                .expect("Training step failed");
+            splats = new_splats;
+        }
+        splats
+    }
+
+    pub(crate) fn to_vec<B: Backend, const D: usize>(tensor: Tensor<B, D>) -> Vec<f32> {
+        tensor.into_data().to_vec::<f32>().expect("Wrong type")
+    }
+
+    type Inner = <TestBackend as AutodiffBackend>::InnerBackend;
+
+    // Number of rows of the Adam moments of a parameter.
+    fn moment_rows<const D: usize>(trainer: &SplatTrainer<TestBackend>, id: ParamId) -> usize {
+        let mut record = trainer.optim.to_record();
+        let state: AdamState<Inner, D> = record
+            .remove(&id)
+            .expect("Parameter has been stepped")
+            .into_state();
+        assert_eq!(
+            state.momentum.moment_1.dims(),
+            state.momentum.moment_2.dims()
+        );
+        state.momentum.moment_1.dims()[0]
+    }
+
+    #[tokio::test]
+    async fn test_refine_keeps_adam_state() {
+        // Refine on the second step, splitting every splat.
+        let config = TrainConfig::new()
+            .with_warmup_steps(0)
+            .with_refine_every(2)
+            .with_densify_grad_thresh(0.0);
+        let batch = test_batch(vec![test_view()], vec![0]);
+        let device = batch.gt_images.device();
+
+        // Some transparent splats, which get pruned.
+        let mut splats = test_splats(16);
+        Splats::map_param(&mut splats.raw_opacity, |x| {
+            let transparent = Tensor::arange(0..16, &device).lower_elem(4);
+            x.mask_fill(transparent, inverse_sigmoid(0.001))
+        });
+
+        let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &device);
+        let splats = train_steps(&mut trainer, splats, &batch, 1).await;
+        let (splats, stats) = trainer
+            .step(batch.clone(), splats)
+            .await
+            .expect("Training step failed");
+
+        let refine = stats.refine.expect("Step should refine");
+        assert_eq!(refine.num_split, 16);
+        assert_eq!(refine.num_transparent_pruned, 8);
+        assert_eq!(splats.num_splats(), 24);
+
+        assert_eq!(moment_rows::<2>(&trainer, splats.means.id), 24);
+        assert_eq!(moment_rows::<2>(&trainer, splats.rotation.id), 24);
+        assert_eq!(moment_rows::<2>(&trainer, splats.log_scales.id), 24);
+        assert_eq!(moment_rows::<3>(&trainer, splats.sh_coeffs.id), 24);
+        assert_eq!(moment_rows::<1>(&trainer, splats.raw_opacity.id), 24);
+
+        // The optimizer can keep stepping the refined splats.
+        train_steps(&mut trainer, splats, &batch, 1).await;
+    }
+
+    #[test]
+    fn test_masked_mean() {
+        let device = NdArrayDevice::Cpu;
+
+        // Two pixels with two channels, of which only the first pixel is masked in.
+        let x = Tensor::<TestBackend, 4>::from_floats([[[[1.0, 3.0], [10.0, 20.0]]]], &device);
+        let mask = Tensor::<TestBackend, 4>::from_floats([[[[1.0], [0.0]]]], &device);
+        assert_eq!(masked_mean(x.clone(), mask).into_scalar(), 2.0);
+
+        // An empty mask gives a zero loss rather than a NaN.
+        let empty = Tensor::zeros([1, 1, 2, 1], &device);
+        assert_eq!(masked_mean(x, empty).into_scalar(), 0.0);
+    }
+
+    #[test]
+    fn test_sparse_depth_target() {
+        let device = NdArrayDevice::Cpu;
+        let mut view = test_view();
+        view.sparse_points = Some(Arc::new(vec![
+            SparsePoint {
+                uv: vec2(0.5, 0.5),
+                xyz: Vec3::ZERO,
+                error: 1.0,
+            },
+            // Behind the camera.
+            SparsePoint {
+                uv: vec2(0.25, 0.5),
+                xyz: vec3(0.0, 0.0, -6.0),
+                error: 0.0,
+            },
+            // Outside of the image.
+            SparsePoint {
+                uv: vec2(1.0, 0.5),
+                xyz: Vec3::ZERO,
+                error: 0.0,
+            },
+        ]));
+
+        let viewmat = view.camera.world_to_local().to_cols_array_2d();
+        let viewmats = Tensor::<TestBackend, 2>::from_floats(viewmat, &device).unsqueeze();
+        let target = sparse_depth_target(viewmats, &[view], glam::uvec2(IMG_SIZE, IMG_SIZE))
+            .expect("View has sparse points");
+
+        let center = IMG_SIZE * IMG_SIZE / 2 + IMG_SIZE / 2;
+        let left = IMG_SIZE * IMG_SIZE / 2 + IMG_SIZE / 4;
+        let pixels: Vec<u32> = target
+            .pixels
+            .into_data()
+            .convert::<u32>()
+            .to_vec()
+            .expect("Wrong type");
+        assert_eq!(pixels, vec![center, left]);
+        assert_eq!(to_vec(target.depths), vec![4.0, -2.0]);
+        assert_eq!(to_vec(target.weights), vec![0.5, 0.0]);
+    }
+
+    #[test]
+    fn test_resolution_downscale() {
+        let config = TrainConfig::new().with_resolution_schedule(vec![500, 1000]);
+        assert_eq!(config.resolution_downscale(0), 4);
+        assert_eq!(config.resolution_downscale(499), 4);
+        assert_eq!(config.resolution_downscale(500), 2);
+        assert_eq!(config.resolution_downscale(1000), 1);
+        assert_eq!(TrainConfig::new().resolution_downscale(0), 1);
+    }
+}
@@ -5,7 +5,7 @@ use burn::{
         wgpu::{JitBackend, WgpuRuntime},
         Wgpu,
     },
-    tensor::{Tensor, TensorPrimitive},
+    tensor::{Int, Tensor, TensorPrimitive},
 };
 use burn_fusion::client::FusionClient;
 use eframe::egui_wgpu::Renderer;
@@ -13,39 +13,30 @@ use egui::epaint::mutex::RwLock as EguiRwLock;
 use egui::TextureId;
 use wgpu::{CommandEncoderDescriptor, ImageDataLayout};
 
+type InnerWgpu = JitBackend<WgpuRuntime, f32, i32>;
+
+// The bytes_per_row needs to be divisible by 256 in WebGPU, so 4 bytes per pixel means
+// width needs to be disible by 64.
+fn padded_width(width: usize) -> usize {
+    width.div_ceil(64) * 64
+}
+
 fn copy_buffer_to_texture(
-    img: Tensor<JitBackend<WgpuRuntime, f32, i32>, 3>,
+    buffer: &wgpu::Buffer,
+    offset: u64,
+    padded_width: usize,
     texture: &wgpu::Texture,
     encoder: &mut wgpu::CommandEncoder,
 ) {
-    let [height, width, c] = img.dims();
-
-    let padded_shape = vec![height, width.div_ceil(64) * 64, c];
-
-    // Create padded tensor if needed. The bytes_per_row needs to be divisible
-    // by 256 in WebGPU, so 4 bytes per pixel means width needs to be disible by 64.
-    let padded = if width % 64 != 0 {
-        let padded = Tensor::zeros(&padded_shape, &img.device());
-        padded.slice_assign([0..height, 0..width], img)
-    } else {
-        img
-    };
-
-    let prim = padded.clone().into_primitive().tensor();
-
-    let client = &prim.client;
-    client.flush();
-    let img_res = client.get_resource(prim.handle.clone().binding());
-
     // Put compute passes in encoder before copying the buffer.
-    let bytes_per_row = Some(4 * padded_shape[1] as u32);
+    let bytes_per_row = Some(4 * padded_width as u32);
 
     // Now copy the buffer to the texture.
     encoder.copy_buffer_to_texture(
         wgpu::ImageCopyBuffer {
-            buffer: img_res.resource().buffer.as_ref(),
+            buffer,
             layout: ImageDataLayout {
-                offset: img_res.resource().offset(),
+                offset,
                 bytes_per_row,
                 rows_per_image: None,
             },
@@ -57,13 +48,72 @@ fn copy_buffer_to_texture(
             aspect: wgpu::TextureAspect::All,
         },
         wgpu::Extent3d {
-            width: width as u32,
-            height: height as u32,
+            width: texture.width(),
+            height: texture.height(),
             depth_or_array_layers: 1,
         },
     );
 }
 
+// Copy a [H, W, 1] tensor of packed RGBA8 colors to the texture.
+fn copy_packed_to_texture(
+    img: Tensor<InnerWgpu, 3>,
+    texture: &wgpu::Texture,
+    encoder: &mut wgpu::CommandEncoder,
+) {
+    let [height, width, c] = img.dims();
+    let padded_shape = vec![height, padded_width(width), c];
+
+    // Create padded tensor if needed.
+    let padded = if width % 64 != 0 {
+        let padded = Tensor::zeros(&padded_shape, &img.device());
+        padded.slice_assign([0..height, 0..width], img)
+    } else {
+        img
+    };
+
+    let prim = padded.clone().into_primitive().tensor();
+    let client = &prim.client;
+    client.flush();
+    let img_res = client.get_resource(prim.handle.clone().binding());
+    copy_buffer_to_texture(
+        img_res.resource().buffer.as_ref(),
+        img_res.resource().offset(),
+        padded_shape[1],
+        texture,
+        encoder,
+    );
+}
+
+// Same as copy_packed_to_texture, but for colors packed in an int tensor.
+fn copy_packed_ints_to_texture(
+    img: Tensor<InnerWgpu, 2, Int>,
+    texture: &wgpu::Texture,
+    encoder: &mut wgpu::CommandEncoder,
+) {
+    let [height, width] = img.dims();
+    let padded_shape = vec![height, padded_width(width)];
+
+    let padded = if width % 64 != 0 {
+        let padded = Tensor::zeros(&padded_shape, &img.device());
+        padded.slice_assign([0..height, 0..width], img)
+    } else {
+        img
+    };
+
+    let prim = padded.clone().into_primitive();
+    let client = &prim.client;
+    client.flush();
+    let img_res = client.get_resource(prim.handle.clone().binding());
+    copy_buffer_to_texture(
+        img_res.resource().buffer.as_ref(),
+        img_res.resource().offset(),
+        padded_shape[1],
+        texture,
+        encoder,
+    );
+}
+
 struct TextureState {
     texture: wgpu::Texture,
     id: TextureId,
@@ -101,20 +151,12 @@ impl BurnTexture {
         }
     }
 
-    pub fn update_texture(
+    // Make sure the texture matches the size of the image, and return it.
+    fn prepare_texture(
         &mut self,
-        img: Tensor<Wgpu, 3>,
+        size: glam::UVec2,
         renderer: Arc<EguiRwLock<Renderer>>,
-    ) -> TextureId {
-        let mut encoder = self
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor {
-                label: Some("viewer encoder"),
-            });
-
-        let [h, w, _] = img.shape().dims();
-        let size = glam::uvec2(w as u32, h as u32);
-
+    ) -> &TextureState {
         let dirty = if let Some(s) = self.state.as_ref() {
             s.texture.width() != size.x || s.texture.height() != size.y
         } else {
@@ -122,7 +164,7 @@ impl BurnTexture {
         };
 
         if dirty {
-            let texture = create_texture(glam::uvec2(w as u32, h as u32), &self.device);
+            let texture = create_texture(size, &self.device);
 
             if let Some(s) = self.state.as_mut() {
                 s.texture = texture;
@@ -147,16 +189,66 @@ impl BurnTexture {
         let Some(s) = self.state.as_ref() else {
             unreachable!("Somehow failed to initialize")
         };
+        s
+    }
+
+    /// Update the texture from a [H, W, 1] image of packed RGBA8 colors, as rendered by
+    /// `Splats::render` with a u32 buffer.
+    pub fn update_texture(
+        &mut self,
+        img: Tensor<Wgpu, 3>,
+        renderer: Arc<EguiRwLock<Renderer>>,
+    ) -> TextureId {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("viewer encoder"),
+            });
+
+        let [h, w, _] = img.shape().dims();
+        let s = self.prepare_texture(glam::uvec2(w as u32, h as u32), renderer);
 
         let img = img.into_primitive().tensor();
         let client = img.client.clone();
-        let img = client.resolve_tensor_float::<JitBackend<WgpuRuntime, f32, i32>>(img);
+        let img = client.resolve_tensor_float::<InnerWgpu>(img);
         let img = Tensor::from_primitive(TensorPrimitive::Float(img));
-        copy_buffer_to_texture(img, &s.texture, &mut encoder);
+        copy_packed_to_texture(img, &s.texture, &mut encoder);
 
+        let id = s.id;
         self.queue.submit([encoder.finish()]);
+        id
+    }
+
+    /// Update the texture from a [H, W, 4] image of RGBA colors in the 0-1 range.
+    pub fn update_texture_rgba(
+        &mut self,
+        img: Tensor<Wgpu, 3>,
+        renderer: Arc<EguiRwLock<Renderer>>,
+    ) -> TextureId {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("viewer encoder"),
+            });
+
+        let [h, w, _] = img.shape().dims();
+        let s = self.prepare_texture(glam::uvec2(w as u32, h as u32), renderer);
 
-        s.id
+        // Pack the channels into the bytes of a single int. The alpha channel overflows
+        // the sign bit, which wraps around to the right bits.
+        let shifts =
+            Tensor::<Wgpu, 1, Int>::from_ints([1, 1 << 8, 1 << 16, 1 << 24], &img.device());
+        let img = (img.clamp(0.0, 1.0) * 255.0).int();
+        let packed = (img * shifts.reshape([1, 1, 4])).sum_dim(2).squeeze(2);
+
+        let packed = packed.into_primitive();
+        let client = packed.client.clone();
+        let packed = client.resolve_tensor_int::<InnerWgpu>(packed);
+        copy_packed_ints_to_texture(Tensor::from_primitive(packed), &s.texture, &mut encoder);
+
+        let id = s.id;
+        self.queue.submit([encoder.finish()]);
+        id
     }
 
     pub fn id(&self) -> Option<TextureId> {
@@ -8,6 +8,7 @@ use viewer::{ViewerContext, ViewerMessage};
 mod orbit_controls;
 
 mod panels;
+mod selection;
 mod train_loop;
 
 pub mod viewer;
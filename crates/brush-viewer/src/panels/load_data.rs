@@ -12,6 +12,14 @@ pub(crate) struct LoadDataPanel {
     load_args: LoadDatasetArgs,
 
     sh_degree: u32,
+    batch_size: usize,
+    max_splats: Option<usize>,
+    per_view_appearance: bool,
+    optimize_poses: bool,
+    depth_loss_weight: f32,
+    sparse_depth_loss_weight: f32,
+    coarse_to_fine: bool,
+    random_background: bool,
     quality: Quality,
     proxy: bool,
     url: String,
@@ -28,8 +36,17 @@ impl LoadDataPanel {
                 eval_split_every: None,
                 subsample_frames: None,
                 subsample_points: None,
+                depth_scale: None,
             },
             sh_degree: 3,
+            batch_size: 1,
+            max_splats: None,
+            per_view_appearance: false,
+            optimize_poses: false,
+            depth_loss_weight: 0.0,
+            sparse_depth_loss_weight: 0.0,
+            coarse_to_fine: false,
+            random_background: false,
             quality: Quality::Normal,
             proxy: false,
             url: "splat.com/example.ply".to_owned(),
@@ -65,7 +82,17 @@ impl ViewerPanel for LoadDataPanel {
                     sh_degree: self.sh_degree,
                 };
 
-                let mut config = TrainConfig::default();
+                let mut config = TrainConfig::default()
+                    .with_batch_size(self.batch_size)
+                    .with_max_splats(self.max_splats)
+                    .with_per_view_appearance(self.per_view_appearance)
+                    .with_optimize_poses(self.optimize_poses)
+                    .with_depth_loss_weight(self.depth_loss_weight)
+                    .with_sparse_depth_loss_weight(self.sparse_depth_loss_weight)
+                    .with_composite_background(self.random_background);
+                if self.coarse_to_fine {
+                    config = config.with_resolution_schedule(vec![500, 1000]);
+                }
                 if matches!(self.quality, Quality::Low) {
                     config = config
                         .with_densify_grad_thresh(0.0003)
@@ -93,6 +120,55 @@ impl ViewerPanel for LoadDataPanel {
             ui.label("Spherical Harmonics Degree:");
             ui.add(Slider::new(&mut self.sh_degree, 0..=4));
 
+            ui.label("Batch size:")
+                .on_hover_text("Number of views per training step. Images need to have the same resolution.");
+            ui.add(Slider::new(&mut self.batch_size, 1..=8));
+
+            let mut limit_splats = self.max_splats.is_some();
+            if ui.checkbox(&mut limit_splats, "Limit splat count")
+                .on_hover_text("Stop adding splats past this budget. Useful to keep memory use predictable.")
+                .clicked()
+            {
+                self.max_splats = if limit_splats { Some(1_000_000) } else { None };
+            }
+
+            if let Some(max_splats) = self.max_splats.as_mut() {
+                ui.add(Slider::new(max_splats, 10_000..=5_000_000).logarithmic(true));
+            }
+
+            ui.checkbox(&mut self.per_view_appearance, "Per view appearance")
+                .on_hover_text("Learn a colour correction for each image. Helps with captures where the exposure or white balance changes between images.");
+
+            ui.checkbox(&mut self.optimize_poses, "Refine camera poses")
+                .on_hover_text("Correct small errors in the camera poses while training.");
+
+            ui.label("Depth loss weight:")
+                .on_hover_text("Supervise the rendered depth with the depth maps of the dataset, if it has any.");
+            ui.add(Slider::new(&mut self.depth_loss_weight, 0.0..=1.0));
+
+            let mut scale_depth = self.load_args.depth_scale.is_some();
+            if ui
+                .checkbox(&mut scale_depth, "Scale COLMAP depth maps")
+                .on_hover_text("Multiply the values of COLMAP depth maps to convert them to world units, eg. 0.001 for depth maps in millimeters.")
+                .clicked()
+            {
+                self.load_args.depth_scale = if scale_depth { Some(1e-3) } else { None };
+            }
+
+            if let Some(depth_scale) = self.load_args.depth_scale.as_mut() {
+                ui.add(Slider::new(depth_scale, 1e-4..=10.0).logarithmic(true));
+            }
+
+            ui.label("Sparse depth loss weight:")
+                .on_hover_text("Supervise the rendered depth with the sparse COLMAP points seen in each image.");
+            ui.add(Slider::new(&mut self.sparse_depth_loss_weight, 0.0..=1.0));
+
+            ui.checkbox(&mut self.random_background, "Random background")
+                .on_hover_text("For images with transparency, train against a random background colour every step. Avoids dark halos around objects.");
+
+            ui.checkbox(&mut self.coarse_to_fine, "Coarse to fine")
+                .on_hover_text("Train at 1/4 resolution for the first 500 steps, then at 1/2 resolution until step 1000. Speeds up the start of training.");
+
             ui.horizontal(|ui| {
                 ui.label("Quality:");
                 if ui
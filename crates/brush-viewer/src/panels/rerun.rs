@@ -327,6 +327,14 @@ impl VisualizeTools {
                     "refine/num_scale_pruned",
                     &rerun::Scalar::new(refine.num_scale_pruned as f64),
                 )?;
+                rec.log(
+                    "refine/num_relocated",
+                    &rerun::Scalar::new(refine.num_relocated as f64),
+                )?;
+                rec.log(
+                    "refine/num_over_budget",
+                    &rerun::Scalar::new(refine.num_over_budget as f64),
+                )?;
             }
             Ok(())
         });
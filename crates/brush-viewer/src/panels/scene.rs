@@ -1,23 +1,222 @@
 use brush_dataset::splat_export;
 use brush_ui::burn_texture::BurnTexture;
+use burn::tensor::{Bool, Tensor};
 use burn_wgpu::Wgpu;
 use egui::epaint::mutex::RwLock as EguiRwLock;
 use std::sync::Arc;
 
+use brush_render::bounding_box::{BoundingBox, CropBox};
+use brush_render::camera::Camera;
 use brush_render::gaussian_splats::Splats;
 use eframe::egui_wgpu::Renderer;
 use egui::{Color32, Rect};
-use glam::Vec2;
+use glam::{Affine3A, Vec2};
 use tokio_with_wasm::alias as tokio;
+
+use ::tokio::sync::oneshot::{self, error::TryRecvError};
 use tracing::trace_span;
 use web_time::Instant;
 
 use crate::{
+    selection::{self, Selection},
     train_loop::TrainMessage,
     viewer::{ViewerContext, ViewerMessage},
     ViewerPanel,
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RenderMode {
+    Color,
+    Depth,
+    Alpha,
+    SplatCount,
+    TileLoad,
+}
+
+impl RenderMode {
+    const ALL: [Self; 5] = [
+        Self::Color,
+        Self::Depth,
+        Self::Alpha,
+        Self::SplatCount,
+        Self::TileLoad,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Color => "Color",
+            Self::Depth => "Depth",
+            Self::Alpha => "Opacity",
+            Self::SplatCount => "Splats per pixel",
+            Self::TileLoad => "Splats per tile",
+        }
+    }
+}
+
+// Polynomial approximation of the turbo colormap, for values in the 0-1 range.
+// Every undo step keeps a full copy of the splats on the GPU, so only keep the last few.
+const MAX_UNDO_STEPS: usize = 16;
+
+const TURBO: [[f32; 6]; 3] = [
+    [
+        0.135_721_38,
+        4.615_392_6,
+        -42.660_322,
+        132.131_08,
+        -152.942_4,
+        59.286_38,
+    ],
+    [
+        0.091_402_61,
+        2.194_188_4,
+        4.842_966_6,
+        -14.185_033,
+        4.277_298_7,
+        2.829_566,
+    ],
+    [
+        0.106_673_3,
+        12.641_946,
+        -60.582_05,
+        110.362_77,
+        -89.903_11,
+        27.348_25,
+    ],
+];
+
+// Map [H, W] values in the 0-1 range to [H, W, 4] opaque colors.
+fn colormap(values: Tensor<Wgpu, 2>) -> Tensor<Wgpu, 3> {
+    let x = values.clamp(0.0, 1.0).unsqueeze_dim(2);
+    let mut channels: Vec<_> = TURBO
+        .iter()
+        .map(|coeffs| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(x.zeros_like(), |acc, &c| acc * x.clone() + c)
+        })
+        .collect();
+    channels.push(x.ones_like());
+    Tensor::cat(channels, 2)
+}
+
+// Colormap [H, W] values, normalized between min and the max value. Pixels outside of the
+// mask are transparent.
+fn colormap_masked(
+    values: Tensor<Wgpu, 2>,
+    mask: Tensor<Wgpu, 2, Bool>,
+    min: Tensor<Wgpu, 1>,
+) -> Tensor<Wgpu, 3> {
+    let max = values.clone().max().reshape([1, 1]);
+    let min = min.reshape([1, 1]);
+    let normalized = (values - min.clone()) / (max - min).clamp_min(1e-6);
+    let colors = colormap(normalized);
+    let outside = mask.bool_not().unsqueeze_dim(2).repeat_dim(2, 4);
+    colors.mask_fill(outside, 0.0)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SelectTool {
+    // Dragging moves the camera.
+    Navigate,
+    // Click to select the splat that contributes most to a pixel.
+    Click,
+    // Drag a rectangle around the splats to select.
+    Rect,
+    // Draw a free form outline around the splats to select.
+    Lasso,
+    // Select the splats inside of a box in world space.
+    Box,
+}
+
+impl SelectTool {
+    const ALL: [Self; 5] = [
+        Self::Navigate,
+        Self::Click,
+        Self::Rect,
+        Self::Lasso,
+        Self::Box,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Navigate => "✋ Navigate",
+            Self::Click => "👆 Click",
+            Self::Rect => "⬚ Rectangle",
+            Self::Lasso => "➰ Lasso",
+            Self::Box => "📦 Box",
+        }
+    }
+}
+
+// Project a world space position to a position in the scene rect, if it's in front of the camera.
+fn project_point(
+    camera: &Camera,
+    size: glam::UVec2,
+    rect: Rect,
+    point: glam::Vec3,
+) -> Option<egui::Pos2> {
+    let local = camera.world_to_local().transform_point3(point);
+    if local.z <= 0.01 {
+        return None;
+    }
+    let pixel = local.truncate() / local.z * camera.focal(size) + camera.center(size);
+    Some(rect.min + egui::vec2(pixel.x, pixel.y))
+}
+
+// The splats as they are shown, with the transform being edited applied, and everything outside
+// of the crop box hidden.
+fn visible_splats(
+    splats: &Splats<Wgpu>,
+    transform: Option<Affine3A>,
+    crop_box: Option<&CropBox>,
+) -> Splats<Wgpu> {
+    let mut splats = match transform {
+        Some(transform) => splats.transform(transform),
+        None => splats.clone(),
+    };
+    if let Some(crop_box) = crop_box {
+        splats = splats.hide_outside(crop_box);
+    }
+    splats
+}
+
+// Rotations are edited as YXZ euler angles in degrees.
+fn quat_from_degrees(angles: glam::Vec3) -> glam::Quat {
+    let angles = angles * std::f32::consts::PI / 180.0;
+    glam::Quat::from_euler(glam::EulerRot::YXZ, angles.x, angles.y, angles.z)
+}
+
+fn degrees_from_quat(rotation: glam::Quat) -> glam::Vec3 {
+    let (y, x, z) = rotation.to_euler(glam::EulerRot::YXZ);
+    glam::vec3(y, x, z) * 180.0 / std::f32::consts::PI
+}
+
+// Draw the edges of a box, given its corners as returned by [`CropBox::corners`].
+fn draw_box(
+    painter: &egui::Painter,
+    camera: &Camera,
+    size: glam::UVec2,
+    rect: Rect,
+    corners: &[glam::Vec3; 8],
+    stroke: egui::Stroke,
+) {
+    // Every pair of corners that differ in a single axis is an edge.
+    for a in 0..8 {
+        for axis in [1, 2, 4] {
+            let b = a | axis;
+            if a == b {
+                continue;
+            }
+            let start = project_point(camera, size, rect, corners[a]);
+            let end = project_point(camera, size, rect, corners[b]);
+            if let (Some(start), Some(end)) = (start, end) {
+                painter.line_segment([start, end], stroke);
+            }
+        }
+    }
+}
+
 pub(crate) struct ScenePanel {
     pub(crate) backbuffer: BurnTexture,
     pub(crate) last_draw: Option<Instant>,
@@ -27,6 +226,38 @@ pub(crate) struct ScenePanel {
     is_training: bool,
     live_update: bool,
     paused: bool,
+    // Colour to render the splats over. When not set, the splats are transparent.
+    background: Option<glam::Vec3>,
+    render_mode: RenderMode,
+
+    // Editing state. Edits are only possible once the splats are done loading or training.
+    tool: SelectTool,
+    selection: Option<Selection>,
+    // Screen positions of the current rectangle or lasso drag.
+    drag_points: Vec<egui::Pos2>,
+    select_box: BoundingBox,
+    undo_stack: Vec<Splats<Wgpu>>,
+    redo_stack: Vec<Splats<Wgpu>>,
+    // A click pick in flight, and whether to add it to the selection.
+    pending_pick: Option<(oneshot::Receiver<Option<u32>>, bool)>,
+    // Splats with a selection removed, which get the current splats once done.
+    pending_edit: Option<oneshot::Receiver<Option<Splats<Wgpu>>>>,
+
+    // Whether the crop box editor is open.
+    crop_open: bool,
+    // Rotation of the crop box, as YXZ euler angles in degrees.
+    crop_angles: glam::Vec3,
+    // Also prune splats outside of the crop box while training.
+    crop_prune: bool,
+    // The crop box last sent to the training loop.
+    crop_sent: Option<CropBox>,
+
+    // Whether the transform editor is open.
+    transform_open: bool,
+    // The transform being edited, which is previewed until it's applied to the splats.
+    transform_angles: glam::Vec3,
+    transform_translation: glam::Vec3,
+    transform_scale: f32,
 
     last_size: glam::UVec2,
     dirty: bool,
@@ -48,6 +279,24 @@ impl ScenePanel {
             last_message: None,
             live_update: true,
             paused: false,
+            background: None,
+            render_mode: RenderMode::Color,
+            tool: SelectTool::Navigate,
+            selection: None,
+            drag_points: vec![],
+            select_box: BoundingBox::from_min_max(-glam::Vec3::ONE, glam::Vec3::ONE),
+            undo_stack: vec![],
+            redo_stack: vec![],
+            pending_pick: None,
+            pending_edit: None,
+            crop_open: false,
+            crop_angles: glam::Vec3::ZERO,
+            crop_prune: false,
+            crop_sent: None,
+            transform_open: false,
+            transform_angles: glam::Vec3::ZERO,
+            transform_translation: glam::Vec3::ZERO,
+            transform_scale: 1.0,
             dirty: true,
             last_size: glam::UVec2::ZERO,
             is_loading: false,
@@ -58,6 +307,563 @@ impl ScenePanel {
         }
     }
 
+    fn reset_edits(&mut self) {
+        self.selection = None;
+        self.drag_points.clear();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.pending_pick = None;
+        self.pending_edit = None;
+        self.reset_transform();
+    }
+
+    fn reset_transform(&mut self) {
+        self.transform_angles = glam::Vec3::ZERO;
+        self.transform_translation = glam::Vec3::ZERO;
+        self.transform_scale = 1.0;
+        self.dirty = true;
+    }
+
+    // The transform being edited, if any.
+    fn pending_transform(&self) -> Option<Affine3A> {
+        let transform = Affine3A::from_scale_rotation_translation(
+            glam::Vec3::splat(self.transform_scale),
+            quat_from_degrees(self.transform_angles),
+            self.transform_translation,
+        );
+        (transform != Affine3A::IDENTITY).then_some(transform)
+    }
+
+    fn apply_transform(&mut self) {
+        if let (Some(transform), Some(current)) = (self.pending_transform(), self.current_splats())
+        {
+            self.apply_edit(current.transform(transform));
+        }
+        self.reset_transform();
+    }
+
+    fn can_edit(&self) -> bool {
+        !self.is_loading && !self.is_training
+    }
+
+    fn current_splats(&self) -> Option<Splats<Wgpu>> {
+        match &self.last_message {
+            Some(ViewerMessage::Splats { splats, .. }) => Some(*splats.clone()),
+            _ => None,
+        }
+    }
+
+    fn set_splats(&mut self, new_splats: Splats<Wgpu>) {
+        if let Some(ViewerMessage::Splats { splats, .. }) = &mut self.last_message {
+            *splats = Box::new(new_splats);
+        }
+        self.selection = None;
+        self.dirty = true;
+    }
+
+    fn select(&mut self, selection: Selection, add: bool) {
+        self.selection = Some(match self.selection.take() {
+            Some(current) if add => selection::union(current, selection),
+            _ => selection,
+        });
+        self.dirty = true;
+    }
+
+    // Remove the selected splats, or all but the selected splats when isolating.
+    fn remove_selection(&mut self, isolate: bool) {
+        let (Some(selection), Some(splats)) = (self.selection.clone(), self.current_splats())
+        else {
+            return;
+        };
+        let remove = if isolate {
+            selection.bool_not()
+        } else {
+            selection
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        tokio::task::spawn(async move {
+            let _ = sender.send(selection::remove_selected(&splats, remove).await);
+        });
+        self.pending_edit = Some(receiver);
+    }
+
+    // Replace the current splats with edited splats, keeping the current ones to undo the edit.
+    fn apply_edit(&mut self, edited: Splats<Wgpu>) {
+        if let Some(current) = self.current_splats() {
+            self.push_undo(current);
+            self.redo_stack.clear();
+            self.set_splats(edited);
+        }
+    }
+
+    fn push_undo(&mut self, splats: Splats<Wgpu>) {
+        self.undo_stack.push(splats);
+        if self.undo_stack.len() > MAX_UNDO_STEPS {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    fn undo(&mut self) {
+        if let (Some(previous), Some(current)) = (self.undo_stack.pop(), self.current_splats()) {
+            self.redo_stack.push(current);
+            self.set_splats(previous);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let (Some(next), Some(current)) = (self.redo_stack.pop(), self.current_splats()) {
+            self.push_undo(current);
+            self.set_splats(next);
+        }
+    }
+
+    // Check on picks and edits running in the background.
+    fn poll_pending(&mut self, ctx: &egui::Context) {
+        if let Some((receiver, add)) = &mut self.pending_pick {
+            let add = *add;
+            match receiver.try_recv() {
+                Ok(picked) => {
+                    self.pending_pick = None;
+                    match (picked, self.current_splats()) {
+                        (Some(id), Some(splats)) => {
+                            self.select(selection::select_index(&splats, id), add)
+                        }
+                        _ if !add => {
+                            self.selection = None;
+                            self.dirty = true;
+                        }
+                        _ => {}
+                    }
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Closed) => self.pending_pick = None,
+            }
+        }
+
+        if let Some(receiver) = &mut self.pending_edit {
+            match receiver.try_recv() {
+                Ok(edited) => {
+                    self.pending_edit = None;
+                    match edited {
+                        Some(edited) => self.apply_edit(edited),
+                        None => log::warn!("Not removing the selection, no splats would be left."),
+                    }
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Closed) => self.pending_edit = None,
+            }
+        }
+    }
+
+    // Handle the input of the active selection tool.
+    fn handle_selection(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        rect: Rect,
+        size: glam::UVec2,
+        camera: &Camera,
+        splats: &Splats<Wgpu>,
+    ) {
+        let add = ui.input(|i| i.modifiers.shift);
+        let to_pixel = |pos: egui::Pos2| glam::vec2(pos.x - rect.min.x, pos.y - rect.min.y);
+
+        match self.tool {
+            SelectTool::Click => {
+                if let (true, Some(pos)) = (response.clicked(), response.interact_pointer_pos()) {
+                    let pixel = to_pixel(pos).as_uvec2();
+                    let splats = splats.clone();
+                    let camera = camera.clone();
+
+                    let (sender, receiver) = oneshot::channel();
+                    tokio::task::spawn(async move {
+                        let (_, aux) = splats.render(&camera, size, glam::Vec3::ZERO, true);
+                        let picked = aux.read_picked_pixel(pixel).await;
+                        let _ = sender.send(picked.first().map(|p| p.global_id));
+                    });
+                    self.pending_pick = Some((receiver, add));
+                }
+            }
+            SelectTool::Rect | SelectTool::Lasso => {
+                if response.drag_started_by(egui::PointerButton::Primary) {
+                    self.drag_points.clear();
+                }
+
+                if let (true, Some(pos)) = (
+                    response.dragged_by(egui::PointerButton::Primary),
+                    response.interact_pointer_pos(),
+                ) {
+                    let pos = rect.clamp(pos);
+                    if self.tool == SelectTool::Rect {
+                        self.drag_points.truncate(1);
+                        self.drag_points.push(pos);
+                    } else if self
+                        .drag_points
+                        .last()
+                        .map_or(true, |last| last.distance(pos) > 4.0)
+                    {
+                        // Skip tiny movements, every lasso point is an extra pass over the splats.
+                        self.drag_points.push(pos);
+                    }
+                }
+
+                if response.drag_stopped() && self.drag_points.len() >= 2 {
+                    let points: Vec<_> = self.drag_points.drain(..).map(to_pixel).collect();
+                    let selection = if self.tool == SelectTool::Rect {
+                        let (a, b) = (points[0], points[points.len() - 1]);
+                        selection::select_rect(splats, camera, size, a.min(b), a.max(b))
+                    } else {
+                        selection::select_lasso(splats, camera, size, &points)
+                    };
+                    self.select(selection, add);
+                }
+            }
+            SelectTool::Navigate | SelectTool::Box => {}
+        }
+    }
+
+    // Draw the rectangle, lasso or box of the active selection tool.
+    fn draw_tool_overlay(&self, ui: &egui::Ui, rect: Rect, size: glam::UVec2, camera: &Camera) {
+        let stroke = egui::Stroke::new(1.5, Color32::from_rgb(255, 128, 26));
+        let painter = ui.painter_at(rect);
+
+        match self.tool {
+            SelectTool::Rect => {
+                if let (Some(&a), Some(&b)) = (self.drag_points.first(), self.drag_points.last()) {
+                    painter.rect_stroke(Rect::from_two_pos(a, b), 0.0, stroke);
+                }
+            }
+            SelectTool::Lasso => {
+                painter.add(egui::Shape::closed_line(self.drag_points.clone(), stroke));
+            }
+            SelectTool::Box => {
+                let corners = CropBox::from(self.select_box).corners();
+                draw_box(&painter, camera, size, rect, &corners, stroke);
+            }
+            SelectTool::Navigate | SelectTool::Click => {}
+        }
+    }
+
+    fn crop_ui(&mut self, ui: &mut egui::Ui, context: &mut ViewerContext) {
+        ui.horizontal(|ui| {
+            let mut enabled = context.crop_box.is_some();
+            if ui.checkbox(&mut enabled, "Crop").changed() {
+                context.crop_box = enabled.then(|| {
+                    // Start with a box around the training cameras, or the orbit focus.
+                    let bounds = if context.dataset.train.views.is_empty() {
+                        BoundingBox {
+                            center: context.controls.focus,
+                            extent: glam::Vec3::splat(2.0),
+                        }
+                    } else {
+                        context.dataset.train.bounds(0.0, 0.0)
+                    };
+                    CropBox::from(bounds)
+                });
+                self.crop_angles = glam::Vec3::ZERO;
+                self.dirty = true;
+            }
+
+            let Some(crop_box) = &mut context.crop_box else {
+                return;
+            };
+
+            let mut changed = false;
+            ui.label("Center");
+            for v in crop_box.bounds.center.as_mut() {
+                changed |= ui.add(egui::DragValue::new(v).speed(0.01)).changed();
+            }
+            ui.label("Extent");
+            for v in crop_box.bounds.extent.as_mut() {
+                changed |= ui
+                    .add(egui::DragValue::new(v).speed(0.01).range(0.0..=f32::MAX))
+                    .changed();
+            }
+            ui.label("Rotation");
+            for v in self.crop_angles.as_mut() {
+                changed |= ui
+                    .add(egui::DragValue::new(v).speed(0.5).suffix("°"))
+                    .changed();
+            }
+            if changed {
+                crop_box.rotation = quat_from_degrees(self.crop_angles);
+                self.dirty = true;
+            }
+
+            if self.is_training {
+                ui.add_space(15.0);
+                ui.checkbox(&mut self.crop_prune, "Prune while training")
+                    .on_hover_text("Remove splats outside of the box when refining");
+            }
+        });
+    }
+
+    // Keep the crop box of the training loop in sync. This waits for any drags to finish, so
+    // the training loop isn't flooded with messages.
+    fn sync_train_crop(&mut self, ui: &egui::Ui, context: &ViewerContext) {
+        let crop_box = if self.crop_prune {
+            context.crop_box
+        } else {
+            None
+        };
+        if self.is_training && crop_box != self.crop_sent && !ui.input(|i| i.pointer.any_down()) {
+            context.send_train_message(TrainMessage::SetCropBox(crop_box));
+            self.crop_sent = crop_box;
+        }
+    }
+
+    fn edit_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for tool in SelectTool::ALL {
+                if ui
+                    .selectable_value(&mut self.tool, tool, tool.label())
+                    .changed()
+                {
+                    self.drag_points.clear();
+                }
+            }
+
+            ui.add_space(15.0);
+
+            let has_selection = self.selection.is_some() && self.pending_edit.is_none();
+            let (delete_key, clear_key, undo_key, redo_key) = ui.input_mut(|i| {
+                let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+                let redo = egui::KeyboardShortcut::new(
+                    egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                    egui::Key::Z,
+                );
+                // Check redo first, the undo shortcut also matches with shift held.
+                let redo = i.consume_shortcut(&redo);
+                (
+                    i.key_pressed(egui::Key::Delete),
+                    i.key_pressed(egui::Key::Escape),
+                    i.consume_shortcut(&undo),
+                    redo,
+                )
+            });
+
+            if ui
+                .add_enabled(has_selection, egui::Button::new("🗑 Delete"))
+                .on_hover_text("Remove the selected splats (Del)")
+                .clicked()
+                || (delete_key && has_selection)
+            {
+                self.remove_selection(false);
+            }
+            if ui
+                .add_enabled(has_selection, egui::Button::new("Isolate"))
+                .on_hover_text("Remove all splats except the selected ones")
+                .clicked()
+            {
+                self.remove_selection(true);
+            }
+            if ui
+                .add_enabled(
+                    self.selection.is_some(),
+                    egui::Button::new("Clear selection"),
+                )
+                .on_hover_text("Esc")
+                .clicked()
+                || (clear_key && self.selection.is_some())
+            {
+                self.selection = None;
+                self.dirty = true;
+            }
+
+            ui.add_space(15.0);
+
+            let idle = self.pending_edit.is_none();
+            if ui
+                .add_enabled(
+                    idle && !self.undo_stack.is_empty(),
+                    egui::Button::new("↶ Undo"),
+                )
+                .clicked()
+                || (undo_key && idle)
+            {
+                self.undo();
+            }
+            if ui
+                .add_enabled(
+                    idle && !self.redo_stack.is_empty(),
+                    egui::Button::new("↷ Redo"),
+                )
+                .clicked()
+                || (redo_key && idle)
+            {
+                self.redo();
+            }
+
+            ui.add_space(15.0);
+
+            if ui
+                .selectable_label(self.transform_open, "⟲ Transform")
+                .on_hover_text("Re-orient the scene")
+                .clicked()
+            {
+                self.transform_open = !self.transform_open;
+            }
+        });
+
+        if self.tool == SelectTool::Box {
+            ui.horizontal(|ui| {
+                let mut changed = false;
+                ui.label("Center");
+                for v in self.select_box.center.as_mut() {
+                    changed |= ui.add(egui::DragValue::new(v).speed(0.01)).changed();
+                }
+                ui.label("Extent");
+                for v in self.select_box.extent.as_mut() {
+                    changed |= ui
+                        .add(egui::DragValue::new(v).speed(0.01).range(0.0..=f32::MAX))
+                        .changed();
+                }
+                if changed {
+                    self.dirty = true;
+                }
+
+                if ui.button("Select in box").clicked() {
+                    if let Some(splats) = self.current_splats() {
+                        let add = ui.input(|i| i.modifiers.shift);
+                        self.select(selection::select_box(&splats, self.select_box), add);
+                    }
+                }
+            });
+        }
+    }
+
+    fn transform_ui(&mut self, ui: &mut egui::Ui, context: &mut ViewerContext) {
+        ui.horizontal(|ui| {
+            let mut changed = false;
+            ui.label("Rotation");
+            for v in self.transform_angles.as_mut() {
+                changed |= ui
+                    .add(egui::DragValue::new(v).speed(0.5).suffix("°"))
+                    .changed();
+            }
+            ui.label("Translation");
+            for v in self.transform_translation.as_mut() {
+                changed |= ui.add(egui::DragValue::new(v).speed(0.01)).changed();
+            }
+            ui.label("Scale");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.transform_scale)
+                        .speed(0.01)
+                        .range(0.01..=100.0),
+                )
+                .changed();
+
+            if ui
+                .button("Level to view")
+                .on_hover_text("Rotate the scene so that up in the current view is up")
+                .clicked()
+            {
+                // The camera looks along +Z, with +Y pointing down in the image.
+                let view_up = context.camera.rotation * glam::Vec3::NEG_Y;
+                let level = glam::Quat::from_rotation_arc(view_up, glam::Vec3::NEG_Y);
+                self.transform_angles =
+                    degrees_from_quat(level * quat_from_degrees(self.transform_angles));
+                self.transform_translation = level * self.transform_translation;
+
+                // Move the camera along, so the view stays the same.
+                context.camera.rotation = level * context.camera.rotation;
+                context.camera.position = level * context.camera.position;
+                context.controls.focus = level * context.controls.focus;
+                changed = true;
+            }
+
+            if changed {
+                self.dirty = true;
+            }
+
+            ui.add_space(15.0);
+
+            let has_transform = self.pending_transform().is_some();
+            if ui
+                .add_enabled(
+                    has_transform && self.pending_edit.is_none(),
+                    egui::Button::new("Apply"),
+                )
+                .clicked()
+            {
+                self.apply_transform();
+            }
+            if ui
+                .add_enabled(has_transform, egui::Button::new("Reset"))
+                .clicked()
+            {
+                self.reset_transform();
+            }
+        });
+    }
+
+    // Draw the axes of the transform being edited, at its origin.
+    fn draw_transform_gizmo(&self, ui: &egui::Ui, rect: Rect, size: glam::UVec2, camera: &Camera) {
+        let painter = ui.painter_at(rect);
+        let rotation = quat_from_degrees(self.transform_angles);
+        let origin = self.transform_translation;
+
+        // Keep the gizmo at roughly the same size on screen.
+        let length = 0.15 * (origin - camera.position).length();
+        let Some(start) = project_point(camera, size, rect, origin) else {
+            return;
+        };
+        let axes = [
+            (glam::Vec3::X, Color32::from_rgb(230, 60, 60)),
+            (glam::Vec3::Y, Color32::from_rgb(60, 200, 60)),
+            (glam::Vec3::Z, Color32::from_rgb(60, 100, 230)),
+        ];
+        for (axis, color) in axes {
+            let end = origin + rotation * axis * length;
+            if let Some(end) = project_point(camera, size, rect, end) {
+                painter.arrow(start, end - start, egui::Stroke::new(2.0, color));
+            }
+        }
+    }
+
+    // Render one of the debug visualizations as an [H, W, 4] image.
+    fn render_visualization(
+        &self,
+        splats: &Splats<Wgpu>,
+        camera: &Camera,
+        size: glam::UVec2,
+        background: glam::Vec3,
+    ) -> Tensor<Wgpu, 3> {
+        let (rgba, _, median_depth, aux) = splats.render_depth(camera, size, background);
+        let [h, w, _] = rgba.dims();
+        let device = rgba.device();
+        let alpha = rgba.slice([0..h, 0..w, 3..4]).squeeze(2);
+
+        match self.render_mode {
+            RenderMode::Depth => {
+                // Normalize between the nearest and furthest covered pixel.
+                let covered = median_depth.clone().greater_elem(0.0);
+                let min = median_depth
+                    .clone()
+                    .mask_fill(covered.clone().bool_not(), f32::MAX)
+                    .min();
+                colormap_masked(median_depth, covered, min)
+            }
+            RenderMode::Alpha => {
+                let opaque = alpha.ones_like().unsqueeze_dim(2);
+                Tensor::cat(vec![alpha.unsqueeze_dim(2).repeat_dim(2, 3), opaque], 2)
+            }
+            RenderMode::SplatCount => {
+                let count = aux.read_pixel_depth().float();
+                let covered = count.clone().greater_elem(0.0);
+                colormap_masked(count, covered, Tensor::zeros([1], &device))
+            }
+            RenderMode::TileLoad => {
+                let count = aux.read_pixel_tile_depth().float();
+                let covered = count.clone().greater_elem(0.0);
+                colormap_masked(count, covered, Tensor::zeros([1], &device))
+            }
+            RenderMode::Color => unreachable!("Color renders don't need a visualization"),
+        }
+    }
+
     pub(crate) fn draw_splats(
         &mut self,
         ui: &mut egui::Ui,
@@ -77,12 +883,20 @@ impl ScenePanel {
 
         let (rect, response) = ui.allocate_exact_size(
             egui::Vec2::new(size.x as f32, size.y as f32),
-            egui::Sense::drag(),
+            egui::Sense::click_and_drag(),
         );
 
         let mouse_delta = glam::vec2(response.drag_delta().x, response.drag_delta().y);
 
-        let (pan, rotate) = if response.dragged_by(egui::PointerButton::Primary) {
+        // The selection tools take over the primary button, the camera can still be panned.
+        let selecting = self.tool != SelectTool::Navigate && self.can_edit();
+        if selecting {
+            let visible =
+                visible_splats(splats, self.pending_transform(), context.crop_box.as_ref());
+            self.handle_selection(ui, &response, rect, size, &context.camera, &visible);
+        }
+
+        let (pan, rotate) = if response.dragged_by(egui::PointerButton::Primary) && !selecting {
             (Vec2::ZERO, mouse_delta)
         } else if response.dragged_by(egui::PointerButton::Secondary)
             || response.dragged_by(egui::PointerButton::Middle)
@@ -123,15 +937,31 @@ impl ScenePanel {
         // If this viewport is re-rendering.
         if ui.ctx().has_requested_repaint() && size.x > 0 && size.y > 0 && self.dirty {
             let _span = trace_span!("Render splats").entered();
-            let (img, _) = splats.render(&context.camera, size, true);
-            self.backbuffer.update_texture(img, self.renderer.clone());
+            let background = self.background.unwrap_or(glam::Vec3::ZERO);
+            let mut splats =
+                visible_splats(splats, self.pending_transform(), context.crop_box.as_ref());
+            if let Some(selection) = &self.selection {
+                splats = selection::highlight_selected(&splats, selection.clone());
+            }
+            if self.render_mode == RenderMode::Color {
+                let (img, _) = splats.render(&context.camera, size, background, true);
+                self.backbuffer.update_texture(img, self.renderer.clone());
+            } else {
+                let img = self.render_visualization(&splats, &context.camera, size, background);
+                self.backbuffer
+                    .update_texture_rgba(img, self.renderer.clone());
+            }
             self.dirty = false;
             self.last_size = size;
         }
 
         if let Some(id) = self.backbuffer.id() {
             ui.scope(|ui| {
-                if context
+                if self.background.is_some() {
+                    // The background is already part of the render, and the colors are
+                    // premultiplied by the coverage, so only black should show through.
+                    ui.painter().rect_filled(rect, 0.0, Color32::BLACK);
+                } else if context
                     .dataset
                     .train
                     .views
@@ -157,6 +987,27 @@ impl ScenePanel {
                 );
             });
         }
+
+        if selecting {
+            self.draw_tool_overlay(ui, rect, size, &context.camera);
+        }
+
+        if self.transform_open && self.can_edit() {
+            self.draw_transform_gizmo(ui, rect, size, &context.camera);
+        }
+
+        if let (true, Some(crop_box)) = (self.crop_open, &context.crop_box) {
+            let stroke = egui::Stroke::new(1.5, Color32::from_rgb(80, 180, 255));
+            let painter = ui.painter_at(rect);
+            draw_box(
+                &painter,
+                &context.camera,
+                size,
+                rect,
+                &crop_box.corners(),
+                stroke,
+            );
+        }
     }
 }
 
@@ -173,6 +1024,9 @@ impl ViewerPanel for ScenePanel {
         match message.clone() {
             ViewerMessage::NewSource => {
                 self.last_message = None;
+                self.reset_edits();
+                // A new training loop doesn't have a crop box yet.
+                self.crop_sent = None;
                 self.paused = false;
                 self.is_loading = false;
                 self.is_training = false;
@@ -183,6 +1037,7 @@ impl ViewerPanel for ScenePanel {
             ViewerMessage::StartLoading { training } => {
                 self.is_training = training;
                 self.last_message = None;
+                self.reset_edits();
                 self.is_loading = true;
             }
             ViewerMessage::Splats { iter: _, splats: _ } => {
@@ -230,6 +1085,9 @@ For bigger training runs consider using the native app."#,
             return;
         }
 
+        // Apply any finished edits before drawing the splats.
+        self.poll_pending(ui.ctx());
+
         if let Some(message) = self.last_message.clone() {
             match message {
                 ViewerMessage::Error(e) => {
@@ -238,6 +1096,19 @@ For bigger training runs consider using the native app."#,
                 ViewerMessage::Splats { iter: _, splats } => {
                     self.draw_splats(ui, context, &splats);
 
+                    if self.can_edit() {
+                        self.edit_ui(ui);
+                    }
+
+                    if self.transform_open && self.can_edit() {
+                        self.transform_ui(ui, context);
+                    }
+
+                    if self.crop_open {
+                        self.crop_ui(ui, context);
+                    }
+                    self.sync_train_crop(ui, context);
+
                     ui.horizontal(|ui| {
                         if self.is_training {
                             ui.add_space(15.0);
@@ -267,38 +1138,111 @@ For bigger training runs consider using the native app."#,
 
                             ui.add_space(15.0);
 
-                            if ui.button("⬆ Export").clicked() {
-                                let splats = splats.clone();
+                            if ui.button("💾 Save checkpoint").clicked() {
+                                context.send_train_message(TrainMessage::SaveCheckpoint);
+                            }
 
-                                let fut = async move {
-                                    let file = rrfd::save_file("export.ply").await;
+                            if ui.button("⟲ Resume checkpoint").clicked() {
+                                let sender = context.train_sender();
 
-                                    // Not sure where/how to show this error if any.
-                                    match file {
+                                let fut = async move {
+                                    let data = match rrfd::pick_file().await {
+                                        Ok(file) => file.read().await,
                                         Err(e) => {
-                                            log::error!("Failed to save file: {e}");
-                                        }
-                                        Ok(file) => {
-                                            let data = splat_export::splat_to_ply(*splats).await;
-
-                                            let data = match data {
-                                                Ok(data) => data,
-                                                Err(e) => {
-                                                    log::error!("Failed to serialize file: {e}");
-                                                    return;
-                                                }
-                                            };
-
-                                            if let Err(e) = file.write(&data).await {
-                                                log::error!("Failed to write file: {e}");
-                                            }
+                                            log::error!("Failed to pick checkpoint: {e}");
+                                            return;
                                         }
+                                    };
+
+                                    if let Some(sender) = sender {
+                                        let _ =
+                                            sender.send(TrainMessage::LoadCheckpoint(data)).await;
                                     }
                                 };
 
                                 tokio::task::spawn(fut);
                             }
+
+                            ui.add_space(15.0);
+                        }
+
+                        if ui.button("⬆ Export").clicked() {
+                            let splats = splats.clone();
+                            let transform = self.pending_transform();
+                            let crop_box = context.crop_box;
+
+                            let fut = async move {
+                                let file = rrfd::save_file("export.ply").await;
+
+                                // Not sure where/how to show this error if any.
+                                match file {
+                                    Err(e) => {
+                                        log::error!("Failed to save file: {e}");
+                                    }
+                                    Ok(file) => {
+                                        let mut splats = match transform {
+                                            Some(transform) => splats.transform(transform),
+                                            None => *splats,
+                                        };
+                                        if let Some(crop_box) = crop_box {
+                                            splats = splats.crop(&crop_box).await;
+                                        }
+                                        let data = splat_export::splat_to_ply(splats).await;
+
+                                        let data = match data {
+                                            Ok(data) => data,
+                                            Err(e) => {
+                                                log::error!("Failed to serialize file: {e}");
+                                                return;
+                                            }
+                                        };
+
+                                        if let Err(e) = file.write(&data).await {
+                                            log::error!("Failed to write file: {e}");
+                                        }
+                                    }
+                                }
+                            };
+
+                            tokio::task::spawn(fut);
+                        }
+
+                        ui.add_space(15.0);
+
+                        let mut solid_background = self.background.is_some();
+                        if ui.checkbox(&mut solid_background, "Background").clicked() {
+                            // Default to white, which suits synthetic scenes and product shots.
+                            self.background = solid_background.then_some(glam::Vec3::ONE);
+                            self.dirty = true;
+                        }
+                        if let Some(background) = &mut self.background {
+                            let mut rgb = background.to_array();
+                            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                                *background = glam::Vec3::from(rgb);
+                                self.dirty = true;
+                            }
                         }
+
+                        ui.add_space(15.0);
+
+                        if ui.selectable_label(self.crop_open, "✂ Crop box").clicked() {
+                            self.crop_open = !self.crop_open;
+                        }
+
+                        ui.add_space(15.0);
+
+                        egui::ComboBox::from_label("View")
+                            .selected_text(self.render_mode.label())
+                            .show_ui(ui, |ui| {
+                                for mode in RenderMode::ALL {
+                                    if ui
+                                        .selectable_value(&mut self.render_mode, mode, mode.label())
+                                        .changed()
+                                    {
+                                        self.dirty = true;
+                                    }
+                                }
+                            });
                     });
                 }
                 _ => {}
@@ -0,0 +1,161 @@
+use brush_render::{
+    bounding_box::BoundingBox, camera::Camera, gaussian_splats::Splats, render::SH_C0,
+};
+use burn::tensor::{Bool, Tensor};
+use burn_wgpu::Wgpu;
+use glam::Vec2;
+
+// Selections are kept as a [N] mask of the splats, so they never have to leave the GPU.
+pub(crate) type Selection = Tensor<Wgpu, 1, Bool>;
+
+// A [N] float mask of the splats that can show up in a render. Splats hidden by the crop box have
+// zero opacity, and can't be selected.
+fn visible_mask(splats: &Splats<Wgpu>) -> Tensor<Wgpu, 1> {
+    splats.opacity().greater_elem(0.0).float()
+}
+
+// Project the splat centers to pixel coordinates. Returns the [N, 2] positions, and a [N] float
+// mask of the visible splats in front of the camera.
+fn screen_positions(
+    splats: &Splats<Wgpu>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+) -> (Tensor<Wgpu, 2>, Tensor<Wgpu, 1>) {
+    let device = splats.means.device();
+    let n = splats.num_splats();
+
+    // The column major world to camera matrix is transposed, which is what row vectors need.
+    let viewmat =
+        Tensor::<Wgpu, 2>::from_floats(camera.world_to_local().to_cols_array_2d(), &device);
+    let rotation = viewmat.clone().slice([0..3, 0..3]);
+    let translation = viewmat.slice([3..4, 0..3]);
+    let cam_pos = splats.means.val().matmul(rotation) + translation;
+
+    let z = cam_pos.clone().slice([0..n, 2..3]);
+    let in_front = z.clone().greater_elem(0.01).float().squeeze(1) * visible_mask(splats);
+
+    let focal = camera.focal(img_size);
+    let center = camera.center(img_size);
+    let focal = Tensor::<Wgpu, 1>::from_floats([focal.x, focal.y], &device).reshape([1, 2]);
+    let center = Tensor::<Wgpu, 1>::from_floats([center.x, center.y], &device).reshape([1, 2]);
+    let xy = cam_pos.slice([0..n, 0..2]) / z.clamp_min(0.01) * focal + center;
+    (xy, in_front)
+}
+
+// Select the splats whose center is inside of a screen space rectangle.
+pub(crate) fn select_rect(
+    splats: &Splats<Wgpu>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    min: Vec2,
+    max: Vec2,
+) -> Selection {
+    let (xy, in_front) = screen_positions(splats, camera, img_size);
+    let n = splats.num_splats();
+    let x = xy.clone().slice([0..n, 0..1]).squeeze(1);
+    let y = xy.slice([0..n, 1..2]).squeeze(1);
+
+    let inside = in_front
+        * x.clone().greater_equal_elem(min.x).float()
+        * x.lower_equal_elem(max.x).float()
+        * y.clone().greater_equal_elem(min.y).float()
+        * y.lower_equal_elem(max.y).float();
+    inside.greater_elem(0.5)
+}
+
+// Select the splats whose center is inside of a screen space polygon.
+pub(crate) fn select_lasso(
+    splats: &Splats<Wgpu>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    points: &[Vec2],
+) -> Selection {
+    let (xy, in_front) = screen_positions(splats, camera, img_size);
+    let n = splats.num_splats();
+    let x = xy.clone().slice([0..n, 0..1]).squeeze(1);
+    let y = xy.slice([0..n, 1..2]).squeeze(1);
+
+    // Even-odd rule: count the edges crossed by a ray going to the right of each splat.
+    let mut inside = in_front.zeros_like();
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        if a.y == b.y {
+            continue;
+        }
+        let before_a = y.clone().lower_elem(a.y).float();
+        let before_b = y.clone().lower_elem(b.y).float();
+        let straddles = (before_a - before_b).abs();
+        let edge_x = (y.clone() - a.y) * ((b.x - a.x) / (b.y - a.y)) + a.x;
+        let crosses = straddles * x.clone().lower(edge_x).float();
+        // Flip the parity for every crossing.
+        inside = (inside - crosses).abs();
+    }
+    (inside * in_front).greater_elem(0.5)
+}
+
+// Select the splats whose center is inside of a world space box.
+pub(crate) fn select_box(splats: &Splats<Wgpu>, bounds: BoundingBox) -> Selection {
+    let n = splats.num_splats();
+    let means = splats.means.val();
+    let (min, max) = (bounds.min(), bounds.max());
+
+    let mut inside = visible_mask(splats);
+    for axis in 0..3 {
+        let pos = means.clone().slice([0..n, axis..axis + 1]).squeeze(1);
+        inside = inside
+            * pos.clone().greater_equal_elem(min[axis]).float()
+            * pos.lower_equal_elem(max[axis]).float();
+    }
+    inside.greater_elem(0.5)
+}
+
+// Select a single splat.
+pub(crate) fn select_index(splats: &Splats<Wgpu>, index: u32) -> Selection {
+    let device = splats.means.device();
+    let index = index as usize;
+    Tensor::<Wgpu, 1>::zeros([splats.num_splats()], &device)
+        .slice_assign([index..index + 1], Tensor::ones([1], &device))
+        .greater_elem(0.5)
+}
+
+pub(crate) fn union(a: Selection, b: Selection) -> Selection {
+    (a.float() + b.float()).greater_elem(0.5)
+}
+
+// Remove the selected splats. Returns None when that would remove all splats.
+pub(crate) async fn remove_selected(
+    splats: &Splats<Wgpu>,
+    selection: Selection,
+) -> Option<Splats<Wgpu>> {
+    let keep = selection.bool_not().argwhere_async().await.squeeze(1);
+    if keep.dims()[0] == 0 {
+        return None;
+    }
+    Some(splats.select(keep))
+}
+
+// Tint the selected splats, to show the selection in renders.
+pub(crate) fn highlight_selected(splats: &Splats<Wgpu>, selection: Selection) -> Splats<Wgpu> {
+    let [n, c, _] = splats.sh_coeffs.dims();
+    let device = splats.means.device();
+
+    // A flat orange, without any view dependent color.
+    let color = glam::vec3(1.0, 0.5, 0.1);
+    let dc = (color - 0.5) / SH_C0;
+    let mut tint = Tensor::<Wgpu, 1>::from_floats(dc.to_array(), &device)
+        .reshape([1, 1, 3])
+        .repeat_dim(0, n);
+    if c > 1 {
+        tint = Tensor::cat(vec![tint, Tensor::zeros([n, c - 1, 3], &device)], 1);
+    }
+    let mask = selection
+        .reshape([n, 1, 1])
+        .repeat_dim(1, c)
+        .repeat_dim(2, 3);
+
+    let mut highlighted = splats.clone();
+    Splats::map_param(&mut highlighted.sh_coeffs, |coeffs| {
+        coeffs.mask_where(mask.clone(), tint.clone())
+    });
+    highlighted
+}
@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 use async_fn_stream::try_fn_stream;
 
 use brush_dataset::{
     scene_loader::SceneLoader, zip::DatasetZip, Dataset, LoadDatasetArgs, LoadInitArgs,
 };
+use brush_render::bounding_box::CropBox;
 use brush_render::gaussian_splats::{RandomSplatsConfig, Splats};
 use brush_train::train::{SplatTrainer, TrainConfig};
 use burn::module::AutodiffModule;
@@ -25,7 +28,15 @@ const UPDATE_EVERY: u32 = 5;
 #[derive(Debug, Clone)]
 pub enum TrainMessage {
     Paused(bool),
-    Eval { view_count: Option<usize> },
+    Eval {
+        view_count: Option<usize>,
+    },
+    /// Save the current training state to a checkpoint file.
+    SaveCheckpoint,
+    /// Resume training from the given checkpoint data.
+    LoadCheckpoint(Vec<u8>),
+    /// Remove splats outside of this box while training, or stop doing so.
+    SetCropBox(Option<CropBox>),
 }
 
 pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
@@ -42,8 +53,6 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
         // TODO: async zip ideally.
         let zip_data = DatasetZip::from_data(bytes)?;
 
-        let batch_size = 1;
-
         // Maybe good if the seed would be configurable.
         let seed = 42;
         <Wgpu as burn::prelude::Backend>::seed(seed);
@@ -99,10 +108,12 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
         let train_scene = dataset.train.clone();
         let eval_scene = dataset.eval.clone();
 
-        let mut dataloader = SceneLoader::new(&train_scene, batch_size, seed, &device);
+        let mut dataloader = SceneLoader::new(&train_scene, &config, 0, seed, &device)?;
         let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &device);
 
         let mut is_paused = false;
+        // The crop box set from the viewer. This overrides the one of a loaded checkpoint.
+        let mut viewer_crop_box = None;
 
         loop {
             let message = if is_paused {
@@ -141,6 +152,68 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
                             .await;
                     }
                 }
+                Some(TrainMessage::SaveCheckpoint) => {
+                    // A failed checkpoint shouldn't end the training run, so report it and
+                    // carry on.
+                    match trainer.to_checkpoint(&splats).await {
+                        Ok(data) => {
+                            // Don't hold up training while the user picks a file.
+                            tokio_with_wasm::alias::task::spawn(async move {
+                                let file = match rrfd::save_file("checkpoint.safetensors").await {
+                                    Ok(file) => file,
+                                    Err(e) => {
+                                        log::error!("Failed to save checkpoint: {e}");
+                                        return;
+                                    }
+                                };
+                                if let Err(e) = file.write(&data).await {
+                                    log::error!("Failed to write checkpoint: {e}");
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            log::error!("Failed to create checkpoint: {e:?}");
+                            emitter.emit(ViewerMessage::Error(Arc::new(e))).await;
+                        }
+                    }
+                }
+                Some(TrainMessage::LoadCheckpoint(data)) => {
+                    // Keep training the current splats if the checkpoint can't be loaded.
+                    // The checkpoint might have been trained with a different batch size
+                    // or resolution schedule, and continues from its own step.
+                    let loaded =
+                        SplatTrainer::from_checkpoint(&data, &device).and_then(|(t, s)| {
+                            let loader =
+                                SceneLoader::new(&train_scene, t.config(), t.iter, seed, &device)?;
+                            Ok((t, s, loader))
+                        });
+                    match loaded {
+                        Ok((new_trainer, new_splats, new_dataloader)) => {
+                            trainer = new_trainer;
+                            splats = new_splats;
+                            dataloader = new_dataloader;
+
+                            if let Some(crop_box) = viewer_crop_box {
+                                trainer.set_crop_box(crop_box);
+                            }
+
+                            emitter
+                                .emit(ViewerMessage::Splats {
+                                    iter: trainer.iter,
+                                    splats: Box::new(splats.valid()),
+                                })
+                                .await;
+                        }
+                        Err(e) => {
+                            log::error!("Failed to load checkpoint: {e:?}");
+                            emitter.emit(ViewerMessage::Error(Arc::new(e))).await;
+                        }
+                    }
+                }
+                Some(TrainMessage::SetCropBox(crop_box)) => {
+                    viewer_crop_box = Some(crop_box);
+                    trainer.set_crop_box(crop_box);
+                }
                 // By default, continue training.
                 None => {
                     let batch = dataloader
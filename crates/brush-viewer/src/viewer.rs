@@ -3,6 +3,7 @@ use std::{pin::Pin, sync::Arc};
 use async_fn_stream::try_fn_stream;
 
 use brush_dataset::{self, splat_import, Dataset, LoadDatasetArgs, LoadInitArgs};
+use brush_render::bounding_box::CropBox;
 use brush_render::camera::Camera;
 use brush_render::gaussian_splats::Splats;
 use brush_train::train::TrainStepStats;
@@ -85,6 +86,9 @@ pub(crate) struct ViewerContext {
     pub dataset: Dataset,
     pub camera: Camera,
     pub controls: OrbitControls,
+    // Region of interest. Splats outside of it are hidden, and left out of exports. This stays
+    // around when loading new data.
+    pub crop_box: Option<CropBox>,
     device: WgpuDevice,
     ctx: egui::Context,
 
@@ -208,6 +212,7 @@ impl ViewerContext {
                 glam::vec2(0.5, 0.5),
             ),
             controls: OrbitControls::new(),
+            crop_box: None,
             device,
             ctx,
             dataset: Dataset::empty(),
@@ -282,6 +287,11 @@ impl ViewerContext {
         task::spawn(fut);
     }
 
+    /// A handle to the current training loop, for sending messages from async tasks.
+    pub fn train_sender(&self) -> Option<Sender<TrainMessage>> {
+        self.sender.clone()
+    }
+
     pub fn send_train_message(&self, message: TrainMessage) {
         if let Some(sender) = self.sender.as_ref() {
             match sender.try_send(message) {
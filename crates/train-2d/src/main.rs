@@ -60,7 +60,10 @@ fn spawn_train_loop(
         // One batch of training data, it's the same every step so can just cosntruct it once.
         let batch = SceneBatch {
             gt_images: image_to_tensor(&view.image, &device).unsqueeze(),
+            gt_masks: None,
+            gt_depths: None,
             gt_views: vec![view],
+            gt_view_ids: vec![0],
             scene_extent: 1.0,
         };
 
@@ -123,6 +126,9 @@ impl App {
             name: "crabby".to_owned(),
             camera,
             image: Arc::new(image),
+            mask: None,
+            depth: None,
+            sparse_points: None,
         };
         let (sender, receiver) = tokio::sync::mpsc::channel(32);
 
@@ -134,7 +140,8 @@ impl App {
             cc.egui_ctx
                 .load_texture("nearest_view_tex", color_img, TextureOptions::default());
 
-        let config = TrainConfig::new(ExponentialLrSchedulerConfig::new(lr_max, decay))
+        let config = TrainConfig::new()
+            .with_lr_mean(ExponentialLrSchedulerConfig::new(lr_max, decay))
             .with_max_refine_step(u32::MAX) // Just keep refining
             .with_warmup_steps(100) // Don't really need a warmup for simple 2D
             .with_reset_alpha_every_refine(u32::MAX); // Don't use alpha reset.
@@ -167,6 +174,7 @@ impl eframe::App for App {
             let (img, _) = msg.splats.render(
                 &self.view.camera,
                 glam::uvec2(image.width(), image.height()),
+                glam::Vec3::ZERO,
                 true,
             );
 